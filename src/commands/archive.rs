@@ -1,17 +1,22 @@
 use std::collections::{BTreeMap, HashSet};
 
 use async_imap::{imap_proto::NameAttribute, types::Uid};
-use chrono::{DateTime, Duration, FixedOffset, Utc};
+use chrono::{
+    DateTime, FixedOffset, Utc,
+    format::{Item, StrftimeItems},
+};
 use clap::Args;
 use exn::{OptionExt as _, Result, ResultExt as _, bail};
-use futures::TryStreamExt as _;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::libs::{
     args,
-    config::Config,
+    config::{CheckExtra, Config},
     imap::{Imap, ids_list_to_collapsed_sequence},
-    render::{Renderer, new_renderer},
+    period::Period,
+    pool::ImapPool,
+    render::{Renderer, RowBuffer, Run, new_renderer},
 };
 
 #[derive(Debug, derive_more::Display)]
@@ -22,10 +27,10 @@ pub enum ArchiveError {
     NewRenderer,
     #[display("Connecting to IMAP server")]
     ImapConnect,
+    #[display("Reconnecting to IMAP server")]
+    ImapReconnect,
     #[display("Closing IMAP session")]
     ImapClose,
-    #[display("Checking IMAP capability {cap}")]
-    ImapCapability { cap: String },
     #[display("Creating archive mailbox {mailbox}")]
     ImapCreate { mailbox: String },
     #[display("Listing mailboxes")]
@@ -46,10 +51,6 @@ pub enum ArchiveError {
     MissingExtra { mailbox: String },
     #[display("Moving messages to {mailbox:?}")]
     ImapMove { mailbox: String },
-    #[display("Copying messages to {mailbox:?}")]
-    ImapCopy { mailbox: String },
-    #[display("Storing message flags")]
-    ImapStore,
     #[display("Fetching messages by UID")]
     ImapUidFetch,
     #[display("server did not return INTERNALDATE for UID {uid:?}")]
@@ -72,23 +73,41 @@ The destination mailbox can be configured, as well as the retention."
 )]
 pub struct Archive {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct MyExtra {
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "ArchiveExtra")]
+pub(super) struct MyExtra {
+    /// The archive mailbox, a date format where `%%MBX` is the mailbox.
     format: String,
-    days: u32, // TODO: should this be a float instead ?
+    /// The age of the messages to archive, a number of days or a duration
+    /// like `6 months`.
+    days: Period,
 }
 
-static RENDERER_LEN: usize = 6;
-static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<42", ":>5", ":<25", ":>5", ":>11", ""];
+impl CheckExtra for MyExtra {
+    const REQUIRED: bool = true;
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn check(&self) -> Vec<String> {
+        if StrftimeItems::new(&self.format).any(|item| item == Item::Error) {
+            vec![format!("Invalid date format {:?}", self.format)]
+        } else {
+            vec![]
+        }
+    }
+}
+
+static RENDERER_LEN: usize = 7;
+static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<42", ":>5", ":<25", ":>5", ":>11", ":<10", ""];
 static RENDERER_HEADERS: &[&str; RENDERER_LEN] = &[
     "Mailbox",
     "Msgs",
     "Archive mbx",
     "Arc",
     "Cutoff date",
+    "Age",
     "Sequence",
 ];
 
@@ -97,12 +116,14 @@ impl Archive {
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), ArchiveError> {
-        let config = Config::<MyExtra>::new(&self.config).or_raise(|| ArchiveError::Config)?;
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), ArchiveError> {
+        let config = Config::<MyExtra>::new(&self.config, Some("archive"))
+            .or_raise(|| ArchiveError::Config)?;
         #[cfg(feature = "tracing")]
         tracing::trace!(?config);
 
         let mut renderer = new_renderer(
+            run,
             config.base.renderer,
             if config.base.dry_run {
                 "Mailbox Archiving DRY-RUN"
@@ -114,49 +135,52 @@ impl Archive {
         )
         .or_raise(|| ArchiveError::NewRenderer)?;
 
-        let mut imap = Imap::connect(&config)
+        let pool = ImapPool::connect(&config, true)
             .await
             .or_raise(|| ArchiveError::ImapConnect)?;
 
-        for (mailbox, result) in imap.list().await.or_raise(|| ArchiveError::ImapList)? {
-            match result.extra {
-                Some(ref extra) => {
-                    Self::archive(
-                        &mut imap,
-                        &mut renderer,
-                        &mailbox,
-                        extra,
-                        config.base.dry_run,
-                    )
-                    .await
-                    .or_raise(|| ArchiveError::Archive { mailbox })?;
-                },
-                None => bail!(ArchiveError::MissingExtra { mailbox }),
-            }
-        }
+        pool.for_each_mailbox(
+            pool.list().await.or_raise(|| ArchiveError::ImapList)?,
+            async |imap, mailbox, result| {
+                let Some(ref extra) = result.extra else {
+                    bail!(ArchiveError::MissingExtra { mailbox })
+                };
+                imap.with_reconnect(
+                    async |imap| {
+                        let mut rows = RowBuffer::default();
+                        Self::archive(imap, &mut rows, &mailbox, extra, config.base.dry_run)
+                            .await?;
+                        Ok(rows)
+                    },
+                    || ArchiveError::ImapReconnect,
+                )
+                .await
+                .or_raise(|| ArchiveError::Archive { mailbox })
+            },
+            |rows| {
+                rows.replay(renderer.as_mut())
+                    .or_raise(|| ArchiveError::RendererAddRow)
+            },
+        )
+        .await?;
 
-        imap.close().await.or_raise(|| ArchiveError::ImapClose)?;
+        pool.close().await.or_raise(|| ArchiveError::ImapClose)?;
 
         Ok(())
     }
 
-    #[expect(
-        clippy::too_many_lines,
-        reason = "archive handles dry-run/non-dry-run with MOVE/COPY paths"
-    )]
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(imap, renderer), err(level = "info"))
     )]
     async fn archive(
         imap: &mut Imap<MyExtra>,
-        renderer: &mut Box<dyn Renderer<RENDERER_LEN> + Send>,
+        renderer: &mut (dyn Renderer<RENDERER_LEN> + Send),
         mailbox: &str,
         extra: &MyExtra,
         dry_run: bool,
     ) -> Result<(), ArchiveError> {
         let mbx = imap
-            .session
             .examine(mailbox)
             .await
             .or_raise(|| ArchiveError::ImapExamine {
@@ -168,14 +192,13 @@ impl Archive {
             return Ok(());
         }
 
-        let cutoff_date = Utc::now() - Duration::days(i64::from(extra.days));
+        let cutoff_date = extra.days.cutoff(Utc::now());
 
         let cutoff_str = cutoff_date.format("%d-%b-%Y").to_string();
 
         // Search for messages older than the cutoff date and that are neither unread nor flagged
         let uids_to_move = imap
-            .session
-            .uid_search(format!("SEEN UNFLAGGED BEFORE {cutoff_str}"))
+            .uid_search(&format!("SEEN UNFLAGGED BEFORE {cutoff_str}"))
             .await
             .or_raise(|| ArchiveError::ImapUidSearch {
                 cutoff_str: cutoff_str.clone(),
@@ -201,13 +224,13 @@ impl Archive {
                             &archive_mailbox.replace(mailbox, "%MBX"),
                             &moving_msgs,
                             &cutoff_str,
+                            &extra.days,
                             &sequence,
                         ])
                         .or_raise(|| ArchiveError::RendererAddRow)?;
                 }
             } else {
-                imap.session
-                    .select(mailbox)
+                imap.select(mailbox)
                     .await
                     .or_raise(|| ArchiveError::ImapSelect {
                         mailbox: mailbox.to_owned(),
@@ -224,18 +247,12 @@ impl Archive {
                             &archive_mailbox
                         };
 
-                    let names: Vec<_> = imap
-                        .session
-                        .list(None, Some(quoted_mailbox))
-                        .await
-                        .or_raise(|| ArchiveError::ImapListPattern {
-                            pattern: quoted_mailbox.clone(),
-                        })?
-                        .try_collect()
-                        .await
-                        .or_raise(|| ArchiveError::ImapListPattern {
-                            pattern: quoted_mailbox.clone(),
-                        })?;
+                    let names =
+                        imap.list_names(None, Some(quoted_mailbox))
+                            .await
+                            .or_raise(|| ArchiveError::ImapListPattern {
+                                pattern: quoted_mailbox.clone(),
+                            })?;
 
                     // If archive mailbox does not exist, or is a simple folder that is not a mailbox, create it
                     if names.is_empty()
@@ -243,48 +260,19 @@ impl Archive {
                             .iter()
                             .all(|n| n.attributes().contains(&NameAttribute::NoSelect))
                     {
-                        imap.session.create(&archive_mailbox).await.or_raise(|| {
+                        imap.create(&archive_mailbox).await.or_raise(|| {
                             ArchiveError::ImapCreate {
                                 mailbox: archive_mailbox.clone(),
                             }
                         })?;
                     }
 
-                    if imap.has_capability("MOVE").await.or_raise(|| {
-                        ArchiveError::ImapCapability {
-                            cap: "MOVE".to_owned(),
-                        }
-                    })? {
-                        // MV does COPY / MARK \Deleted / EXPUNGE all in one go
-                        imap.session
-                            .uid_mv(&sequence, quoted_mailbox)
-                            .await
-                            .or_raise(|| ArchiveError::ImapMove {
-                                mailbox: quoted_mailbox.clone(),
-                            })?;
-                    } else {
-                        // If we don't have MV, do it the old fashion way.
-                        imap.session
-                            .uid_copy(&sequence, quoted_mailbox)
-                            .await
-                            .or_raise(|| ArchiveError::ImapCopy {
-                                mailbox: quoted_mailbox.clone(),
-                            })?;
-
-                        {
-                            let mut stream = imap
-                                .session
-                                .uid_store(&sequence, "+FLAGS (\\Deleted)")
-                                .await
-                                .or_raise(|| ArchiveError::ImapStore)?;
-                            while stream
-                                .try_next()
-                                .await
-                                .or_raise(|| ArchiveError::ImapStore)?
-                                .is_some()
-                            {}
-                        }
-                    }
+                    // With MOVE, or else COPY then delete
+                    imap.move_uids(&sequence, &archive_mailbox)
+                        .await
+                        .or_raise(|| ArchiveError::ImapMove {
+                            mailbox: archive_mailbox.clone(),
+                        })?;
 
                     renderer
                         .add_row(&[
@@ -293,16 +281,11 @@ impl Archive {
                             &archive_mailbox.replace(mailbox, "%MBX"),
                             &moving_msgs,
                             &cutoff_str,
+                            &extra.days,
                             &sequence,
                         ])
                         .or_raise(|| ArchiveError::RendererAddRow)?;
                 }
-
-                // Close the moved messages
-                imap.session
-                    .close()
-                    .await
-                    .or_raise(|| ArchiveError::ImapClose)?;
             }
         }
 
@@ -323,17 +306,12 @@ impl Archive {
         let mut uids_by_mailbox = BTreeMap::<String, HashSet<Uid>>::new();
 
         {
-            let mut stream = imap
-                .session
+            let fetched = imap
                 .uid_fetch(&uid_set, "INTERNALDATE")
                 .await
                 .or_raise(|| ArchiveError::ImapUidFetch)?;
 
-            while let Some(message) = stream
-                .try_next()
-                .await
-                .or_raise(|| ArchiveError::ImapUidFetch)?
-            {
+            for message in fetched {
                 let mbx = Self::archive_mbx(
                     mailbox,
                     &extra.format,
@@ -376,7 +354,7 @@ mod tests {
 
     #[tokio::test]
     async fn archive_non_dry_run_with_move() {
-        // Non-dry-run path with MOVE capability: SELECT, LIST, UID MV, CLOSE
+        // Non-dry-run path with MOVE capability: SELECT, LIST, UID MV
         let server = MockServer::start(&["MOVE"], vec![
             // EXAMINE INBOX → 5 messages
             MockExchange::ok("EXAMINE \"INBOX\"", vec![
@@ -405,8 +383,6 @@ mod tests {
             ]),
             // UID MV
             MockExchange::ok("UID MOVE 1:3 \"Archives/2020/01/INBOX\"", vec![]),
-            // CLOSE
-            MockExchange::ok("CLOSE", vec![]),
         ])
         .await;
         let base = test_base();
@@ -415,16 +391,17 @@ mod tests {
             .expect("connect");
         let extra = MyExtra {
             format: "Archives/%Y/%m/%%MBX".to_owned(),
-            days: 30,
+            days: Period::days(30),
         };
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Archiving",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = Archive::archive(&mut imap, &mut renderer, "INBOX", &extra, false).await;
+        let result = Archive::archive(&mut imap, renderer.as_mut(), "INBOX", &extra, false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
@@ -434,18 +411,20 @@ mod tests {
             .map(std::borrow::ToOwned::to_owned)
             .collect();
         assert_eq!(out.len(), 3);
-        assert_snapshot!(out[0], @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Sequence");
+        assert_snapshot!(out[0], @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Age,Sequence");
         assert!(
-            regex::Regex::new(r"^INBOX,5,Archives/2020/01/%MBX,3,\d\d-\w\w\w-\d\d\d\d,1:3$")
-                .expect("should parse")
-                .is_match(&out[1])
+            regex::Regex::new(
+                r"^INBOX,5,Archives/2020/01/%MBX,3,\d\d-\w\w\w-\d\d\d\d,30 days,1:3$"
+            )
+            .expect("should parse")
+            .is_match(&out[1])
         );
         assert!(out[2].is_empty());
     }
 
     #[tokio::test]
     async fn archive_non_dry_run_copy_delete_fallback() {
-        // Non-dry-run path without MOVE capability: SELECT, LIST, UID COPY, UID STORE, CLOSE
+        // Non-dry-run path without MOVE capability: SELECT, LIST, UID COPY, UID STORE, UID EXPUNGE
         let server = MockServer::start(&[], vec![
             // EXAMINE INBOX → 5 messages
             MockExchange::ok("EXAMINE \"INBOX\"", vec![
@@ -476,8 +455,8 @@ mod tests {
             MockExchange::ok("UID COPY 1:3 \"Archives/2020/01/INBOX\"", vec![]),
             // UID STORE +FLAGS (\Deleted)
             MockExchange::ok("UID STORE 1:3 +FLAGS (\\Deleted)", vec![]),
-            // CLOSE
-            MockExchange::ok("CLOSE", vec![]),
+            // UID EXPUNGE only the copied messages
+            MockExchange::ok("UID EXPUNGE 1:3", vec!["* 1 EXPUNGE\r\n".into()]),
         ])
         .await;
         let base = test_base();
@@ -486,16 +465,17 @@ mod tests {
             .expect("connect");
         let extra = MyExtra {
             format: "Archives/%Y/%m/%%MBX".to_owned(),
-            days: 30,
+            days: Period::days(30),
         };
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Archiving",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = Archive::archive(&mut imap, &mut renderer, "INBOX", &extra, false).await;
+        let result = Archive::archive(&mut imap, renderer.as_mut(), "INBOX", &extra, false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
@@ -505,11 +485,13 @@ mod tests {
             .map(std::borrow::ToOwned::to_owned)
             .collect();
         assert_eq!(out.len(), 3);
-        assert_snapshot!(out[0], @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Sequence");
+        assert_snapshot!(out[0], @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Age,Sequence");
         assert!(
-            regex::Regex::new(r"^INBOX,5,Archives/2020/01/%MBX,3,\d\d-\w\w\w-\d\d\d\d,1:3$")
-                .expect("should parse")
-                .is_match(&out[1])
+            regex::Regex::new(
+                r"^INBOX,5,Archives/2020/01/%MBX,3,\d\d-\w\w\w-\d\d\d\d,30 days,1:3$"
+            )
+            .expect("should parse")
+            .is_match(&out[1])
         );
         assert!(out[2].is_empty());
     }
@@ -548,20 +530,21 @@ mod tests {
             .expect("connect");
         let extra = MyExtra {
             format: "Archives/%Y/%m/%%MBX".to_owned(),
-            days: 30,
+            days: Period::days(30),
         };
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Archiving",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = Archive::archive(&mut imap, &mut renderer, "INBOX", &extra, false).await;
+        let result = Archive::archive(&mut imap, renderer.as_mut(), "INBOX", &extra, false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        assert_snapshot!(renderer.output(), @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Sequence");
+        assert_snapshot!(renderer.output(), @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Age,Sequence");
     }
 
     #[tokio::test]
@@ -591,16 +574,17 @@ mod tests {
             .expect("connect");
         let extra = MyExtra {
             format: "Archives/%Y/%m/%%MBX".to_owned(),
-            days: 30,
+            days: Period::days(30),
         };
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Archiving",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = Archive::archive(&mut imap, &mut renderer, "INBOX", &extra, true).await;
+        let result = Archive::archive(&mut imap, renderer.as_mut(), "INBOX", &extra, true).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
@@ -610,11 +594,13 @@ mod tests {
             .map(std::borrow::ToOwned::to_owned)
             .collect();
         assert_eq!(out.len(), 3);
-        assert_snapshot!(out[0], @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Sequence");
+        assert_snapshot!(out[0], @"Mailbox,Msgs,Archive mbx,Arc,Cutoff date,Age,Sequence");
         assert!(
-            regex::Regex::new(r"^INBOX,5,Archives/2020/01/%MBX,3,\d\d-\w\w\w-\d\d\d\d,1:3$")
-                .expect("should parse")
-                .is_match(&out[1])
+            regex::Regex::new(
+                r"^INBOX,5,Archives/2020/01/%MBX,3,\d\d-\w\w\w-\d\d\d\d,30 days,1:3$"
+            )
+            .expect("should parse")
+            .is_match(&out[1])
         );
         assert!(out[2].is_empty());
     }
@@ -1,16 +1,30 @@
-use std::collections::BTreeMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
 
-use chrono::{Duration, Utc};
+use async_imap::types::Uid;
+use chrono::{DateTime, FixedOffset, Utc};
 use clap::Args;
 use exn::{OptionExt as _, Result, ResultExt as _, bail};
-use futures::TryStreamExt as _;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{
+    Deserialize, Serialize,
+    de::{IntoDeserializer as _, Visitor, value::MapAccessDeserializer},
+    ser::SerializeMap as _,
+};
+use serde_value::Value;
 use size::Size;
 
 use crate::libs::{
     args,
-    config::Config,
-    imap::{Imap, ids_list_to_collapsed_sequence},
-    render::{Renderer, new_renderer},
+    config::{CheckExtra, Config},
+    imap::{EXPUNGED, Imap, ids_list_to_collapsed_sequence},
+    period::Period,
+    pool::ImapPool,
+    render::{Renderer, RowBuffer, Run, new_renderer},
+    search::Search,
 };
 
 #[derive(Debug, derive_more::Display)]
@@ -23,6 +37,8 @@ pub enum CleanError {
     Cleanup { mailbox: String },
     #[display("Connecting to IMAP server")]
     ImapConnect,
+    #[display("Reconnecting to IMAP server")]
+    ImapReconnect,
     #[display("Listing mailboxes")]
     ImapList,
     #[display("Mailbox {mailbox} does not have an extra parameter")]
@@ -33,12 +49,16 @@ pub enum CleanError {
     ImapExamine { mailbox: String },
     #[display("Fetching message size and date in {mailbox}")]
     ImapUidFetch { mailbox: String },
+    #[display("server did not return INTERNALDATE for UID {uid} in {mailbox}")]
+    ImapNoInternalDate { mailbox: String, uid: Uid },
     #[display("Could not find the first message where there should be one")]
     NoFirstDate,
     #[display("Searching old messages in {mailbox}")]
     ImapUidSearch { mailbox: String },
     #[display("Deleting messages by UID in {mailbox}")]
     ImapDeleteUid { mailbox: String },
+    #[display("Finding where the deleted messages of {mailbox} go")]
+    Destination { mailbox: String },
     #[display("Adding renderer row")]
     RendererAddRow,
 }
@@ -53,29 +73,460 @@ It can be configured to keep more messages if they don't take too much space."
 )]
 pub struct Clean {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
+}
+
+/// The rules of a mailbox by its size, how many of its newest messages to
+/// keep, and the thresholds under which it is not cleaned, read as one map.
+/// The thresholds not set are taken from the `extra` at the top of the
+/// configuration, then their defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct MyExtra {
+    rules: BTreeMap<Size, Rule>,
+    keep_newest: Option<u32>,
+    keep_size: Option<Size>,
+    keep_deletes_any: bool,
+    min_messages: Option<u32>,
+    min_size: Option<Size>,
+}
+
+/// A message of the mailbox being cleaned.
+#[derive(Debug)]
+struct Message {
+    uid: Uid,
+    date: DateTime<FixedOffset>,
+    size: u32,
+}
+
+/// The messages a rule of [`MyExtra`] deletes: older than `days`, and
+/// matching `search`, read as a [`Period`] alone or as a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Rule {
+    days: Period,
+    search: Option<Search>,
+}
+
+/// The search of the rules without one.
+const DEFAULT_SEARCH: &str = "SEEN UNFLAGGED";
+
+impl Rule {
+    /// The search selecting the messages, besides their age.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn search(&self) -> Cow<'_, str> {
+        self.search
+            .as_ref()
+            .map_or(Cow::Borrowed(DEFAULT_SEARCH), |search| {
+                Cow::Owned(search.to_string())
+            })
+    }
+}
+
+impl From<Period> for Rule {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn from(days: Period) -> Self {
+        Self { days, search: None }
+    }
+}
+
+/// A [`Rule`] written as a table.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RuleTable {
+    days: Period,
+    #[serde(default)]
+    search: Option<Search>,
+}
+
+impl<'de> Deserialize<'de> for Rule {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(deserializer), ret, err(level = "debug"))
+    )]
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct RuleVisitor;
+
+        impl<'de> Visitor<'de> for RuleVisitor {
+            type Value = Rule;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a duration, or a table with days and search")
+            }
+
+            fn visit_u64<E>(self, v: u64) -> std::result::Result<Rule, E>
+            where
+                E: serde::de::Error,
+            {
+                Period::deserialize(v.into_deserializer()).map(Rule::from)
+            }
+
+            fn visit_i64<E>(self, v: i64) -> std::result::Result<Rule, E>
+            where
+                E: serde::de::Error,
+            {
+                Period::deserialize(v.into_deserializer()).map(Rule::from)
+            }
+
+            fn visit_str<E>(self, v: &str) -> std::result::Result<Rule, E>
+            where
+                E: serde::de::Error,
+            {
+                Period::deserialize(v.into_deserializer()).map(Rule::from)
+            }
+
+            fn visit_map<A>(self, map: A) -> std::result::Result<Rule, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let table = RuleTable::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(Rule {
+                    days: table.days,
+                    search: table.search,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RuleVisitor)
+    }
+}
+
+impl Serialize for Rule {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, serializer), err(level = "debug"))
+    )]
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let Some(ref search) = self.search else {
+            return self.days.serialize(serializer);
+        };
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("days", &self.days)?;
+        map.serialize_entry("search", search)?;
+        map.end()
+    }
+}
+
+impl JsonSchema for Rule {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        "CleanRule".into()
+    }
+
+    /// A duration, or a table with the days and the search.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(generator))
+    )]
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let days = generator.subschema_for::<Period>();
+        let search = generator.subschema_for::<Search>();
+        json_schema!({
+            "anyOf": [
+                days,
+                {
+                    "type": "object",
+                    "properties": {
+                        "days": days,
+                        "search": search,
+                    },
+                    "required": ["days"],
+                    "additionalProperties": false,
+                },
+            ],
+        })
+    }
+}
+
+impl MyExtra {
+    /// Set the threshold or the rule of `key` to `value`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "debug"))
+    )]
+    fn set(&mut self, key: &Value, value: Value) -> std::result::Result<(), String> {
+        let name = match *key {
+            Value::String(ref name) => name.clone(),
+            ref key => format!("{key:?}"),
+        };
+        match name.as_str() {
+            "min-messages" => {
+                self.min_messages = Some(
+                    value
+                        .deserialize_into()
+                        .map_err(|err| format!("Invalid min-messages: {err}"))?,
+                );
+            },
+            "min-size" => {
+                self.min_size = Some(
+                    value
+                        .deserialize_into()
+                        .map_err(|err| format!("Invalid min-size: {err}"))?,
+                );
+            },
+            "keep-newest" => {
+                self.keep_newest = Some(
+                    value
+                        .deserialize_into()
+                        .map_err(|err| format!("Invalid keep-newest: {err}"))?,
+                );
+            },
+            "keep-size" => {
+                self.keep_size = Some(
+                    value
+                        .deserialize_into()
+                        .map_err(|err| format!("Invalid keep-size: {err}"))?,
+                );
+            },
+            "keep-deletes-any" => {
+                self.keep_deletes_any = value
+                    .deserialize_into()
+                    .map_err(|err| format!("Invalid keep-deletes-any: {err}"))?;
+            },
+            _ => {
+                let size = key
+                    .clone()
+                    .deserialize_into::<Size>()
+                    .map_err(|err| format!("Invalid size {name:?}: {err}"))?;
+                let rule = value
+                    .deserialize_into::<Rule>()
+                    .map_err(|err| format!("Invalid rule for {name:?}: {err}"))?;
+                self.rules.insert(size, rule);
+            },
+        }
+        Ok(())
+    }
+
+    /// The number of messages a mailbox must have more of to be cleaned.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn min_messages(&self, top: Option<&Self>) -> u32 {
+        self.min_messages
+            .or_else(|| top?.min_messages)
+            .unwrap_or(DEFAULT_MIN_MESSAGE_COUNT)
+    }
+
+    /// Whether `keep-newest` or `keep-size` is set.
+    const fn keeps(&self) -> bool {
+        self.keep_newest.is_some() || self.keep_size.is_some()
+    }
+
+    /// The search of the messages `keep-newest` and `keep-size` may delete,
+    /// the first one set by a rule, unless `keep-deletes-any` lets them
+    /// delete any message.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn keep_search(&self) -> Option<Cow<'_, str>> {
+        if self.keep_deletes_any {
+            return None;
+        }
+        Some(
+            self.rules
+                .values()
+                .find(|rule| rule.search.is_some())
+                .map_or(Cow::Borrowed(DEFAULT_SEARCH), Rule::search),
+        )
+    }
+
+    /// The messages to delete to keep only the `keep-newest` newest ones,
+    /// taking at most `keep-size`, newest first, besides those `deleted`.
+    /// Of those beyond, only the `deletable` ones are deleted when set.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(messages, deleted, deletable), ret)
+    )]
+    fn dropped<'messages>(
+        &self,
+        messages: &'messages [Message],
+        deleted: &HashSet<Uid>,
+        deletable: Option<&HashSet<Uid>>,
+    ) -> Vec<&'messages Message> {
+        if !self.keeps() {
+            return vec![];
+        }
+        let mut kept: Vec<_> = messages
+            .iter()
+            .filter(|message| !deleted.contains(&message.uid))
+            .collect();
+        kept.sort_by(|a, b| b.date.cmp(&a.date).then(b.uid.cmp(&a.uid)));
+
+        let mut count = 0_u32;
+        let mut size = 0_i64;
+        let first_dropped = kept.iter().position(|message| {
+            count = count.saturating_add(1);
+            size = size.saturating_add(i64::from(message.size));
+            self.keep_newest.is_some_and(|keep| count > keep)
+                || self.keep_size.is_some_and(|keep| size > keep.bytes())
+        });
+        let mut dropped = first_dropped.map_or_else(Vec::new, |index| kept.split_off(index));
+        if let Some(deletable) = deletable {
+            dropped.retain(|message| deletable.contains(&message.uid));
+        }
+        dropped
+    }
+
+    /// What `keep-newest` and `keep-size` are set to.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn keep(&self) -> String {
+        let mut keep = vec![];
+        if let Some(newest) = self.keep_newest {
+            keep.push(format!("keep-newest {newest}"));
+        }
+        if let Some(size) = self.keep_size {
+            keep.push(format!("keep-size {}", size.format()));
+        }
+        keep.join(", ")
+    }
+
+    /// The size a mailbox must be larger than to be cleaned.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn min_size(&self, top: Option<&Self>) -> Size {
+        self.min_size
+            .or_else(|| top?.min_size)
+            .unwrap_or_else(|| Size::from_bytes(DEFAULT_MIN_TOTAL_SIZE_BYTES))
+    }
 }
 
-type MyExtra = BTreeMap<Size, u64>;
+impl<'de> Deserialize<'de> for MyExtra {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(deserializer), ret, err(level = "debug"))
+    )]
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let mut extra = Self::default();
+        for (key, value) in BTreeMap::<Value, Value>::deserialize(deserializer)? {
+            extra.set(&key, value).map_err(serde::de::Error::custom)?;
+        }
+        Ok(extra)
+    }
+}
 
-/// Minimum number of messages in a mailbox before cleanup is considered.
-const MIN_MESSAGE_COUNT: u32 = 300;
+impl Serialize for MyExtra {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, serializer), err(level = "debug"))
+    )]
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(min_messages) = self.min_messages {
+            map.serialize_entry("min-messages", &min_messages)?;
+        }
+        if let Some(ref min_size) = self.min_size {
+            map.serialize_entry("min-size", min_size)?;
+        }
+        if let Some(keep_newest) = self.keep_newest {
+            map.serialize_entry("keep-newest", &keep_newest)?;
+        }
+        if let Some(ref keep_size) = self.keep_size {
+            map.serialize_entry("keep-size", keep_size)?;
+        }
+        if self.keep_deletes_any {
+            map.serialize_entry("keep-deletes-any", &true)?;
+        }
+        for (size, rule) in &self.rules {
+            map.serialize_entry(size, rule)?;
+        }
+        map.end()
+    }
+}
 
-/// Minimum total mailbox size in bytes before cleanup is considered.
-const MIN_TOTAL_SIZE_BYTES: i64 = 1_000_000;
+impl JsonSchema for MyExtra {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        "CleanExtra".into()
+    }
 
-static RENDERER_LEN: usize = 8;
-static RENDERER_FORMAT: &[&str; RENDERER_LEN] =
-    &[":<42", ":>5", ":>10", ":>4", ":>11", ":>11", ":>4", ""];
+    /// The thresholds, and the rules by size.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(generator))
+    )]
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let rule = generator.subschema_for::<Rule>();
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "min-messages": {
+                    "description": "Mailboxes with at most this many messages are not cleaned.",
+                    "type": "integer",
+                    "minimum": 0,
+                },
+                "min-size": {
+                    "description": "Mailboxes of at most this size are not cleaned.",
+                    "type": ["string", "integer"],
+                },
+                "keep-newest": {
+                    "description": "Delete the messages older than this many newest ones.",
+                    "type": "integer",
+                    "minimum": 0,
+                },
+                "keep-size": {
+                    "description": "Delete the oldest messages until the others take at most this size.",
+                    "type": ["string", "integer"],
+                },
+                "keep-deletes-any": {
+                    "description": "Let keep-newest and keep-size delete unread and flagged messages too.",
+                    "type": "boolean",
+                },
+            },
+            "additionalProperties": rule,
+        })
+    }
+}
+
+impl CheckExtra for MyExtra {
+    const REQUIRED: bool = true;
+
+    /// Each threshold, size and rule on its own.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(value), ret)
+    )]
+    fn problems(value: &Value) -> Vec<String> {
+        let Value::Map(ref entries) = *value else {
+            return vec!["extra must map sizes to rules".to_owned()];
+        };
+        let mut extra = Self::default();
+        entries
+            .iter()
+            .filter_map(|(key, value)| extra.set(key, value.clone()).err())
+            .collect()
+    }
+}
+
+/// Default minimum number of messages in a mailbox before cleanup is
+/// considered, see `min-messages`.
+const DEFAULT_MIN_MESSAGE_COUNT: u32 = 300;
+
+/// Default minimum total mailbox size in bytes before cleanup is considered,
+/// see `min-size`.
+const DEFAULT_MIN_TOTAL_SIZE_BYTES: i64 = 1_000_000;
+
+static RENDERER_LEN: usize = 12;
+static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[
+    ":<42", ":>5", ":>10", ":>4", ":<20", ":>11", ":>11", ":<10", ":>4", ":<20", ":<20", "",
+];
 static RENDERER_HEADERS: &[&str; RENDERER_LEN] = &[
     "Mailbox",
     "Msgs",
     "Size",
     "Del",
+    "Destination",
     "First date",
     "Cutoff date",
+    "Age",
     "Days",
+    "Search",
     "Sequence",
+    "Skipped",
 ];
 
 impl Clean {
@@ -83,16 +534,18 @@ impl Clean {
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), CleanError> {
-        let config = Config::<MyExtra>::new(&self.config).or_raise(|| CleanError::Config)?;
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), CleanError> {
+        let config =
+            Config::<MyExtra>::new(&self.config, Some("clean")).or_raise(|| CleanError::Config)?;
         #[cfg(feature = "tracing")]
         tracing::trace!(?config);
 
-        let mut imap = Imap::connect(&config)
+        let pool = ImapPool::connect(&config, false)
             .await
             .or_raise(|| CleanError::ImapConnect)?;
 
         let mut renderer = new_renderer(
+            run,
             config.base.renderer,
             if config.base.dry_run {
                 "Mailbox Cleaner DRY-RUN"
@@ -104,24 +557,39 @@ impl Clean {
         )
         .or_raise(|| CleanError::NewRenderer)?;
 
-        for (mailbox, result) in imap.list().await.or_raise(|| CleanError::ImapList)? {
-            match result.extra {
-                Some(ref extra) => {
-                    Self::cleanup_mailbox(
-                        &mut imap,
-                        &mut renderer,
-                        &mailbox,
-                        extra,
-                        config.base.dry_run,
-                    )
-                    .await
-                    .or_raise(|| CleanError::Cleanup { mailbox })?;
-                },
-                None => bail!(CleanError::MissingExtra { mailbox }),
-            }
-        }
+        pool.for_each_mailbox(
+            pool.list().await.or_raise(|| CleanError::ImapList)?,
+            async |imap, mailbox, result| {
+                let Some(ref extra) = result.extra else {
+                    bail!(CleanError::MissingExtra { mailbox })
+                };
+                imap.with_reconnect(
+                    async |imap| {
+                        let mut rows = RowBuffer::default();
+                        Self::cleanup_mailbox(
+                            imap,
+                            &mut rows,
+                            &mailbox,
+                            extra,
+                            config.extra.as_ref(),
+                            config.base.dry_run,
+                        )
+                        .await?;
+                        Ok(rows)
+                    },
+                    || CleanError::ImapReconnect,
+                )
+                .await
+                .or_raise(|| CleanError::Cleanup { mailbox })
+            },
+            |rows| {
+                rows.replay(renderer.as_mut())
+                    .or_raise(|| CleanError::RendererAddRow)
+            },
+        )
+        .await?;
 
-        imap.close().await.or_raise(|| CleanError::ImapClose)?;
+        pool.close().await.or_raise(|| CleanError::ImapClose)?;
 
         Ok(())
     }
@@ -132,13 +600,13 @@ impl Clean {
     )]
     async fn cleanup_mailbox(
         imap: &mut Imap<MyExtra>,
-        renderer: &mut Box<dyn Renderer<RENDERER_LEN> + Send>,
+        renderer: &mut (dyn Renderer<RENDERER_LEN> + Send),
         mailbox: &str,
         extra: &MyExtra,
+        top: Option<&MyExtra>,
         dry_run: bool,
     ) -> Result<(), CleanError> {
         let mbx = imap
-            .session
             .examine(mailbox)
             .await
             .or_raise(|| CleanError::ImapExamine {
@@ -146,53 +614,55 @@ impl Clean {
             })?;
 
         // If there are not enough messages, skip
-        if mbx.exists <= MIN_MESSAGE_COUNT {
-            return Ok(());
-        }
-
-        let mut total_size: i64 = 0;
-        let mut first_date: Option<chrono::DateTime<chrono::FixedOffset>> = None;
-
-        {
-            let mut stream = imap
-                .session
-                .uid_fetch("1:*", "(RFC822.SIZE INTERNALDATE)")
-                .await
-                .or_raise(|| CleanError::ImapUidFetch {
-                    mailbox: mailbox.to_owned(),
-                })?;
-
-            while let Some(m) = stream
-                .try_next()
-                .await
-                .or_raise(|| CleanError::ImapUidFetch {
-                    mailbox: mailbox.to_owned(),
-                })?
-            {
-                total_size = total_size.saturating_add(i64::from(m.size.unwrap_or(0)));
-                if first_date.is_none() {
-                    first_date = Some(m.internal_date().unwrap_or_default());
-                }
-            }
+        let min_messages = extra.min_messages(top);
+        if mbx.exists <= min_messages {
+            return Self::skipped(
+                renderer,
+                mailbox,
+                mbx.exists,
+                "",
+                &format!("{} messages, min-messages is {min_messages}", mbx.exists),
+            );
         }
 
-        let first_date = first_date.ok_or_raise(|| CleanError::NoFirstDate)?;
+        let messages = Self::fetch_messages(imap, mailbox).await?;
+        let total_size = messages.iter().fold(0_i64, |total, message| {
+            total.saturating_add(i64::from(message.size))
+        });
+        let first_date = messages
+            .first()
+            .ok_or_raise(|| CleanError::NoFirstDate)?
+            .date;
 
         // If size is less than the minimum, skip
-        if total_size <= MIN_TOTAL_SIZE_BYTES {
-            return Ok(());
+        let min_size = extra.min_size(top);
+        if total_size <= min_size.bytes() {
+            let size = Size::from_bytes(total_size).format().to_string();
+            return Self::skipped(
+                renderer,
+                mailbox,
+                mbx.exists,
+                &size,
+                &format!("{size}, min-size is {}", min_size.format()),
+            );
         }
 
-        for (rule_size, rule_days) in extra {
-            let cutoff_date =
-                Utc::now() - Duration::days(i64::try_from(*rule_days).unwrap_or(i64::MAX));
+        let destination = imap
+            .destination(mailbox)
+            .await
+            .or_raise(|| CleanError::Destination {
+                mailbox: mailbox.to_owned(),
+            })?;
+        let mut deleted = HashSet::new();
+        for (rule_size, rule) in &extra.rules {
+            let cutoff_date = rule.days.cutoff(Utc::now());
 
             let cutoff_str = cutoff_date.format("%d-%b-%Y").to_string();
 
-            // Search for messages older than the cutoff date
+            // Search for the messages of the rule older than the cutoff date
+            let search = rule.search();
             let uids_to_delete = imap
-                .session
-                .uid_search(format!("SEEN UNFLAGGED BEFORE {cutoff_str}"))
+                .uid_search(&format!("{search} BEFORE {cutoff_str}"))
                 .await
                 .or_raise(|| CleanError::ImapUidSearch {
                     mailbox: mailbox.to_owned(),
@@ -203,14 +673,7 @@ impl Clean {
                 // Mark messages for deletion
 
                 let sequence = ids_list_to_collapsed_sequence(&uids_to_delete);
-
-                if !dry_run {
-                    imap.delete_uids(mailbox, &sequence).await.or_raise(|| {
-                        CleanError::ImapDeleteUid {
-                            mailbox: mailbox.to_owned(),
-                        }
-                    })?;
-                }
+                Self::delete(imap, mailbox, &sequence, dry_run).await?;
 
                 renderer
                     .add_row(&[
@@ -218,19 +681,147 @@ impl Clean {
                         &mbx.exists,
                         &Size::from_bytes(total_size).format(),
                         &uids_to_delete.len(),
+                        &destination.as_deref().unwrap_or(EXPUNGED),
                         &first_date.format("%d-%b-%Y"),
                         &cutoff_str,
+                        &rule.days,
                         &cutoff_date.signed_duration_since(first_date).num_days(),
+                        &search,
                         &sequence,
+                        &"",
                     ])
                     .or_raise(|| CleanError::RendererAddRow)?;
 
+                deleted = uids_to_delete;
                 break;
             }
         }
 
+        // Then drop the oldest others beyond keep-newest or keep-size, if deletable
+        let dropped = Self::dropped(imap, mailbox, extra, &messages, &deleted).await?;
+        if let Some(newest) = dropped.first() {
+            let uids: HashSet<_> = dropped.iter().map(|message| message.uid).collect();
+            let sequence = ids_list_to_collapsed_sequence(&uids);
+            Self::delete(imap, mailbox, &sequence, dry_run).await?;
+
+            renderer
+                .add_row(&[
+                    &mailbox,
+                    &mbx.exists,
+                    &Size::from_bytes(total_size).format(),
+                    &uids.len(),
+                    &destination.as_deref().unwrap_or(EXPUNGED),
+                    &first_date.format("%d-%b-%Y"),
+                    &newest.date.format("%d-%b-%Y"),
+                    &"",
+                    &newest.date.signed_duration_since(first_date).num_days(),
+                    &extra.keep(),
+                    &sequence,
+                    &"",
+                ])
+                .or_raise(|| CleanError::RendererAddRow)?;
+        }
+
         Ok(())
     }
+
+    /// The messages of `mailbox` beyond `keep-newest` or `keep-size`, besides
+    /// those `deleted`, searching those they may delete.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(imap, messages, deleted), err(level = "info"))
+    )]
+    async fn dropped<'messages>(
+        imap: &mut Imap<MyExtra>,
+        mailbox: &str,
+        extra: &MyExtra,
+        messages: &'messages [Message],
+        deleted: &HashSet<Uid>,
+    ) -> Result<Vec<&'messages Message>, CleanError> {
+        let deletable = match extra.keep_search() {
+            Some(search) if extra.keeps() => Some(imap.uid_search(&search).await.or_raise(
+                || CleanError::ImapUidSearch {
+                    mailbox: mailbox.to_owned(),
+                },
+            )?),
+            _ => None,
+        };
+        Ok(extra.dropped(messages, deleted, deletable.as_ref()))
+    }
+
+    /// The UID, date and size of every message of `mailbox`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(imap), err(level = "info"))
+    )]
+    async fn fetch_messages(
+        imap: &mut Imap<MyExtra>,
+        mailbox: &str,
+    ) -> Result<Vec<Message>, CleanError> {
+        let fetched = imap
+            .uid_fetch("1:*", "(RFC822.SIZE INTERNALDATE)")
+            .await
+            .or_raise(|| CleanError::ImapUidFetch {
+                mailbox: mailbox.to_owned(),
+            })?;
+
+        let mut messages = vec![];
+        for m in fetched {
+            if let Some(uid) = m.uid {
+                messages.push(Message {
+                    uid,
+                    date: m
+                        .internal_date()
+                        .ok_or_raise(|| CleanError::ImapNoInternalDate {
+                            mailbox: mailbox.to_owned(),
+                            uid,
+                        })?,
+                    size: m.size.unwrap_or(0),
+                });
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Delete the messages of `sequence` in `mailbox`, unless `dry_run`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(imap), err(level = "info"))
+    )]
+    async fn delete(
+        imap: &mut Imap<MyExtra>,
+        mailbox: &str,
+        sequence: &str,
+        dry_run: bool,
+    ) -> Result<(), CleanError> {
+        if dry_run {
+            return Ok(());
+        }
+        imap.delete_uids(mailbox, sequence)
+            .await
+            .or_raise(|| CleanError::ImapDeleteUid {
+                mailbox: mailbox.to_owned(),
+            })
+    }
+
+    /// Report that `mailbox` was not cleaned, and why.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(renderer), err(level = "debug"))
+    )]
+    fn skipped(
+        renderer: &mut (dyn Renderer<RENDERER_LEN> + Send),
+        mailbox: &str,
+        exists: u32,
+        size: &str,
+        reason: &str,
+    ) -> Result<(), CleanError> {
+        renderer
+            .add_row(&[
+                &mailbox, &exists, &size, &0, &"", &"", &"", &"", &"", &"", &"", &reason,
+            ])
+            .or_raise(|| CleanError::RendererAddRow)
+    }
 }
 
 #[cfg(test)]
@@ -245,22 +836,28 @@ mod tests {
     #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(), ret))]
     fn test_extra() -> MyExtra {
         // Delete messages older than 30 days when mailbox exceeds 1 MB
-        [(Size::from_bytes(1_000_000_i64), 30_u64)].into()
+        MyExtra {
+            rules: [(Size::from_bytes(1_000_000_i64), Period::days(30).into())].into(),
+            ..MyExtra::default()
+        }
     }
 
-    #[tokio::test]
-    async fn cleanup_skips_small_mailbox() {
-        // exists = 50 ≤ 300 → should return immediately without UID FETCH
-        let server = MockServer::start(&[], vec![MockExchange::ok("EXAMINE \"INBOX\"", vec![
-            "* 50 EXISTS\r\n".into(),
-            "* 0 RECENT\r\n".into(),
-        ])])
-        .await;
+    /// Clean `mailbox` on a server following `script`, and return the result
+    /// with what was rendered.
+    async fn cleanup(
+        mailbox: &str,
+        extra: &MyExtra,
+        top: Option<&MyExtra>,
+        dry_run: bool,
+        script: Vec<MockExchange>,
+    ) -> (Result<(), CleanError>, String) {
+        let server = MockServer::start(&[], script).await;
         let base = test_base();
         let mut imap: Imap<MyExtra> = Imap::connect_base_on_port(&base, server.port)
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Cleaner",
             RENDERER_FORMAT,
@@ -268,92 +865,93 @@ mod tests {
         )
         .expect("renderer");
         let result =
-            Clean::cleanup_mailbox(&mut imap, &mut renderer, "INBOX", &test_extra(), false).await;
+            Clean::cleanup_mailbox(&mut imap, renderer.as_mut(), mailbox, extra, top, dry_run)
+                .await;
         let _ = imap.close().await;
         server.join().await;
+        (result, renderer.output())
+    }
+
+    #[tokio::test]
+    async fn cleanup_skips_small_mailbox() {
+        // exists = 50 ≤ 300 → should return immediately without UID FETCH
+        let (result, output) =
+            cleanup("INBOX", &test_extra(), None, false, vec![MockExchange::ok(
+                "EXAMINE \"INBOX\"",
+                vec!["* 50 EXISTS\r\n".into(), "* 0 RECENT\r\n".into()],
+            )])
+            .await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        assert_snapshot!(renderer.output(), @"Mailbox,Msgs,Size,Del,First date,Cutoff date,Days,Sequence");
+        assert_snapshot!(output, @r#"
+        Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped
+        INBOX,50,,0,,,,,,,,"50 messages, min-messages is 300"
+        "#);
     }
 
     #[tokio::test]
     async fn cleanup_skips_small_total_size() {
         // exists = 350 but total size < 1 MB → should skip
-        let server = MockServer::start(
-            &[],
-            vec![
-                // EXAMINE → 350 messages
-                MockExchange::ok("EXAMINE \"INBOX\"", vec![
-                    "* 350 EXISTS\r\n".into(),
-                    "* 0 RECENT\r\n".into(),
-                ]),
-                // UID FETCH RFC822.SIZE INTERNALDATE → small messages
-                MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
-                    "* 1 FETCH (UID 1 RFC822.SIZE 1024 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                    "* 2 FETCH (UID 2 RFC822.SIZE 1024 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                ]),
-            ],
-        )
+        let (result, output) = cleanup("INBOX", &test_extra(), None, false, vec![
+            // EXAMINE → 350 messages
+            MockExchange::ok("EXAMINE \"INBOX\"", vec![
+                "* 350 EXISTS\r\n".into(),
+                "* 0 RECENT\r\n".into(),
+            ]),
+            // UID FETCH RFC822.SIZE INTERNALDATE → small messages
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 1024 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 1024 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+        ])
         .await;
-        let base = test_base();
-        let mut imap: Imap<MyExtra> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
-        let mut renderer = new_renderer(
-            base.renderer,
-            "Mailbox Cleaner",
-            RENDERER_FORMAT,
-            RENDERER_HEADERS,
-        )
-        .expect("renderer");
-        let result =
-            Clean::cleanup_mailbox(&mut imap, &mut renderer, "INBOX", &test_extra(), false).await;
-        let _ = imap.close().await;
-        server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        assert_snapshot!(renderer.output(), @"Mailbox,Msgs,Size,Del,First date,Cutoff date,Days,Sequence");
+        assert_snapshot!(output, @r#"
+        Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped
+        INBOX,350,2.00 KiB,0,,,,,,,,"2.00 KiB, min-size is 977 KiB"
+        "#);
+    }
+
+    #[tokio::test]
+    async fn cleanup_fails_without_internal_date() {
+        // A message without INTERNALDATE must not be taken as from 1970
+        let (result, _) = cleanup("INBOX", &test_extra(), None, false, vec![
+            MockExchange::ok("EXAMINE \"INBOX\"", vec!["* 350 EXISTS\r\n".into()]),
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 1024 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 1024)\r\n".into(),
+            ]),
+        ])
+        .await;
+        let err = result.expect_err("missing INTERNALDATE");
+        assert!(
+            format!("{err:?}").contains("server did not return INTERNALDATE for UID 2 in INBOX"),
+            "unexpected error: {err:?}"
+        );
     }
 
     #[tokio::test]
     async fn cleanup_skips_when_no_old_messages() {
         // Large mailbox (> 1 MB) but UID SEARCH returns empty → no deletion
-        let server = MockServer::start(
-            &[],
-            vec![
-                // EXAMINE → 350 messages
-                MockExchange::ok("EXAMINE \"INBOX\"", vec![
-                    "* 350 EXISTS\r\n".into(),
-                    "* 0 RECENT\r\n".into(),
-                ]),
-                // UID FETCH → 2 large messages (total > 1 MB)
-                MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
-                    "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                    "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                ]),
-                // UID SEARCH → no results
-                MockExchange::ok(
-                    r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
-                    vec!["* SEARCH\r\n".into()],
-                ),
-            ],
-        )
+        let (result, output) = cleanup("INBOX", &test_extra(), None, false, vec![
+            // EXAMINE → 350 messages
+            MockExchange::ok("EXAMINE \"INBOX\"", vec![
+                "* 350 EXISTS\r\n".into(),
+                "* 0 RECENT\r\n".into(),
+            ]),
+            // UID FETCH → 2 large messages (total > 1 MB)
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            // UID SEARCH → no results
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH\r\n".into()],
+            ),
+        ])
         .await;
-        let base = test_base();
-        let mut imap: Imap<MyExtra> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
-        let mut renderer = new_renderer(
-            base.renderer,
-            "Mailbox Cleaner",
-            RENDERER_FORMAT,
-            RENDERER_HEADERS,
-        )
-        .expect("renderer");
-        let result =
-            Clean::cleanup_mailbox(&mut imap, &mut renderer, "INBOX", &test_extra(), false).await;
-        let _ = imap.close().await;
-        server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        assert_snapshot!(renderer.output(), @"Mailbox,Msgs,Size,Del,First date,Cutoff date,Days,Sequence");
+        assert_snapshot!(output, @"Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped");
     }
 
     #[tokio::test]
@@ -361,64 +959,51 @@ mod tests {
         // Two rules: (500 KB, 1 day) and (1 MB, 365 days). BTreeMap iterates ascending by size,
         // so the 500 KB rule runs first. Total size is 1.2 MB so both thresholds are exceeded,
         // but the 1-day search returns nothing → first rule skipped. 365-day search finds old
-        // messages → second rule matches. dry_run=true so no SELECT/STORE/CLOSE.
-        let extra: MyExtra = [
-            (Size::from_bytes(500_000_i64), 1_u64),
-            (Size::from_bytes(1_000_000_i64), 365_u64),
-        ]
-        .into();
-        let server = MockServer::start(
-            &[],
-            vec![
-                // EXAMINE → 350 messages
-                MockExchange::ok("EXAMINE \"INBOX\"", vec![
-                    "* 350 EXISTS\r\n".into(),
-                    "* 0 RECENT\r\n".into(),
-                ]),
-                // UID FETCH → 2 large messages (total 1.2 MB)
-                MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
-                    "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                    "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                ]),
-                // UID SEARCH (1 day) → empty
-                MockExchange::ok(
-                    r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
-                    vec!["* SEARCH\r\n".into()],
-                ),
-                // UID SEARCH (365 days) → UIDs 1 and 2
-                MockExchange::ok(
-                    r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
-                    vec!["* SEARCH 1 2\r\n".into()],
-                ),
-            ],
-        )
+        // messages → second rule matches. dry_run=true so no SELECT/STORE/EXPUNGE.
+        let extra = MyExtra {
+            rules: [
+                (Size::from_bytes(500_000_i64), Period::days(1).into()),
+                (Size::from_bytes(1_000_000_i64), Period::days(365).into()),
+            ]
+            .into(),
+            ..MyExtra::default()
+        };
+        let (result, output) = cleanup("INBOX", &extra, None, true, vec![
+            // EXAMINE → 350 messages
+            MockExchange::ok("EXAMINE \"INBOX\"", vec![
+                "* 350 EXISTS\r\n".into(),
+                "* 0 RECENT\r\n".into(),
+            ]),
+            // UID FETCH → 2 large messages (total 1.2 MB)
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            // UID SEARCH (1 day) → empty
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH\r\n".into()],
+            ),
+            // UID SEARCH (365 days) → UIDs 1 and 2
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH 1 2\r\n".into()],
+            ),
+        ])
         .await;
-        let base = test_base();
-        let mut imap: Imap<MyExtra> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
-        let mut renderer = new_renderer(
-            base.renderer,
-            "Mailbox Cleaner",
-            RENDERER_FORMAT,
-            RENDERER_HEADERS,
-        )
-        .expect("renderer");
-        let result = Clean::cleanup_mailbox(&mut imap, &mut renderer, "INBOX", &extra, true).await;
-        let _ = imap.close().await;
-        server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        let out: Vec<String> = renderer
-            .output()
+        let out: Vec<String> = output
             .split('\n')
             .map(std::borrow::ToOwned::to_owned)
             .collect();
         assert_eq!(out.len(), 3);
-        assert_snapshot!(out[0], @"Mailbox,Msgs,Size,Del,First date,Cutoff date,Days,Sequence");
+        assert_snapshot!(out[0], @"Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped");
         assert!(
-            regex::Regex::new(r"^INBOX,350,1.14 MiB,2,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,\d+,1:2$")
-                .expect("should parse")
-                .is_match(&out[1]),
+            regex::Regex::new(
+                r"^INBOX,350,1.14 MiB,2,expunged,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,365 days,\d+,SEEN UNFLAGGED,1:2,$"
+            )
+            .expect("should parse")
+            .is_match(&out[1]),
             "not matching {:?}",
             out[1]
         );
@@ -427,121 +1012,328 @@ mod tests {
 
     #[tokio::test]
     async fn cleanup_dry_run_large_old_mailbox() {
-        // exists = 350, total size > 1 MB, old messages → dry-run: no SELECT/STORE/CLOSE
-        let server = MockServer::start(
-            &[],
-            vec![
-                // EXAMINE → 350 messages
-                MockExchange::ok("EXAMINE \"INBOX\"", vec![
-                    "* 350 EXISTS\r\n".into(),
-                    "* 0 RECENT\r\n".into(),
-                ]),
-                // UID FETCH → 2 large old messages (total > 1 MB)
-                MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
-                    "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                    "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                ]),
-                // UID SEARCH → old messages to delete
-                MockExchange::ok(
-                    r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
-                    vec!["* SEARCH 1 2\r\n".into()],
-                ),
-            ],
-        )
+        // exists = 350, total size > 1 MB, old messages → dry-run: no SELECT/STORE/EXPUNGE
+        let (result, output) = cleanup("INBOX", &test_extra(), None, true, vec![
+            // EXAMINE → 350 messages
+            MockExchange::ok("EXAMINE \"INBOX\"", vec![
+                "* 350 EXISTS\r\n".into(),
+                "* 0 RECENT\r\n".into(),
+            ]),
+            // UID FETCH → 2 large old messages (total > 1 MB)
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            // UID SEARCH → old messages to delete
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH 1 2\r\n".into()],
+            ),
+        ])
         .await;
-        let base = test_base();
-        let mut imap: Imap<MyExtra> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
-        let mut renderer = new_renderer(
-            base.renderer,
-            "Mailbox Cleaner",
-            RENDERER_FORMAT,
-            RENDERER_HEADERS,
-        )
-        .expect("renderer");
-        let result =
-            Clean::cleanup_mailbox(&mut imap, &mut renderer, "INBOX", &test_extra(), true).await;
-        let _ = imap.close().await;
-        server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        let out: Vec<String> = renderer
-            .output()
+        let out: Vec<String> = output
             .split('\n')
             .map(std::borrow::ToOwned::to_owned)
             .collect();
         assert_eq!(out.len(), 3);
-        assert_snapshot!(out[0], @"Mailbox,Msgs,Size,Del,First date,Cutoff date,Days,Sequence");
+        assert_snapshot!(out[0], @"Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped");
         assert!(
-            regex::Regex::new(r"^INBOX,350,1.14 MiB,2,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,\d+,1:2$")
-                .expect("should parse")
-                .is_match(&out[1]),
+            regex::Regex::new(
+                r"^INBOX,350,1.14 MiB,2,expunged,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,30 days,\d+,SEEN UNFLAGGED,1:2,$"
+            )
+            .expect("should parse")
+            .is_match(&out[1]),
             "not matching {:?}",
             out[1]
         );
         assert!(out[2].is_empty());
     }
 
+    #[test]
+    fn rules_with_and_without_search() {
+        let extra: MyExtra = serde_any::from_str(
+            r#"
+            "1 MB" = 30
+            "10 MB" = { days = "2w", search = "UNSEEN FROM news@example.com" }
+            "100 MB" = "6 months"
+            "#,
+            serde_any::Format::Toml,
+        )
+        .expect("valid rules");
+        assert_eq!(
+            extra
+                .rules
+                .values()
+                .map(|rule| format!("{} {}", rule.days, rule.search()))
+                .collect::<Vec<_>>(),
+            [
+                "30 days SEEN UNFLAGGED",
+                "2 weeks UNSEEN FROM news@example.com",
+                "6 months SEEN UNFLAGGED"
+            ]
+        );
+        let err = serde_any::from_str::<MyExtra>(
+            r#""1 MB" = { days = 7, search = "UNREAD" }"#,
+            serde_any::Format::Toml,
+        )
+        .expect_err("unknown search key");
+        assert!(
+            err.to_string().contains("Unknown search key \"UNREAD\""),
+            "got: {err}"
+        );
+    }
+
     #[tokio::test]
-    async fn cleanup_destructive_large_old_mailbox() {
-        // Same as dry_run test but with dry_run=false: expects SELECT + UID STORE + CLOSE
-        let server = MockServer::start(
-            &[],
-            vec![
-                // EXAMINE → 350 messages
-                MockExchange::ok("EXAMINE \"INBOX\"", vec![
-                    "* 350 EXISTS\r\n".into(),
-                    "* 0 RECENT\r\n".into(),
-                ]),
-                // UID FETCH RFC822.SIZE INTERNALDATE → 2 large old messages (total > 1 MB)
-                MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
-                    "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                    "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
-                ]),
-                // UID SEARCH → old messages to delete
-                MockExchange::ok(
-                    r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
-                    vec!["* SEARCH 1 2\r\n".into()],
-                ),
-                // SELECT INBOX (read-write for deletion)
-                MockExchange::ok("SELECT \"INBOX\"", vec![
-                    "* 350 EXISTS\r\n".into(),
-                    "* 0 RECENT\r\n".into(),
-                ]),
-                // UID STORE +FLAGS (\Deleted)
-                MockExchange::ok("UID STORE 1:2 +FLAGS (\\Deleted)", vec![]),
-                // CLOSE (expunge)
-                MockExchange::ok("CLOSE", vec![]),
-            ],
+    async fn cleanup_thresholds_from_filter_then_top() {
+        // The filter turns min-size off, the top extra min-messages: the
+        // 2 small messages are cleaned
+        let extra: MyExtra = serde_any::from_str(
+            r#"{ "min-size": 0, "0 MB": "1 month" }"#,
+            serde_any::Format::Json,
+        )
+        .expect("valid extra");
+        let top: MyExtra = serde_any::from_str(
+            r#"{ "min-messages": 0, "min-size": "1 GB" }"#,
+            serde_any::Format::Json,
         )
+        .expect("valid top extra");
+        assert_eq!(
+            (extra.min_messages(Some(&top)), extra.min_messages(None)),
+            (0, 300)
+        );
+        let (result, output) = cleanup("Junk", &extra, Some(&top), true, vec![
+            MockExchange::ok("EXAMINE \"Junk\"", vec!["* 2 EXISTS\r\n".into()]),
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 1024 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 1024 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH 1 2\r\n".into()],
+            ),
+        ])
         .await;
-        let base = test_base();
-        let mut imap: Imap<MyExtra> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
-        let mut renderer = new_renderer(
-            base.renderer,
-            "Mailbox Cleaner",
-            RENDERER_FORMAT,
-            RENDERER_HEADERS,
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+        assert!(
+            regex::Regex::new(
+                r"(?m)^Junk,2,2.00 KiB,2,expunged,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,1 month,\d+,SEEN UNFLAGGED,1:2,$"
+            )
+            .expect("should parse")
+            .is_match(&output),
+            "not matching {output:?}"
+        );
+    }
+
+    #[test]
+    fn dropped_beyond_keep_newest_or_keep_size() {
+        let messages: Vec<_> = [(1, 1, 300), (2, 3, 100), (3, 2, 200), (4, 4, 100)]
+            .into_iter()
+            .map(|(uid, day, size)| Message {
+                uid,
+                date: DateTime::parse_from_rfc3339(&format!("2020-01-{day:02}T10:00:00Z"))
+                    .expect("valid date"),
+                size,
+            })
+            .collect();
+        let dropped = |keep_newest, keep_size: Option<i64>, deleted: &[Uid]| {
+            let extra = MyExtra {
+                keep_newest,
+                keep_size: keep_size.map(Size::from_bytes),
+                ..MyExtra::default()
+            };
+            extra
+                .dropped(&messages, &deleted.iter().copied().collect(), None)
+                .iter()
+                .map(|message| message.uid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dropped(None, None, &[]), [] as [Uid; 0], "nothing to keep");
+        assert_eq!(dropped(Some(2), None, &[]), [3, 1], "newest by date");
+        assert_eq!(dropped(Some(2), None, &[4]), [1], "deleted by a rule");
+        assert_eq!(dropped(None, Some(300), &[]), [3, 1], "oldest first");
+        assert_eq!(dropped(Some(1), Some(1000), &[]), [2, 3, 1], "both");
+
+        let extra = MyExtra {
+            keep_newest: Some(1),
+            ..MyExtra::default()
+        };
+        let dropped: Vec<_> = extra
+            .dropped(&messages, &HashSet::new(), Some(&[1, 2, 4].into()))
+            .iter()
+            .map(|message| message.uid)
+            .collect();
+        assert_eq!(dropped, [2, 1], "only the deletable ones");
+    }
+
+    #[test]
+    fn keep_search_of_the_rules_unless_deleting_any() {
+        let extra = |json| {
+            serde_any::from_str::<MyExtra>(json, serde_any::Format::Json)
+                .expect("valid extra")
+                .keep_search()
+                .map(Cow::into_owned)
+        };
+        assert_eq!(
+            extra(r#"{ "keep-newest": 1 }"#).as_deref(),
+            Some(DEFAULT_SEARCH)
+        );
+        assert_eq!(
+            extra(r#"{ "keep-newest": 1, "0 MB": 30, "1 MB": { "days": 7, "search": "UNSEEN" } }"#)
+                .as_deref(),
+            Some("UNSEEN")
+        );
+        assert_eq!(
+            extra(r#"{ "keep-newest": 1, "keep-deletes-any": true }"#),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_keeps_newest_besides_rules() {
+        // The rule deletes UID 1, keep-newest the oldest of the others
+        let extra: MyExtra = serde_any::from_str(
+            r#"{ "min-messages": 0, "min-size": 0, "keep-newest": 1, "0 MB": 30 }"#,
+            serde_any::Format::Json,
         )
-        .expect("renderer");
-        let result =
-            Clean::cleanup_mailbox(&mut imap, &mut renderer, "INBOX", &test_extra(), false).await;
-        let _ = imap.close().await;
-        server.join().await;
+        .expect("valid extra");
+        let (result, output) = cleanup("Alerts", &extra, None, false, vec![
+            MockExchange::ok("EXAMINE \"Alerts\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 1024 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 1024 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 3 FETCH (UID 3 RFC822.SIZE 1024 INTERNALDATE \"03-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH 1\r\n".into()],
+            ),
+            MockExchange::ok("SELECT \"Alerts\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 1 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID EXPUNGE 1", vec![]),
+            MockExchange::ok("UID SEARCH SEEN UNFLAGGED", vec!["* SEARCH 2 3\r\n".into()]),
+            MockExchange::ok("SELECT \"Alerts\"", vec!["* 2 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 2 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID EXPUNGE 2", vec![]),
+        ])
+        .await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+        let keep_row = output.lines().last().expect("keep row");
+        assert_snapshot!(keep_row, @"Alerts,3,3.00 KiB,1,expunged,01-Jan-2020,02-Jan-2020,,1,keep-newest 1,2,");
+        assert!(
+            output.contains(",SEEN UNFLAGGED,1,"),
+            "missing the rule row in {output:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_keeps_flagged_beyond_keep_newest() {
+        // UID 1 is flagged, so only UID 2 is deleted beyond the newest
+        let extra: MyExtra = serde_any::from_str(
+            r#"{ "min-messages": 0, "min-size": 0, "keep-newest": 1 }"#,
+            serde_any::Format::Json,
+        )
+        .expect("valid extra");
+        let (result, output) = cleanup("Alerts", &extra, None, false, vec![
+            MockExchange::ok("EXAMINE \"Alerts\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 1024 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 1024 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 3 FETCH (UID 3 RFC822.SIZE 1024 INTERNALDATE \"03-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            MockExchange::ok("UID SEARCH SEEN UNFLAGGED", vec!["* SEARCH 2 3\r\n".into()]),
+            MockExchange::ok("SELECT \"Alerts\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 2 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID EXPUNGE 2", vec![]),
+        ])
+        .await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+        assert_snapshot!(output, @"
+        Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped
+        Alerts,3,3.00 KiB,1,expunged,01-Jan-2020,02-Jan-2020,,1,keep-newest 1,2,
+        ");
+    }
+
+    #[tokio::test]
+    async fn cleanup_uses_the_search_of_the_rule() {
+        let extra = MyExtra {
+            rules: [(Size::from_bytes(1_000_000_i64), Rule {
+                days: Period::days(30),
+                search: Some(
+                    "UNSEEN FROM news@example.com"
+                        .parse()
+                        .expect("valid search"),
+                ),
+            })]
+            .into(),
+            ..MyExtra::default()
+        };
+        let (result, output) = cleanup("INBOX", &extra, None, true, vec![
+            MockExchange::ok("EXAMINE \"INBOX\"", vec!["* 350 EXISTS\r\n".into()]),
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            MockExchange::ok(
+                r"/^UID SEARCH UNSEEN FROM news@example.com BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH 2\r\n".into()],
+            ),
+        ])
+        .await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+        assert!(
+            regex::Regex::new(
+                r"(?m)^INBOX,350,1.14 MiB,1,expunged,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,30 days,\d+,UNSEEN FROM news@example.com,2,$"
+            )
+            .expect("should parse")
+            .is_match(&output),
+            "not matching {output:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_destructive_large_old_mailbox() {
+        // Same as dry_run test but with dry_run=false: expects SELECT + UID STORE + UID EXPUNGE
+        let (result, output) = cleanup("INBOX", &test_extra(), None, false, vec![
+            // EXAMINE → 350 messages
+            MockExchange::ok("EXAMINE \"INBOX\"", vec![
+                "* 350 EXISTS\r\n".into(),
+                "* 0 RECENT\r\n".into(),
+            ]),
+            // UID FETCH RFC822.SIZE INTERNALDATE → 2 large old messages (total > 1 MB)
+            MockExchange::ok("UID FETCH 1:* (RFC822.SIZE INTERNALDATE)", vec![
+                "* 1 FETCH (UID 1 RFC822.SIZE 600000 INTERNALDATE \"01-Jan-2020 10:00:00 +0000\")\r\n".into(),
+                "* 2 FETCH (UID 2 RFC822.SIZE 600000 INTERNALDATE \"02-Jan-2020 10:00:00 +0000\")\r\n".into(),
+            ]),
+            // UID SEARCH → old messages to delete
+            MockExchange::ok(
+                r"/^UID SEARCH SEEN UNFLAGGED BEFORE \d\d-\w\w\w-\d\d\d\d$/",
+                vec!["* SEARCH 1 2\r\n".into()],
+            ),
+            // SELECT INBOX (read-write for deletion)
+            MockExchange::ok("SELECT \"INBOX\"", vec![
+                "* 350 EXISTS\r\n".into(),
+                "* 0 RECENT\r\n".into(),
+            ]),
+            // UID STORE +FLAGS (\Deleted)
+            MockExchange::ok("UID STORE 1:2 +FLAGS (\\Deleted)", vec![]),
+            // UID EXPUNGE only the flagged UIDs
+            MockExchange::ok("UID EXPUNGE 1:2", vec![]),
+        ])
+        .await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        let out: Vec<String> = renderer
-            .output()
+        let out: Vec<String> = output
             .split('\n')
             .map(std::borrow::ToOwned::to_owned)
             .collect();
         assert_eq!(out.len(), 3);
-        assert_snapshot!(out[0], @"Mailbox,Msgs,Size,Del,First date,Cutoff date,Days,Sequence");
+        assert_snapshot!(out[0], @"Mailbox,Msgs,Size,Del,Destination,First date,Cutoff date,Age,Days,Search,Sequence,Skipped");
         assert!(
-            regex::Regex::new(r"^INBOX,350,1.14 MiB,2,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,\d+,1:2$")
-                .expect("should parse")
-                .is_match(&out[1]),
+            regex::Regex::new(
+                r"^INBOX,350,1.14 MiB,2,expunged,01-Jan-2020,\d\d-\w\w\w-\d\d\d\d,30 days,\d+,SEEN UNFLAGGED,1:2,$"
+            )
+            .expect("should parse")
+            .is_match(&out[1]),
             "not matching {:?}",
             out[1]
         );
@@ -0,0 +1,337 @@
+use std::{collections::BTreeMap, fs};
+
+use clap::{Args, ValueEnum};
+use exn::{Result, ResultExt as _, bail};
+use serde_value::Value;
+
+use crate::{
+    commands::{archive, clean},
+    libs::{
+        args,
+        config::{Config, Problem},
+        config_file::{self, Source},
+        render::{Renderer, Run, new_renderer},
+    },
+};
+
+#[derive(Debug, derive_more::Display)]
+pub enum ConfigCheckError {
+    #[display("Loading the configuration of {command}")]
+    Config { command: &'static str },
+    #[display("Creating renderer")]
+    NewRenderer,
+    #[display("Adding renderer row")]
+    RendererAddRow,
+    #[display("Problems found in the configuration: {count}")]
+    Invalid { count: usize },
+}
+impl std::error::Error for ConfigCheckError {}
+
+#[derive(Args, Debug, Clone)]
+#[command(
+    about = "Check the configuration without connecting",
+    long_about = "This command reads the configuration like the other commands would, without
+connecting to the server, and reports all its problems at once: unknown or
+invalid settings, bad filter regexes, archive formats and clean sizes, with
+the file and line they are at.
+
+The settings of every command are checked, unless --command is given."
+)]
+pub struct Check {
+    #[clap(flatten)]
+    pub(super) config: args::Generic,
+
+    /// Only check the settings of this command.
+    #[arg(long, value_enum)]
+    command: Option<Checked>,
+}
+
+/// The commands whose settings are checked, each with its own `extra`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Checked {
+    Archive,
+    Clean,
+    FindDups,
+    /// The list and imap commands, which use no section.
+    List,
+}
+
+impl Checked {
+    /// The name of the command.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Archive => "archive",
+            Self::Clean => "clean",
+            Self::FindDups => "find-dups",
+            Self::List => "list",
+        }
+    }
+
+    /// The section of the configuration of the command.
+    const fn section(self) -> Option<&'static str> {
+        match self {
+            Self::List => None,
+            Self::Archive | Self::Clean | Self::FindDups => Some(self.name()),
+        }
+    }
+
+    /// The problems of the `settings` of the command.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(settings, args), ret)
+    )]
+    fn problems(self, settings: &BTreeMap<String, Value>, args: &args::Generic) -> Vec<Problem> {
+        match self {
+            Self::Archive => Config::<archive::MyExtra>::check(settings, args),
+            Self::Clean => Config::<clean::MyExtra>::check(settings, args),
+            Self::FindDups | Self::List => Config::<Value>::check(settings, args),
+        }
+    }
+}
+
+static RENDERER_LEN: usize = 4;
+static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<20", ":<24", ":<50", ""];
+static RENDERER_HEADERS: &[&str; RENDERER_LEN] = &["Command", "Setting", "Problem", "Location"];
+
+/// A problem, with the commands it was found for.
+type Found = (Vec<&'static str>, [String; 3]);
+
+impl Check {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub fn execute(&self, run: Option<&Run>) -> Result<(), ConfigCheckError> {
+        let found = self.problems()?;
+
+        let mut renderer = new_renderer(
+            run,
+            self.config.renderer,
+            "Configuration check",
+            RENDERER_FORMAT,
+            RENDERER_HEADERS,
+        )
+        .or_raise(|| ConfigCheckError::NewRenderer)?;
+        self.run(&found, &mut renderer)?;
+
+        if !found.is_empty() {
+            bail!(ConfigCheckError::Invalid { count: found.len() });
+        }
+        Ok(())
+    }
+
+    /// The commands to check.
+    fn commands(&self) -> Vec<Checked> {
+        self.command.map_or_else(
+            || Checked::value_variants().to_vec(),
+            |command| vec![command],
+        )
+    }
+
+    /// The problems of the settings of each command, those shared by
+    /// several commands only once.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
+    )]
+    fn problems(&self) -> Result<Vec<Found>, ConfigCheckError> {
+        let mut found: Vec<Found> = vec![];
+        for command in self.commands() {
+            let (settings, sources) = config_file::load_sourced::<BTreeMap<String, Value>>(
+                self.config.config.as_deref(),
+                self.config.account.as_deref(),
+                command.section(),
+                &[],
+            )
+            .or_raise(|| ConfigCheckError::Config {
+                command: command.name(),
+            })?;
+
+            for problem in command.problems(&settings, &self.config) {
+                let location = problem
+                    .setting
+                    .split(['.', '['])
+                    .next()
+                    .and_then(|key| sources.get(key))
+                    .map(|source| locate(source, problem.needle.as_deref()))
+                    .unwrap_or_default();
+                let row = [problem.setting, problem.message, location];
+                if let Some(known) = found.iter_mut().find(|known| known.1 == row) {
+                    known.0.push(command.name());
+                } else {
+                    found.push((vec![command.name()], row));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Render the `found` problems, or that each command is fine.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, renderer), err(level = "debug"))
+    )]
+    fn run(
+        &self,
+        found: &[Found],
+        renderer: &mut Box<dyn Renderer<RENDERER_LEN> + Send>,
+    ) -> Result<(), ConfigCheckError> {
+        if found.is_empty() {
+            for command in self.commands() {
+                renderer
+                    .add_row(&[&command.name(), &"", &"OK", &""])
+                    .or_raise(|| ConfigCheckError::RendererAddRow)?;
+            }
+        }
+        for known in found {
+            let (ref commands, [ref setting, ref message, ref location]) = *known;
+            renderer
+                .add_row(&[&commands.join(", "), setting, message, location])
+                .or_raise(|| ConfigCheckError::RendererAddRow)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where `needle` is in the file of `source`, as `file:line:column`, or
+/// the source itself.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn locate(source: &Source, needle: Option<&str>) -> String {
+    if let Source::File {
+        ref file,
+        ref table,
+    } = *source
+        && let Some(needle) = needle
+        && let Ok(content) = fs::read_to_string(file)
+        && let Some((line, column)) = position(&content, table.as_deref(), needle)
+    {
+        return format!("{}:{line}:{column}", file.display());
+    }
+    source.to_string()
+}
+
+/// The line and column of `needle` in `content`, past the start of `table`,
+/// counted from 1. A line setting `needle` is preferred over one holding it.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(content), ret)
+)]
+fn position(content: &str, table: Option<&str>, needle: &str) -> Option<(usize, usize)> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut start = 0;
+    if let Some(table) = table {
+        let header = format!("{table}]");
+        if let Some(index) = lines
+            .iter()
+            .position(|line| line.trim_start().starts_with('[') && line.contains(&header))
+        {
+            start = index + 1;
+        } else {
+            // Each key of the table in turn, like in YAML or JSON
+            for key in table.split('.') {
+                if let Some(index) = lines.iter().skip(start).position(|line| sets(line, key)) {
+                    start += index + 1;
+                }
+            }
+        }
+    }
+
+    let rest = lines.get(start..)?;
+    let index = rest
+        .iter()
+        .position(|line| sets(line, needle))
+        .or_else(|| rest.iter().position(|line| line.contains(needle)))?;
+    let column = rest
+        .get(index)?
+        .split(needle)
+        .next()
+        .map(|before| before.chars().count() + 1)?;
+    Some((start + index + 1, column))
+}
+
+/// Whether `line` sets `key`, in TOML, YAML or JSON.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn sets(line: &str, key: &str) -> bool {
+    let line = line.trim_start().trim_start_matches("- ");
+    let line = line.strip_prefix('"').unwrap_or(line);
+    line.strip_prefix(key).is_some_and(|rest| {
+        let rest = rest.strip_prefix('"').unwrap_or(rest).trim_start();
+        rest.starts_with('=') || rest.starts_with(':')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use std::{fs::File, io::Write as _};
+
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    const CONFIG: &str = r#"server = "imap.example.com"
+username = "alice@example.com"
+password = "s3cret"
+prot = 993
+
+[archive]
+extra = { format = "Archives/%Y/%Q/%%MBX", days = 365 }
+
+[clean]
+extra = { "10 MB" = 30, "lots" = 60, "1 GB" = { days = 7, search = "UNREAD" } }
+
+[[clean.filters]]
+reference = "INBOX"
+include-re = ["^Lists/(", "^Spam$"]
+"#;
+
+    #[test]
+    fn every_problem_with_its_position() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let file = dir.path().join("config.toml");
+        File::create(&file)
+            .expect("create")
+            .write_all(CONFIG.as_bytes())
+            .expect("write");
+        let check = Check {
+            config: args::Generic {
+                config: Some(file),
+                ..Default::default()
+            },
+            command: None,
+        };
+
+        let found = check.problems().expect("problems");
+        let mut renderer =
+            new_renderer(None, None, "Check", RENDERER_FORMAT, RENDERER_HEADERS).expect("renderer");
+        check.run(&found, &mut renderer).expect("run");
+        let output = renderer
+            .output()
+            .replace(&dir.path().display().to_string(), "DIR");
+        assert_snapshot!(output, @r#"
+        Command,Setting,Problem,Location
+        archive,extra,"Invalid date format ""Archives/%Y/%Q/%%MBX""",DIR/config.toml:7:1
+        "archive, clean, find-dups, list",prot,"Unknown field prot. Expected one of renderer, server, port, username, password, password-command, password-file, password-env, password-netrc, mode, debug, dry-run, auth, oauth2-command, oauth2-token-file, oauth2-token-env, oauth2-token-netrc, authorize-as, oauth2, expunge-fallback, uidplus-fallback, delete-mode, trash-mailbox, reconnect-attempts, reconnect-delay, connect-timeout, command-timeout, total-timeout, concurrency, tls-ca-file, tls-client-cert, tls-client-key, tls-pin-sha256, insecure-skip-verify, tls-policy, allow-plaintext-auth, tunnel, proxy, debug-file",DIR/config.toml:4:1
+        clean,extra,"Invalid rule for ""1 GB"": Unknown search key ""UNREAD""",DIR/config.toml:10:1
+        clean,extra,"Invalid size ""lots"": Invalid size: ""lots""",DIR/config.toml:10:1
+        clean,filters[0].include-re,unclosed group,DIR/config.toml:14:16
+        "#);
+    }
+
+    #[test]
+    fn position_in_table() {
+        let content = "a = 1\n[accounts.work]\nserver = \"x\"\n  port = 1\n";
+        assert_eq!(
+            position(content, Some("accounts.work"), "port"),
+            Some((4, 3)),
+            "setting in a TOML table"
+        );
+        let content = "accounts:\n  work:\n    port: 1\n";
+        assert_eq!(
+            position(content, Some("accounts.work"), "port"),
+            Some((3, 5)),
+            "setting in a YAML table"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use clap::Subcommand;
+use exn::{Result, ResultExt as _};
+
+use crate::libs::{args, render::Run};
+mod check;
+mod schema;
+mod show;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommands {
+    #[command(aliases = &["print"])]
+    Show(show::Show),
+
+    #[command(aliases = &["validate", "lint"])]
+    Check(check::Check),
+
+    Schema(schema::Schema),
+}
+
+#[derive(Debug, derive_more::Display)]
+pub enum ConfigCommandsError {
+    #[display("Running config show subcommand")]
+    Show,
+    #[display("Running config check subcommand")]
+    Check,
+    #[display("Running config schema subcommand")]
+    Schema,
+}
+impl std::error::Error for ConfigCommandsError {}
+
+impl ConfigCommands {
+    /// The generic arguments of the subcommand, if it has them.
+    pub(super) const fn generic_mut(&mut self) -> Option<&mut args::Generic> {
+        match *self {
+            Self::Show(ref mut show) => Some(&mut show.config),
+            Self::Check(ref mut check) => Some(&mut check.config),
+            Self::Schema(_) => None,
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub fn execute(&self, run: Option<&Run>) -> Result<(), ConfigCommandsError> {
+        match *self {
+            Self::Show(ref show) => show.execute(run).or_raise(|| ConfigCommandsError::Show),
+            Self::Check(ref check) => check.execute(run).or_raise(|| ConfigCommandsError::Check),
+            Self::Schema(ref schema) => schema.execute().or_raise(|| ConfigCommandsError::Schema),
+        }
+    }
+}
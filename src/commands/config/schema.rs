@@ -0,0 +1,149 @@
+use std::{fs::File, io::Write, path::PathBuf};
+
+use clap::Args;
+use exn::{Result, ResultExt as _};
+use schemars::SchemaGenerator;
+use serde_json::{Value, json};
+
+use crate::{
+    commands::{archive, clean},
+    libs::config::Config,
+};
+
+#[derive(Debug, derive_more::Display)]
+pub enum ConfigSchemaError {
+    #[display("Serializing the schema")]
+    Serialize,
+    #[display("Creating {file:?}")]
+    CreateOutput { file: PathBuf },
+    #[display("Writing command output")]
+    WriteOutput,
+}
+impl std::error::Error for ConfigSchemaError {}
+
+#[derive(Args, Debug, Clone)]
+#[command(
+    about = "Print the JSON Schema of the configuration file",
+    long_about = "This command prints a JSON Schema of the configuration file, with the
+command sections, the accounts and the includes, for the completion and
+validation of editors.
+
+For example, with taplo or the Even Better TOML extension, save it and start
+the configuration file with:
+
+#:schema ./imap-tools.schema.json"
+)]
+pub struct Schema {
+    /// Write the schema to this file instead of the standard output.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl Schema {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub fn execute(&self) -> Result<(), ConfigSchemaError> {
+        match self.output {
+            Some(ref file) => Self::run(
+                &mut File::create(file)
+                    .or_raise(|| ConfigSchemaError::CreateOutput { file: file.clone() })?,
+            ),
+            None => Self::run(&mut std::io::stdout()),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(out), err(level = "debug"))
+    )]
+    fn run(out: &mut (dyn Write + Send)) -> Result<(), ConfigSchemaError> {
+        serde_json::to_writer_pretty(&mut *out, &schema())
+            .or_raise(|| ConfigSchemaError::Serialize)?;
+        writeln!(out).or_raise(|| ConfigSchemaError::WriteOutput)
+    }
+}
+
+/// The schema of the configuration file: the settings with the command
+/// sections, the accounts, each with the same settings and sections, and
+/// the includes.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+fn schema() -> Value {
+    let mut generator = SchemaGenerator::default();
+    let sections = [
+        (
+            "archive",
+            generator.subschema_for::<Config<archive::MyExtra>>(),
+        ),
+        ("clean", generator.subschema_for::<Config<clean::MyExtra>>()),
+        ("find-dups", generator.subschema_for::<Config<Value>>()),
+    ];
+    let mut root = generator.into_root_schema_for::<Config<Value>>().to_value();
+
+    if let Some(properties) = root
+        .pointer_mut("/properties")
+        .and_then(Value::as_object_mut)
+    {
+        for (name, section) in sections {
+            properties.insert(name.to_owned(), section.to_value());
+        }
+    }
+    let account = json!({
+        "type": "object",
+        "properties": root.pointer("/properties").cloned().unwrap_or_default(),
+        "additionalProperties": false,
+    });
+
+    if let Some(properties) = root
+        .pointer_mut("/properties")
+        .and_then(Value::as_object_mut)
+    {
+        properties.insert(
+            "include".to_owned(),
+            json!({
+                "description": "Files read before this one, relative to it.",
+                "anyOf": [
+                    { "type": "string" },
+                    { "type": "array", "items": { "type": "string" } },
+                ],
+            }),
+        );
+        properties.insert(
+            "accounts".to_owned(),
+            json!({
+                "description": "The settings of each account, over those of the top of the file.",
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/Account" },
+            }),
+        );
+    }
+    if let Some(defs) = root.pointer_mut("/$defs").and_then(Value::as_object_mut) {
+        defs.insert("Account".to_owned(), account);
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn schema_has_sections_accounts_and_includes() {
+        let mut output = Vec::<u8>::new();
+        Schema::run(&mut output).expect("run");
+        let schema: Value = serde_json::from_slice(&output).expect("json");
+
+        let properties = schema
+            .pointer("/properties")
+            .and_then(Value::as_object)
+            .expect("properties");
+        assert_snapshot!(properties.keys().cloned().collect::<Vec<_>>().join(" "), @"accounts allow-plaintext-auth archive auth authorize-as clean command-timeout concurrency connect-timeout debug debug-file delete-mode dry-run extra filters find-dups include insecure-skip-verify mode oauth2 oauth2-command oauth2-token-env oauth2-token-file oauth2-token-netrc password password-command password-env password-file password-netrc port proxy reconnect-attempts reconnect-delay renderer server tls-ca-file tls-client-cert tls-client-key tls-pin-sha256 tls-policy total-timeout trash-mailbox tunnel uidplus-fallback username");
+        assert_snapshot!(schema.pointer("/properties/archive").expect("archive"), @r##"{"$ref":"#/$defs/Config_for_ArchiveExtra"}"##);
+        assert_snapshot!(schema.pointer("/properties/mode").expect("mode"), @r##"{"anyOf":[{"$ref":"#/$defs/Mode"},{"type":"null"}],"default":null}"##);
+    }
+}
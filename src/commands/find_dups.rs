@@ -3,14 +3,14 @@ use std::collections::{HashMap, HashSet};
 use async_imap::types::Uid;
 use clap::Args;
 use exn::{OptionExt as _, Result, ResultExt as _};
-use futures::TryStreamExt as _;
 use regex::Regex;
 
 use crate::libs::{
     args,
     config::Config,
-    imap::{Imap, ids_list_to_collapsed_sequence},
-    render::{Renderer, new_renderer},
+    imap::{EXPUNGED, Imap, ids_list_to_collapsed_sequence},
+    pool::ImapPool,
+    render::{Renderer, RowBuffer, Run, new_renderer},
 };
 
 #[derive(Debug, derive_more::Display)]
@@ -19,6 +19,8 @@ pub enum DuError {
     Config,
     #[display("Connecting to IMAP server")]
     Connect,
+    #[display("Reconnecting to IMAP server")]
+    Reconnect,
     #[display("Creating renderer")]
     NewRenderer,
     #[display("Listing mailboxes")]
@@ -31,14 +33,14 @@ pub enum DuError {
     ImapExamine { mailbox: String },
     #[display("Fetching message headers by UID in {mailbox}")]
     ImapUidFetch { mailbox: String },
-    #[display("Streaming UID FETCH results for {mailbox}")]
-    ImapUidFetchStream { mailbox: String },
     #[display(
         "The server does not support the UIDPLUS capability, and all our operations need UIDs for safety"
     )]
     NoUidPlus,
     #[display("Deleting duplicate messages in {mailbox}")]
     DeleteUids { mailbox: String },
+    #[display("Finding where the duplicate messages of {mailbox} go")]
+    Destination { mailbox: String },
     #[display("Adding renderer row")]
     RendererAddRow,
 }
@@ -54,7 +56,7 @@ it will delete the duplicates."
 )]
 pub struct FindDups {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 }
 
 type MyExtra = serde_value::Value;
@@ -72,21 +74,23 @@ static MESSAGE_ID_REGEX: std::sync::LazyLock<Regex> = std::sync::LazyLock::new(|
         .unwrap()
 });
 
-static RENDERER_LEN: usize = 3;
-static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<42", "", ""];
-static RENDERER_HEADERS: &[&str; RENDERER_LEN] = &["Mailbox", "Dups", "Sequence"];
+static RENDERER_LEN: usize = 4;
+static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<42", "", ":<20", ""];
+static RENDERER_HEADERS: &[&str; RENDERER_LEN] = &["Mailbox", "Dups", "Destination", "Sequence"];
 
 impl FindDups {
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), DuError> {
-        let config = Config::<MyExtra>::new(&self.config).or_raise(|| DuError::Config)?;
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), DuError> {
+        let config =
+            Config::<MyExtra>::new(&self.config, Some("find-dups")).or_raise(|| DuError::Config)?;
         #[cfg(feature = "tracing")]
         tracing::trace!(?config);
 
         let mut renderer = new_renderer(
+            run,
             config.base.renderer,
             if config.base.dry_run {
                 "Mailbox Deduplication DRY-RUN"
@@ -98,15 +102,32 @@ impl FindDups {
         )
         .or_raise(|| DuError::NewRenderer)?;
 
-        let mut imap = Imap::connect(&config).await.or_raise(|| DuError::Connect)?;
-
-        for (mailbox, _result) in imap.list().await.or_raise(|| DuError::ImapList)? {
-            Self::process(&mut imap, &mut renderer, &mailbox, config.base.dry_run)
+        let pool = ImapPool::connect(&config, false)
+            .await
+            .or_raise(|| DuError::Connect)?;
+
+        pool.for_each_mailbox(
+            pool.list().await.or_raise(|| DuError::ImapList)?,
+            async |imap, mailbox, _result| {
+                imap.with_reconnect(
+                    async |imap| {
+                        let mut rows = RowBuffer::default();
+                        Self::process(imap, &mut rows, &mailbox, config.base.dry_run).await?;
+                        Ok(rows)
+                    },
+                    || DuError::Reconnect,
+                )
                 .await
-                .or_raise(|| DuError::Process { mailbox })?;
-        }
+                .or_raise(|| DuError::Process { mailbox })
+            },
+            |rows| {
+                rows.replay(renderer.as_mut())
+                    .or_raise(|| DuError::RendererAddRow)
+            },
+        )
+        .await?;
 
-        imap.close().await.or_raise(|| DuError::ImapClose)?;
+        pool.close().await.or_raise(|| DuError::ImapClose)?;
 
         Ok(())
     }
@@ -117,14 +138,13 @@ impl FindDups {
     )]
     async fn process(
         imap: &mut Imap<MyExtra>,
-        renderer: &mut Box<dyn Renderer<RENDERER_LEN> + Send>,
+        renderer: &mut (dyn Renderer<RENDERER_LEN> + Send),
         mailbox: &str,
         dry_run: bool,
     ) -> Result<(), DuError> {
         // Examine the mailbox in read only mode, so that we don't change any
         // "seen" flags if there are no duplicate messages
         let mbx = imap
-            .session
             .examine(mailbox)
             .await
             .or_raise(|| DuError::ImapExamine {
@@ -140,27 +160,16 @@ impl FindDups {
         // Fetch message headers to find duplicates
         let mut message_ids: HashMap<String, Vec<Uid>> = HashMap::new();
 
-        {
-            let mut stream = imap
-                .session
-                .uid_fetch("1:*", "(BODY.PEEK[HEADER.FIELDS (MESSAGE-ID)])")
-                .await
-                .or_raise(|| DuError::ImapUidFetch {
-                    mailbox: mailbox.to_owned(),
-                })?;
-
-            while let Some(message) =
-                stream
-                    .try_next()
-                    .await
-                    .or_raise(|| DuError::ImapUidFetchStream {
-                        mailbox: mailbox.to_owned(),
-                    })?
-            {
-                if let Some(id) = Self::parse_message_id(message.header()) {
-                    let uid = message.uid.ok_or_raise(|| DuError::NoUidPlus)?;
-                    message_ids.entry(id).or_default().push(uid);
-                }
+        let fetched = imap
+            .uid_fetch("1:*", "(BODY.PEEK[HEADER.FIELDS (MESSAGE-ID)])")
+            .await
+            .or_raise(|| DuError::ImapUidFetch {
+                mailbox: mailbox.to_owned(),
+            })?;
+        for message in fetched {
+            if let Some(id) = Self::parse_message_id(message.header()) {
+                let uid = message.uid.ok_or_raise(|| DuError::NoUidPlus)?;
+                message_ids.entry(id).or_default().push(uid);
             }
         }
 
@@ -181,6 +190,12 @@ impl FindDups {
         // Delete duplicate messages
         if !duplicates.is_empty() {
             let duplicate_set = ids_list_to_collapsed_sequence(&duplicates);
+            let destination =
+                imap.destination(mailbox)
+                    .await
+                    .or_raise(|| DuError::Destination {
+                        mailbox: mailbox.to_owned(),
+                    })?;
 
             if !dry_run {
                 imap.delete_uids(mailbox, &duplicate_set)
@@ -191,7 +206,12 @@ impl FindDups {
             }
 
             renderer
-                .add_row(&[&mailbox, &duplicates.len(), &duplicate_set])
+                .add_row(&[
+                    &mailbox,
+                    &duplicates.len(),
+                    &destination.as_deref().unwrap_or(EXPUNGED),
+                    &duplicate_set,
+                ])
                 .or_raise(|| DuError::RendererAddRow)?;
         }
 
@@ -306,17 +326,18 @@ mod tests {
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Deduplication",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = FindDups::process(&mut imap, &mut renderer, "INBOX", false).await;
+        let result = FindDups::process(&mut imap, renderer.as_mut(), "INBOX", false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        assert_snapshot!(renderer.output(), @"Mailbox,Dups,Sequence");
+        assert_snapshot!(renderer.output(), @"Mailbox,Dups,Destination,Sequence");
     }
 
     #[tokio::test]
@@ -343,8 +364,8 @@ mod tests {
             ]),
             // UID STORE +FLAGS (\Deleted) for UIDs 3 and 5
             MockExchange::ok("UID STORE 3,5 +FLAGS (\\Deleted)", vec![]),
-            // CLOSE
-            MockExchange::ok("CLOSE", vec![]),
+            // UID EXPUNGE only the duplicates
+            MockExchange::ok("UID EXPUNGE 3,5", vec![]),
         ])
         .await;
         let base = test_base();
@@ -352,19 +373,20 @@ mod tests {
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Deduplication",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = FindDups::process(&mut imap, &mut renderer, "INBOX", false).await;
+        let result = FindDups::process(&mut imap, renderer.as_mut(), "INBOX", false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @r#"
-        Mailbox,Dups,Sequence
-        INBOX,2,"3,5"
+        Mailbox,Dups,Destination,Sequence
+        INBOX,2,expunged,"3,5"
         "#);
     }
 
@@ -381,17 +403,18 @@ mod tests {
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Deduplication",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = FindDups::process(&mut imap, &mut renderer, "INBOX", false).await;
+        let result = FindDups::process(&mut imap, renderer.as_mut(), "INBOX", false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
-        assert_snapshot!(renderer.output(), @"Mailbox,Dups,Sequence");
+        assert_snapshot!(renderer.output(), @"Mailbox,Dups,Destination,Sequence");
     }
 
     #[tokio::test]
@@ -419,25 +442,26 @@ mod tests {
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Deduplication",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = FindDups::process(&mut imap, &mut renderer, "INBOX", true).await;
+        let result = FindDups::process(&mut imap, renderer.as_mut(), "INBOX", true).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
-        Mailbox,Dups,Sequence
-        INBOX,1,3
+        Mailbox,Dups,Destination,Sequence
+        INBOX,1,expunged,3
         ");
     }
 
     #[tokio::test]
     async fn process_destructive_deletes_duplicates() {
-        // Same as dry_run test but with dry_run=false: expects SELECT + UID STORE + CLOSE
+        // Same as dry_run test but with dry_run=false: expects SELECT + UID STORE + UID EXPUNGE
         let server = MockServer::start(&[], vec![
             // EXAMINE → 3 messages
             MockExchange::ok("EXAMINE \"INBOX\"", vec![
@@ -460,8 +484,8 @@ mod tests {
             ]),
             // UID STORE +FLAGS (\Deleted)
             MockExchange::ok("UID STORE 3 +FLAGS (\\Deleted)", vec![]),
-            // CLOSE (expunge)
-            MockExchange::ok("CLOSE", vec![]),
+            // UID EXPUNGE only the duplicate
+            MockExchange::ok("UID EXPUNGE 3", vec![]),
         ])
         .await;
         let base = test_base();
@@ -469,19 +493,20 @@ mod tests {
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Deduplication",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = FindDups::process(&mut imap, &mut renderer, "INBOX", false).await;
+        let result = FindDups::process(&mut imap, renderer.as_mut(), "INBOX", false).await;
         let _ = imap.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
-        Mailbox,Dups,Sequence
-        INBOX,1,3
+        Mailbox,Dups,Destination,Sequence
+        INBOX,1,expunged,3
         ");
     }
 }
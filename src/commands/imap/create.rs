@@ -1,7 +1,11 @@
 use clap::Args;
 use exn::{Result, ResultExt as _};
 
-use crate::libs::{args, base_config::BaseConfig, imap::Imap};
+use crate::libs::{
+    args,
+    base_config::BaseConfig,
+    imap::{Imap, refusal},
+};
 
 #[derive(Debug, derive_more::Display)]
 pub enum ImapCreateCommandError {
@@ -27,7 +31,7 @@ impl std::error::Error for ImapCreateCommandError {}
 )]
 pub struct Create {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 
     /// The mailbox to create
     mailbox: String,
@@ -67,19 +71,19 @@ impl Create {
     ) -> Result<(), ImapCreateCommandError> {
         let mailbox = &self.mailbox;
 
-        match imap.session.create(mailbox).await {
+        match imap.create(mailbox).await {
             Ok(()) => writeln!(out, "The mailbox {mailbox} has been created")
                 .or_raise(|| ImapCreateCommandError::WriteOutput)?,
-            Err(async_imap::error::Error::No(reason))
-                if reason.contains("Mailbox already exist") =>
-            {
-                writeln!(out, "Cannot create {mailbox:?}, it already exist: {reason}")
-                    .or_raise(|| ImapCreateCommandError::WriteOutput)?;
-            },
-            Err(e) => {
-                return Err(e).or_raise(|| ImapCreateCommandError::ImapCreate {
-                    mailbox: mailbox.clone(),
-                });
+            Err(err) => match refusal(err.frame()) {
+                Some(reason) if reason.contains("Mailbox already exist") => {
+                    writeln!(out, "Cannot create {mailbox:?}, it already exist: {reason}")
+                        .or_raise(|| ImapCreateCommandError::WriteOutput)?;
+                },
+                _ => {
+                    return Err(err.raise(ImapCreateCommandError::ImapCreate {
+                        mailbox: mailbox.clone(),
+                    }));
+                },
             },
         }
 
@@ -144,4 +148,30 @@ mod tests {
         let msg = String::from_utf8(output).expect("utf8");
         assert!(msg.contains("already exist"), "unexpected output: {msg:?}");
     }
+
+    #[tokio::test]
+    async fn create_mailbox_says_it_timed_out() {
+        let server =
+            MockServer::start(&[], vec![MockExchange::silent("CREATE \"NewFolder\"")]).await;
+        let mut base = test_base();
+        base.command_timeout = Some(1);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let cmd = Create {
+            config: args::Generic::default(),
+            mailbox: "NewFolder".to_owned(),
+        };
+        let mut output = Vec::<u8>::new();
+        let err = cmd
+            .run(&mut imap, &mut output)
+            .await
+            .expect_err("no answer");
+        drop(imap);
+        server.join().await;
+        assert!(
+            format!("{err:?}").contains("Timed out during CREATE"),
+            "unexpected error: {err:?}"
+        );
+    }
 }
@@ -1,7 +1,11 @@
 use clap::Args;
 use exn::{Result, ResultExt as _};
 
-use crate::libs::{args, base_config::BaseConfig, imap::Imap};
+use crate::libs::{
+    args,
+    base_config::BaseConfig,
+    imap::{Imap, refusal},
+};
 
 #[derive(Debug, derive_more::Display)]
 pub enum ImapDeleteCommandError {
@@ -27,7 +31,7 @@ impl std::error::Error for ImapDeleteCommandError {}
 )]
 pub struct Delete {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 
     /// The mailbox to delete
     mailbox: String,
@@ -69,22 +73,22 @@ impl Delete {
     ) -> Result<(), ImapDeleteCommandError> {
         let mailbox = &self.mailbox;
 
-        match imap.session.delete(mailbox).await {
+        match imap.delete(mailbox).await {
             Ok(()) => writeln!(out, "The mailbox {mailbox} has been removed")
                 .or_raise(|| ImapDeleteCommandError::Write)?,
-            Err(async_imap::error::Error::No(reason))
-                if reason.contains("Mailbox doesn't exist") =>
-            {
-                writeln!(
-                    out,
-                    "Cannot remove {mailbox:?}, it does not exist: {reason}"
-                )
-                .or_raise(|| ImapDeleteCommandError::Write)?;
-            },
-            Err(e) => {
-                return Err(e).or_raise(|| ImapDeleteCommandError::ImapDelete {
-                    mailbox: mailbox.clone(),
-                });
+            Err(err) => match refusal(err.frame()) {
+                Some(reason) if reason.contains("Mailbox doesn't exist") => {
+                    writeln!(
+                        out,
+                        "Cannot remove {mailbox:?}, it does not exist: {reason}"
+                    )
+                    .or_raise(|| ImapDeleteCommandError::Write)?;
+                },
+                _ => {
+                    return Err(err.raise(ImapDeleteCommandError::ImapDelete {
+                        mailbox: mailbox.clone(),
+                    }));
+                },
             },
         }
 
@@ -1,7 +1,8 @@
+use std::collections::BTreeMap;
+
 use async_imap::imap_proto::NameAttribute;
 use clap::Args;
 use exn::{Result, ResultExt as _};
-use futures::TryStreamExt as _;
 use indicatif::{ProgressBar, ProgressStyle};
 use regex::Regex;
 use size::Size;
@@ -9,8 +10,9 @@ use size::Size;
 use crate::libs::{
     args,
     base_config::BaseConfig,
-    imap::Imap,
-    render::{Renderer, new_renderer},
+    imap::{Imap, ListResult},
+    pool::ImapPool,
+    render::{Renderer, Run, new_renderer},
 };
 
 #[derive(Debug, derive_more::Display)]
@@ -30,8 +32,6 @@ pub enum ImapDuCommandError {
         reference: Option<String>,
         pattern: Option<String>,
     },
-    #[display("Streaming LIST results")]
-    ImapListStream,
     #[display("Parsing message length {len}")]
     ParseU64 { len: usize },
     #[display("Building progress bar style")]
@@ -40,8 +40,6 @@ pub enum ImapDuCommandError {
     ImapExamine { mailbox: String },
     #[display("Fetching message sizes by UID")]
     ImapUidFetch,
-    #[display("Streaming UID FETCH results")]
-    ImapUidFetchStream,
     #[display("Adding renderer row")]
     RendererAddRow,
 }
@@ -67,7 +65,7 @@ pub enum Sort {
 )]
 pub struct DiskUsage {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 
     /// Only include folder paths matching this re
     #[arg(long)]
@@ -102,16 +100,17 @@ impl DiskUsage {
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), ImapDuCommandError> {
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), ImapDuCommandError> {
         let config = BaseConfig::new(&self.config).or_raise(|| ImapDuCommandError::Config)?;
         #[cfg(feature = "tracing")]
         tracing::trace!(?config);
 
-        let mut imap: Imap<()> = Imap::connect_base(&config)
+        let pool: ImapPool<()> = ImapPool::connect_base(&config)
             .await
             .or_raise(|| ImapDuCommandError::Connect)?;
 
         let mut renderer = new_renderer(
+            run,
             config.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
@@ -119,42 +118,35 @@ impl DiskUsage {
         )
         .or_raise(|| ImapDuCommandError::NewRenderer)?;
 
-        self.run(&mut imap, &mut renderer)
+        self.run(&pool, &mut renderer)
             .await
             .or_raise(|| ImapDuCommandError::Run)?;
 
-        imap.close().await.or_raise(|| ImapDuCommandError::Close)?;
+        pool.close().await.or_raise(|| ImapDuCommandError::Close)?;
 
         Ok(())
     }
 
     #[cfg_attr(
         feature = "tracing",
-        tracing::instrument(level = "trace", skip(self, imap, renderer), err(level = "debug"))
+        tracing::instrument(level = "trace", skip(self, pool, renderer), err(level = "debug"))
     )]
     async fn run(
         &self,
-        imap: &mut Imap<()>,
+        pool: &ImapPool<()>,
         renderer: &mut Box<dyn Renderer<RENDERER_LEN> + Send>,
     ) -> Result<(), ImapDuCommandError> {
         let mut result: Vec<(String, u64)> = vec![];
 
-        let names: Vec<_> = {
-            let stream = imap
-                .session
-                .list(self.reference.as_deref(), self.pattern.as_deref())
-                .await
-                .or_raise(|| ImapDuCommandError::ImapList {
-                    reference: self.reference.clone(),
-                    pattern: self.pattern.clone(),
-                })?;
-            stream
-                .try_collect()
-                .await
-                .or_raise(|| ImapDuCommandError::ImapListStream)?
-        };
+        let names = pool
+            .list_names(self.reference.as_deref(), self.pattern.as_deref())
+            .await
+            .or_raise(|| ImapDuCommandError::ImapList {
+                reference: self.reference.clone(),
+                pattern: self.pattern.clone(),
+            })?;
 
-        let mailboxes: Vec<_> = names
+        let mailboxes: BTreeMap<_, _> = names
             .iter()
             // Filter out folders that are marked as NoSelect, which are not mailboxes, only folders
             .filter(|mbx| !mbx.attributes().contains(&NameAttribute::NoSelect))
@@ -176,6 +168,7 @@ impl DiskUsage {
                     self.exclude_re.iter().all(|re| !re.is_match(mbx.name()))
                 }
             })
+            .map(|mbx| (mbx.name().to_owned(), ListResult { extra: None }))
             .collect();
 
         let len_mbox =
@@ -194,42 +187,22 @@ impl DiskUsage {
             );
         }
 
-        for mailbox in mailboxes {
-            if let Some(ref b) = bar {
-                b.inc(1);
-                b.set_message(mailbox.name().to_owned());
-            }
-
-            let mbx = imap.session.examine(mailbox.name()).await.or_raise(|| {
-                ImapDuCommandError::ImapExamine {
-                    mailbox: mailbox.name().to_owned(),
-                }
-            })?;
-
-            if mbx.exists == 0 {
-                result.push((mailbox.name().to_owned(), 0));
-                continue;
-            }
-
-            let total: u64 = {
-                let mut sum = 0u64;
-                let mut stream = imap
-                    .session
-                    .uid_fetch("1:*", "(RFC822.SIZE)")
-                    .await
-                    .or_raise(|| ImapDuCommandError::ImapUidFetch)?;
-                while let Some(m) = stream
-                    .try_next()
-                    .await
-                    .or_raise(|| ImapDuCommandError::ImapUidFetchStream)?
-                {
-                    sum = sum.saturating_add(u64::from(m.size.unwrap_or(0)));
+        pool.for_each_mailbox(
+            mailboxes,
+            async |imap, mailbox, _| {
+                let total = Self::mailbox_size(imap, &mailbox).await?;
+                Ok((mailbox, total))
+            },
+            |(mailbox, total)| {
+                if let Some(ref b) = bar {
+                    b.inc(1);
+                    b.set_message(mailbox.clone());
                 }
-                sum
-            };
-
-            result.push((mailbox.name().to_owned(), total));
-        }
+                result.push((mailbox, total));
+                Ok(())
+            },
+        )
+        .await?;
 
         if let Some(b) = bar {
             b.finish();
@@ -250,6 +223,32 @@ impl DiskUsage {
 
         Ok(())
     }
+
+    /// The total size of the messages of `mailbox`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(imap), ret, err(level = "debug"))
+    )]
+    async fn mailbox_size(imap: &mut Imap<()>, mailbox: &str) -> Result<u64, ImapDuCommandError> {
+        let mbx = imap
+            .examine(mailbox)
+            .await
+            .or_raise(|| ImapDuCommandError::ImapExamine {
+                mailbox: mailbox.to_owned(),
+            })?;
+        if mbx.exists == 0 {
+            return Ok(0);
+        }
+
+        Ok(imap
+            .uid_fetch("1:*", "(RFC822.SIZE)")
+            .await
+            .or_raise(|| ImapDuCommandError::ImapUidFetch)?
+            .iter()
+            .fold(0_u64, |sum, m| {
+                sum.saturating_add(u64::from(m.size.unwrap_or(0)))
+            }))
+    }
 }
 
 #[cfg(test)]
@@ -296,20 +295,23 @@ mod tests {
         ])
         .await;
         let base = test_base();
-        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
+        let pool = ImapPool::of(
+            Imap::connect_base_on_port(&base, server.port)
+                .await
+                .expect("connect"),
+        );
         let mut cmd = default_du();
         cmd.include_re = vec![regex::Regex::new("^INBOX$").expect("valid regex")];
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = cmd.run(&mut imap, &mut renderer).await;
-        let _ = imap.close().await;
+        let result = cmd.run(&pool, &mut renderer).await;
+        let _ = pool.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
@@ -334,20 +336,23 @@ mod tests {
         ])
         .await;
         let base = test_base();
-        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
+        let pool = ImapPool::of(
+            Imap::connect_base_on_port(&base, server.port)
+                .await
+                .expect("connect"),
+        );
         let mut cmd = default_du();
         cmd.exclude_re = vec![regex::Regex::new("^Sent$").expect("valid regex")];
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = cmd.run(&mut imap, &mut renderer).await;
-        let _ = imap.close().await;
+        let result = cmd.run(&pool, &mut renderer).await;
+        let _ = pool.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
@@ -370,19 +375,22 @@ mod tests {
         ])
         .await;
         let base = test_base();
-        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
+        let pool = ImapPool::of(
+            Imap::connect_base_on_port(&base, server.port)
+                .await
+                .expect("connect"),
+        );
         let cmd = default_du();
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = cmd.run(&mut imap, &mut renderer).await;
-        let _ = imap.close().await;
+        let result = cmd.run(&pool, &mut renderer).await;
+        let _ = pool.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
@@ -410,19 +418,22 @@ mod tests {
         ])
         .await;
         let base = test_base();
-        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
+        let pool = ImapPool::of(
+            Imap::connect_base_on_port(&base, server.port)
+                .await
+                .expect("connect"),
+        );
         let cmd = default_du();
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = cmd.run(&mut imap, &mut renderer).await;
-        let _ = imap.close().await;
+        let result = cmd.run(&pool, &mut renderer).await;
+        let _ = pool.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
@@ -480,20 +491,23 @@ mod tests {
         )
         .await;
         let base = test_base();
-        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
+        let pool = ImapPool::of(
+            Imap::connect_base_on_port(&base, server.port)
+                .await
+                .expect("connect"),
+        );
         let mut cmd = default_du();
         cmd.sort = sort;
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = cmd.run(&mut imap, &mut renderer).await;
-        let _ = imap.close().await;
+        let result = cmd.run(&pool, &mut renderer).await;
+        let _ = pool.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(
@@ -519,19 +533,22 @@ mod tests {
         ])
         .await;
         let base = test_base();
-        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
-            .await
-            .expect("connect");
+        let pool = ImapPool::of(
+            Imap::connect_base_on_port(&base, server.port)
+                .await
+                .expect("connect"),
+        );
         let cmd = default_du();
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox Size",
             RENDERER_FORMAT,
             RENDERER_HEADERS,
         )
         .expect("renderer");
-        let result = cmd.run(&mut imap, &mut renderer).await;
-        let _ = imap.close().await;
+        let result = cmd.run(&pool, &mut renderer).await;
+        let _ = pool.close().await;
         server.join().await;
         assert!(result.is_ok(), "expected Ok, got: {result:?}");
         assert_snapshot!(renderer.output(), @"
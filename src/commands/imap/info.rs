@@ -0,0 +1,335 @@
+use std::fmt::Display;
+
+use async_imap::types::QuotaResourceName;
+use clap::Args;
+use exn::{Result, ResultExt as _};
+
+use crate::libs::{
+    args,
+    base_config::BaseConfig,
+    imap::{Handshake, Imap},
+    render::{Renderer, Run, new_renderer},
+};
+
+#[derive(Debug, derive_more::Display)]
+pub enum ImapInfoCommandError {
+    #[display("Loading configuration")]
+    Config,
+    #[display("Connecting to IMAP server")]
+    Connect,
+    #[display("Creating renderer")]
+    NewRenderer,
+    #[display("Running info command")]
+    Run,
+    #[display("Closing IMAP session")]
+    ImapClose,
+    #[display("Querying IMAP capabilities")]
+    ImapCapabilities,
+    #[display("Adding renderer row")]
+    RendererAddRow,
+}
+impl std::error::Error for ImapInfoCommandError {}
+
+#[derive(Args, Debug, Clone)]
+#[command(
+    about = "Show what the server offers",
+    long_about = "This command connects and logs in like the other commands, and shows what
+was learned on the way: the greeting, the TLS version, cipher and
+certificates, the capabilities before and after logging in and the
+authentication mechanism used.
+
+Once logged in, it also shows the NAMESPACE and ID answers, the quota of
+INBOX and the hierarchy delimiter, when the server supports them.
+
+When logging in fails, what was learned before is still shown."
+)]
+pub struct Info {
+    #[clap(flatten)]
+    pub(super) config: args::Generic,
+}
+
+static RENDERER_LEN: usize = 2;
+static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<28", ""];
+static RENDERER_HEADERS: &[&str; RENDERER_LEN] = &["Item", "Value"];
+
+type MyRenderer = Box<dyn Renderer<RENDERER_LEN> + Send>;
+
+impl Info {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), ImapInfoCommandError> {
+        let config = BaseConfig::new(&self.config).or_raise(|| ImapInfoCommandError::Config)?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?config);
+
+        let mut renderer = new_renderer(
+            run,
+            config.renderer,
+            "Server Info",
+            RENDERER_FORMAT,
+            RENDERER_HEADERS,
+        )
+        .or_raise(|| ImapInfoCommandError::NewRenderer)?;
+
+        let mut handshake = Handshake {
+            diagnose: true,
+            ..Handshake::default()
+        };
+        let connected = Imap::<()>::connect_diagnose(&config, &mut handshake).await;
+        render_handshake(&handshake, &mut renderer)?;
+        let mut imap = connected.or_raise(|| ImapInfoCommandError::Connect)?;
+
+        render_info(&mut imap, &mut renderer)
+            .await
+            .or_raise(|| ImapInfoCommandError::Run)?;
+        imap.close()
+            .await
+            .or_raise(|| ImapInfoCommandError::ImapClose)?;
+        Ok(())
+    }
+}
+
+/// Add a row to the `renderer`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(renderer, value), err(level = "debug"))
+)]
+fn add_row(
+    renderer: &mut MyRenderer,
+    item: &str,
+    value: &dyn Display,
+) -> Result<(), ImapInfoCommandError> {
+    renderer
+        .add_row(&[&item, value])
+        .or_raise(|| ImapInfoCommandError::RendererAddRow)
+}
+
+/// Render what was learned while connecting.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(renderer), err(level = "debug"))
+)]
+fn render_handshake(
+    handshake: &Handshake,
+    renderer: &mut MyRenderer,
+) -> Result<(), ImapInfoCommandError> {
+    if let Some(ref greeting) = handshake.greeting {
+        add_row(renderer, "Greeting", greeting)?;
+    }
+    if handshake.tls_version.is_none() && handshake.certificates.is_empty() {
+        add_row(renderer, "TLS", &"none")?;
+    }
+    if let Some(ref version) = handshake.tls_version {
+        add_row(renderer, "TLS version", version)?;
+    }
+    if let Some(ref cipher) = handshake.tls_cipher {
+        add_row(renderer, "TLS cipher", cipher)?;
+    }
+    for certificate in &handshake.certificates {
+        add_row(renderer, "Certificate", certificate)?;
+    }
+    if let Some(ref capabilities) = handshake.capabilities {
+        add_row(
+            renderer,
+            "Capabilities before login",
+            &capabilities.join(" "),
+        )?;
+    }
+    if let Some(mechanism) = handshake.mechanism {
+        add_row(renderer, "Authentication", &mechanism)?;
+    }
+    Ok(())
+}
+
+/// Render what the server tells once logged in. The commands it does not
+/// support are skipped, and those failing are shown as such.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(imap, renderer), err(level = "debug"))
+)]
+async fn render_info(
+    imap: &mut Imap<()>,
+    renderer: &mut MyRenderer,
+) -> Result<(), ImapInfoCommandError> {
+    let mut capabilities = imap
+        .capability_names()
+        .await
+        .or_raise(|| ImapInfoCommandError::ImapCapabilities)?;
+    capabilities.sort();
+    add_row(renderer, "Capabilities", &capabilities.join(" "))?;
+    let supports = |name: &str| capabilities.iter().any(|cap| cap == name);
+
+    let namespace = if supports("NAMESPACE") {
+        match imap.raw_command("NAMESPACE").await {
+            Ok(lines) => lines
+                .iter()
+                .map(|line| line.strip_prefix("* NAMESPACE ").unwrap_or(line))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Err(err) => format!("failed: {err}"),
+        }
+    } else {
+        "not supported".to_owned()
+    };
+    add_row(renderer, "Namespace", &namespace)?;
+
+    let id = if supports("ID") {
+        match imap
+            .id([
+                ("name", Some(env!("CARGO_PKG_NAME"))),
+                ("version", Some(env!("CARGO_PKG_VERSION"))),
+            ])
+            .await
+        {
+            Ok(Some(fields)) => {
+                let mut fields: Vec<_> = fields
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect();
+                fields.sort();
+                fields.join(", ")
+            },
+            Ok(None) => "none".to_owned(),
+            Err(err) => format!("failed: {err}"),
+        }
+    } else {
+        "not supported".to_owned()
+    };
+    add_row(renderer, "ID", &id)?;
+
+    if supports("QUOTA") {
+        render_quota(imap, renderer).await?;
+    } else {
+        add_row(renderer, "Quota roots of INBOX", &"not supported")?;
+    }
+
+    let delimiter = match imap.list_names(None, None).await {
+        Ok(names) => names.first().map_or_else(
+            || "none".to_owned(),
+            |name| {
+                name.delimiter()
+                    .map_or_else(|| "none".to_owned(), |delimiter| format!("{delimiter:?}"))
+            },
+        ),
+        Err(err) => format!("failed: {err}"),
+    };
+    add_row(renderer, "Hierarchy delimiter", &delimiter)?;
+
+    Ok(())
+}
+
+/// Render the quota roots of INBOX and their usage.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(imap, renderer), err(level = "debug"))
+)]
+async fn render_quota(
+    imap: &mut Imap<()>,
+    renderer: &mut MyRenderer,
+) -> Result<(), ImapInfoCommandError> {
+    match imap.quota_root("INBOX").await {
+        Ok((roots, quotas)) => {
+            let names: Vec<_> = roots
+                .iter()
+                .flat_map(|root| root.quota_root_names.iter().map(|name| format!("{name:?}")))
+                .collect();
+            add_row(renderer, "Quota roots of INBOX", &names.join(" "))?;
+            for quota in quotas {
+                let resources: Vec<_> = quota
+                    .resources
+                    .iter()
+                    .map(|resource| {
+                        format!(
+                            "{} {}/{} ({}%)",
+                            match resource.name {
+                                QuotaResourceName::Storage => "STORAGE",
+                                QuotaResourceName::Message => "MESSAGE",
+                                QuotaResourceName::Atom(ref atom) => atom,
+                            },
+                            resource.usage,
+                            resource.limit,
+                            resource.get_usage_percentage()
+                        )
+                    })
+                    .collect();
+                add_row(
+                    renderer,
+                    &format!("Quota {:?}", quota.root_name),
+                    &resources.join(", "),
+                )?;
+            }
+        },
+        Err(err) => add_row(renderer, "Quota roots of INBOX", &format!("failed: {err}"))?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+
+    use super::*;
+    use crate::test_helpers::{MockExchange, MockServer, test_base};
+
+    #[tokio::test]
+    async fn info_shows_handshake_and_server_details() {
+        let server = MockServer::start(&["NAMESPACE", "ID", "QUOTA", "AUTH=PLAIN"], vec![
+            MockExchange::ok("NAMESPACE", vec![
+                "* NAMESPACE ((\"\" \"/\")) NIL ((\"Shared/\" \"/\"))\r\n".into(),
+            ]),
+            MockExchange::ok(r#"/^ID \("name" "imap-tools" "version" "[^"]+"\)$/"#, vec![
+                "* ID (\"name\" \"Mock\" \"version\" \"1.0\")\r\n".into(),
+            ]),
+            MockExchange::ok("GETQUOTAROOT \"INBOX\"", vec![
+                "* QUOTAROOT INBOX \"User quota\"\r\n".into(),
+                "* QUOTA \"User quota\" (STORAGE 512 2048)\r\n".into(),
+            ]),
+            MockExchange::ok("LIST \"\" \"\"", vec![
+                "* LIST (\\Noselect) \".\" \"\"\r\n".into(),
+            ]),
+        ])
+        .await;
+        let mut base = test_base();
+        base.port = Some(server.port);
+        base.mode = Some("plaintext".parse().expect("plaintext is a valid mode"));
+        base.allow_plaintext_auth = true;
+
+        let mut handshake = Handshake {
+            diagnose: true,
+            ..Handshake::default()
+        };
+        let mut imap = Imap::<()>::connect_diagnose(&base, &mut handshake)
+            .await
+            .expect("connect");
+        let mut renderer = new_renderer(
+            None,
+            base.renderer,
+            "Server Info",
+            RENDERER_FORMAT,
+            RENDERER_HEADERS,
+        )
+        .expect("renderer");
+        render_handshake(&handshake, &mut renderer).expect("handshake");
+        let result = render_info(&mut imap, &mut renderer).await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+        assert_snapshot!(renderer.output(), @r#"
+        Item,Value
+        Greeting,* OK IMAP4rev1 mock server ready
+        TLS,none
+        Capabilities before login,AUTH=PLAIN ID IMAP4REV1 NAMESPACE QUOTA UIDPLUS
+        Authentication,LOGIN
+        Capabilities,AUTH=PLAIN ID IMAP4REV1 NAMESPACE QUOTA UIDPLUS
+        Namespace,"(("""" ""/"")) NIL ((""Shared/"" ""/""))"
+        ID,"name=Mock, version=1.0"
+        Quota roots of INBOX,"""User quota"""
+        "Quota ""User quota""",STORAGE 512/2048 (25%)
+        Hierarchy delimiter,"""."""
+        "#);
+    }
+}
@@ -1,14 +1,13 @@
 use async_imap::imap_proto::NameAttribute;
 use clap::Args;
 use exn::{Result, ResultExt as _};
-use futures::TryStreamExt as _;
 use regex::Regex;
 
 use crate::libs::{
     args,
     base_config::BaseConfig,
     imap::Imap,
-    render::{Renderer, new_renderer},
+    render::{Renderer, Run, new_renderer},
 };
 
 #[derive(Debug, derive_more::Display)]
@@ -28,8 +27,6 @@ pub enum ImapListCommandError {
         reference: Option<String>,
         pattern: Option<String>,
     },
-    #[display("Collecting LIST stream results")]
-    ImapListCollect,
     #[display("Adding renderer row")]
     RendererAddRow,
 }
@@ -42,7 +39,7 @@ impl std::error::Error for ImapListCommandError {}
 )]
 pub struct List {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 
     /// Only include folder paths matching this re
     #[arg(long)]
@@ -73,7 +70,7 @@ impl List {
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), ImapListCommandError> {
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), ImapListCommandError> {
         let config = BaseConfig::new(&self.config).or_raise(|| ImapListCommandError::Config)?;
         #[cfg(feature = "tracing")]
         tracing::trace!(?config);
@@ -83,6 +80,7 @@ impl List {
             .or_raise(|| ImapListCommandError::Connect)?;
 
         let mut renderer = new_renderer(
+            run,
             config.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -108,20 +106,13 @@ impl List {
         imap: &mut Imap<()>,
         renderer: &mut Box<dyn Renderer<RENDERER_LEN> + Send>,
     ) -> Result<(), ImapListCommandError> {
-        let names: Vec<_> = {
-            let stream = imap
-                .session
-                .list(self.reference.as_deref(), self.pattern.as_deref())
-                .await
-                .or_raise(|| ImapListCommandError::ImapList {
-                    reference: self.reference.clone(),
-                    pattern: self.pattern.clone(),
-                })?;
-            stream
-                .try_collect()
-                .await
-                .or_raise(|| ImapListCommandError::ImapListCollect)?
-        };
+        let names = imap
+            .list_names(self.reference.as_deref(), self.pattern.as_deref())
+            .await
+            .or_raise(|| ImapListCommandError::ImapList {
+                reference: self.reference.clone(),
+                pattern: self.pattern.clone(),
+            })?;
 
         for mailbox in names
             .iter()
@@ -197,6 +188,7 @@ mod tests {
             .expect("connect");
         let cmd = default_list();
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -228,6 +220,7 @@ mod tests {
             .expect("connect");
         let cmd = default_list();
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -258,6 +251,7 @@ mod tests {
         let mut cmd = default_list();
         cmd.no_select = true;
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -290,6 +284,7 @@ mod tests {
         let mut cmd = default_list();
         cmd.include_re = vec![Regex::new("^INBOX$").expect("valid regex")];
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -322,6 +317,7 @@ mod tests {
         let mut cmd = default_list();
         cmd.exclude_re = vec![Regex::new("^Spam").expect("valid regex")];
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
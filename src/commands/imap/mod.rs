@@ -1,8 +1,11 @@
 use clap::Subcommand;
 use exn::{Result, ResultExt as _};
+
+use crate::libs::{args, render::Run};
 mod create;
 mod delete;
 mod disk_usage;
+mod info;
 mod list;
 
 #[derive(Subcommand, Debug, Clone)]
@@ -18,6 +21,8 @@ pub enum ImapCommands {
 
     #[command(aliases = &["du"])]
     DiskUsage(disk_usage::DiskUsage),
+
+    Info(info::Info),
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -30,17 +35,30 @@ pub enum ImapCommandsError {
     Delete,
     #[display("Running imap disk-usage subcommand")]
     DiskUsage,
+    #[display("Running imap info subcommand")]
+    Info,
 }
 impl std::error::Error for ImapCommandsError {}
 
 impl ImapCommands {
+    /// The generic arguments of the subcommand.
+    pub(super) const fn generic_mut(&mut self) -> &mut args::Generic {
+        match *self {
+            Self::List(ref mut list) => &mut list.config,
+            Self::Create(ref mut create) => &mut create.config,
+            Self::Delete(ref mut delete) => &mut delete.config,
+            Self::DiskUsage(ref mut du) => &mut du.config,
+            Self::Info(ref mut info) => &mut info.config,
+        }
+    }
+
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), ImapCommandsError> {
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), ImapCommandsError> {
         match *self {
-            Self::List(ref list) => list.execute().await.or_raise(|| ImapCommandsError::List),
+            Self::List(ref list) => list.execute(run).await.or_raise(|| ImapCommandsError::List),
             Self::Create(ref create) => create
                 .execute()
                 .await
@@ -49,7 +67,11 @@ impl ImapCommands {
                 .execute()
                 .await
                 .or_raise(|| ImapCommandsError::Delete),
-            Self::DiskUsage(ref du) => du.execute().await.or_raise(|| ImapCommandsError::DiskUsage),
+            Self::DiskUsage(ref du) => du
+                .execute(run)
+                .await
+                .or_raise(|| ImapCommandsError::DiskUsage),
+            Self::Info(ref info) => info.execute(run).await.or_raise(|| ImapCommandsError::Info),
         }
     }
 }
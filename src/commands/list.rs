@@ -5,7 +5,7 @@ use crate::libs::{
     args,
     config::Config,
     imap::Imap,
-    render::{Renderer, new_renderer},
+    render::{Renderer, Run, new_renderer},
 };
 
 #[derive(Debug, derive_more::Display)]
@@ -37,7 +37,7 @@ effect on the mailboxes."
 )]
 pub struct List {
     #[clap(flatten)]
-    config: args::Generic,
+    pub(super) config: args::Generic,
 }
 
 type MyExtra = serde_value::Value;
@@ -51,8 +51,8 @@ impl List {
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn execute(&self) -> Result<(), ListError> {
-        let config = Config::<MyExtra>::new(&self.config).or_raise(|| ListError::Config)?;
+    pub async fn execute(&self, run: Option<&Run>) -> Result<(), ListError> {
+        let config = Config::<MyExtra>::new(&self.config, None).or_raise(|| ListError::Config)?;
         #[cfg(feature = "tracing")]
         tracing::trace!(?config);
 
@@ -61,6 +61,7 @@ impl List {
             .or_raise(|| ListError::Connect)?;
 
         let mut renderer = new_renderer(
+            run,
             config.base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -117,6 +118,7 @@ mod tests {
             .await
             .expect("connect");
         let mut renderer = new_renderer(
+            None,
             base.renderer,
             "Mailbox List",
             RENDERER_FORMAT,
@@ -1,7 +1,18 @@
+use std::{fmt::Display, mem};
+
 use clap::Subcommand;
-use exn::{Result, ResultExt as _};
+use exn::{Exn, Frame, Result, ResultExt as _, bail};
+
+use crate::libs::{
+    args,
+    base_config::SecretCache,
+    config_file,
+    render::{Run, SharedRenderers, new_renderer},
+    users,
+};
 mod archive;
 mod clean;
+mod config;
 mod find_dups;
 mod imap;
 mod list;
@@ -22,47 +33,211 @@ pub enum MainCommands {
 
     #[command(subcommand)]
     Imap(imap::ImapCommands),
+
+    #[command(subcommand)]
+    Config(config::ConfigCommands),
 }
 
 #[derive(Debug, derive_more::Display)]
 pub enum MainCommandError {
     #[display("Running {command} command")]
     Command { command: &'static str },
+    #[display("Reading users file")]
+    Users,
+    #[display("Listing the accounts of the configuration file")]
+    Accounts,
+    #[display("Creating renderer")]
+    NewRenderer,
+    #[display("Adding renderer row")]
+    RendererAddRow,
+    #[display("The command failed for {failed} of its {total} runs")]
+    RunsFailed { failed: usize, total: usize },
 }
 
 impl std::error::Error for MainCommandError {}
 
+static RENDERER_LEN: usize = 2;
+static RENDERER_FORMAT: &[&str; RENDERER_LEN] = &[":<30", ""];
+static USER_HEADERS: [&str; RENDERER_LEN] = ["User", "Result"];
+static ACCOUNT_HEADERS: [&str; RENDERER_LEN] = ["Account", "Result"];
+static ACCOUNT_USER_HEADERS: [&str; RENDERER_LEN] = ["Account/User", "Result"];
+
 impl MainCommands {
+    /// The generic arguments of the command, if it has them.
+    const fn generic_mut(&mut self) -> Option<&mut args::Generic> {
+        match *self {
+            Self::Archive(ref mut archive) => Some(&mut archive.config),
+            Self::Clean(ref mut clean) => Some(&mut clean.config),
+            Self::FindDups(ref mut find_dups) => Some(&mut find_dups.config),
+            Self::List(ref mut list) => Some(&mut list.config),
+            Self::Imap(ref mut imap) => Some(imap.generic_mut()),
+            Self::Config(ref mut config) => config.generic_mut(),
+        }
+    }
+
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
     pub async fn execute(&self) -> Result<(), MainCommandError> {
+        let mut command = self.clone();
+        let Some(generic) = command.generic_mut() else {
+            return Box::pin(self.execute_once(None)).await;
+        };
+        let users = generic.users.take();
+        let all_accounts = mem::take(&mut generic.all_accounts);
+        if users.is_none() && !all_accounts {
+            return Box::pin(self.execute_once(None)).await;
+        }
+
+        let accounts = if all_accounts {
+            config_file::accounts(generic.config.as_deref())
+                .or_raise(|| MainCommandError::Accounts)?
+                .into_iter()
+                .map(Some)
+                .collect()
+        } else {
+            vec![None]
+        };
+        let users = if let Some(ref file) = users {
+            users::read(file)
+                .or_raise(|| MainCommandError::Users)?
+                .into_iter()
+                .map(Some)
+                .collect()
+        } else {
+            vec![None]
+        };
+
+        let mut runs = Vec::with_capacity(accounts.len() * users.len());
+        for account in &accounts {
+            // The users of an account log in with the same credentials
+            let secret_cache = SecretCache::default();
+            for user in &users {
+                let mut run = command.clone();
+                if let Some(generic) = run.generic_mut() {
+                    generic.secret_cache = secret_cache.clone();
+                    if let Some(ref account) = *account {
+                        generic.account = Some(account.clone());
+                    }
+                    if let Some(ref user) = *user {
+                        generic.authorize_as = Some(user.clone());
+                    }
+                }
+                let label = [account, user]
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("/");
+                runs.push((label, run));
+            }
+        }
+        let header = match (all_accounts, users.first()) {
+            (true, Some(&Some(_))) => &ACCOUNT_USER_HEADERS,
+            (true, _) => &ACCOUNT_HEADERS,
+            (false, _) => &USER_HEADERS,
+        };
+        Box::pin(Self::execute_runs(runs, header)).await
+    }
+
+    /// Run each command, for several accounts or users, labelled. The rows
+    /// of all the runs go to one renderer, each prefixed by its label,
+    /// followed by a summary of which runs failed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(runs, headers), err(level = "info"))
+    )]
+    async fn execute_runs(
+        runs: Vec<(String, Self)>,
+        headers: &'static [&'static str; RENDERER_LEN],
+    ) -> Result<(), MainCommandError> {
+        let shared = SharedRenderers::default();
+        let mut results = Vec::with_capacity(runs.len());
+        let mut renderer = None;
+        for (label, mut command) in runs {
+            if let Some(generic) = command.generic_mut() {
+                renderer = renderer.or(generic.renderer);
+            }
+            let run = shared.run(label.clone());
+            let result = Box::pin(command.execute_once(Some(&run))).await;
+            #[cfg(feature = "tracing")]
+            if let Err(ref err) = result {
+                tracing::warn!(label, ?err, "command failed");
+            }
+            results.push((label, result.err().map(|err| root_cause(&err))));
+        }
+        // The summary is rendered like the rows, from the first run's config
+        let renderer = shared.renderer().or(renderer);
+        // The rows of the runs are printed when the shared renderer goes
+        drop(shared);
+
+        let mut renderer = new_renderer(None, renderer, "Summary", RENDERER_FORMAT, headers)
+            .or_raise(|| MainCommandError::NewRenderer)?;
+        for result in &results {
+            let (ref label, ref error) = *result;
+            renderer
+                .add_row(&[
+                    label,
+                    error.as_ref().map_or(&"OK" as &dyn Display, |err| err),
+                ])
+                .or_raise(|| MainCommandError::RendererAddRow)?;
+        }
+
+        let failed = results.iter().filter(|result| result.1.is_some()).count();
+        if failed > 0 {
+            bail!(MainCommandError::RunsFailed {
+                failed,
+                total: results.len(),
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    async fn execute_once(&self, run: Option<&Run>) -> Result<(), MainCommandError> {
         match *self {
             Self::Archive(ref archive) => archive
-                .execute()
+                .execute(run)
                 .await
                 .or_raise(|| MainCommandError::Command { command: "archive" }),
             Self::Clean(ref clean) => clean
-                .execute()
+                .execute(run)
                 .await
                 .or_raise(|| MainCommandError::Command { command: "clean" }),
             Self::FindDups(ref find_dups) => {
                 find_dups
-                    .execute()
+                    .execute(run)
                     .await
                     .or_raise(|| MainCommandError::Command {
                         command: "find-dups",
                     })
             },
             Self::List(ref list) => list
-                .execute()
+                .execute(run)
                 .await
                 .or_raise(|| MainCommandError::Command { command: "list" }),
             Self::Imap(ref imap) => imap
-                .execute()
+                .execute(run)
                 .await
                 .or_raise(|| MainCommandError::Command { command: "imap" }),
+            Self::Config(ref config) => config
+                .execute(run)
+                .or_raise(|| MainCommandError::Command { command: "config" }),
         }
     }
 }
+
+/// The message of the error at the bottom of `err`, the most telling one for
+/// a summary.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(err)))]
+fn root_cause(err: &Exn<MainCommandError>) -> String {
+    let mut frame: &Frame = err.frame();
+    while let Some(child) = frame.children().first() {
+        frame = child;
+    }
+    frame.error().to_string()
+}
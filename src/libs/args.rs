@@ -2,14 +2,29 @@ use std::path::PathBuf;
 
 use clap::Args;
 
-use crate::libs::{auth::AuthMethod, mode::Mode, render::RendererArg};
+use crate::libs::{
+    auth::AuthMethod,
+    base_config::SecretCache,
+    mode::{DeleteMode, Mode, TlsPolicy},
+    render::RendererArg,
+};
 
 #[derive(Args, Debug, Clone, Default)]
+#[expect(clippy::struct_excessive_bools, reason = "independent options")]
 pub struct Generic {
-    /// Path to the configuration file.
-    #[arg(short = 'c', long, default_value = ".imap-tools.toml")]
+    /// Path to the configuration file, read alone instead of the system, user
+    /// and .imap-tools.toml ones.
+    #[arg(short = 'c', long, env = "IMAP_TOOLS_CONFIG")]
     pub config: Option<PathBuf>,
 
+    /// Use this account of the configuration file.
+    #[arg(long, env = "IMAP_TOOLS_ACCOUNT", conflicts_with = "all_accounts")]
+    pub account: Option<String>,
+
+    /// Run the command for each account of the configuration file.
+    #[arg(long)]
+    pub all_accounts: bool,
+
     /// The server to connect to.
     #[arg(short = 's', long)]
     pub server: Option<String>,
@@ -30,6 +45,18 @@ pub struct Generic {
     #[arg(short = 'P', long)]
     pub password_command: Option<String>,
 
+    /// The file holding the password.
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+
+    /// The environment variable holding the password.
+    #[arg(long)]
+    pub password_env: Option<String>,
+
+    /// Look the password up in ~/.netrc or ~/.authinfo, by server and username.
+    #[arg(long)]
+    pub password_netrc: bool,
+
     #[cfg_attr(feature = "__tls", doc = "Select the TLS mode")]
     #[cfg_attr(
         not(feature = "__tls"),
@@ -46,13 +73,128 @@ pub struct Generic {
     #[arg(short = 'n', long)]
     pub dry_run: bool,
 
-    /// Authentication method (login or xoauth2).
+    /// Write the IMAP dialogue to stderr, with the credentials redacted.
+    #[arg(short = 'd', long)]
+    pub debug: bool,
+
+    /// Write the IMAP dialogue to this file instead of stderr.
+    #[arg(long)]
+    pub debug_file: Option<PathBuf>,
+
+    /// Authentication method, or auto to pick the best one the server offers.
     #[arg(long, value_enum)]
     pub auth: Option<AuthMethod>,
 
+    /// Log in with the username credentials but act as this user, with the
+    /// PLAIN authorization identity or the "user*master" LOGIN convention.
+    #[arg(long)]
+    pub authorize_as: Option<String>,
+
+    /// Run the command for each user of this file, a CSV file whose first
+    /// column is the user, or a TOML file with a `users` list.
+    #[arg(long)]
+    pub users: Option<PathBuf>,
+
+    /// The password and token of the account, shared by its runs for
+    /// several users so that they are only fetched once.
+    #[arg(skip)]
+    pub secret_cache: SecretCache,
+
     /// Command whose stdout is the `OAuth2` access token (required when `--auth xoauth2`).
     #[arg(long)]
     pub oauth2_command: Option<String>,
+
+    /// The file holding the `OAuth2` access token.
+    #[arg(long)]
+    pub oauth2_token_file: Option<PathBuf>,
+
+    /// The environment variable holding the `OAuth2` access token.
+    #[arg(long)]
+    pub oauth2_token_env: Option<String>,
+
+    /// Look the `OAuth2` access token up in ~/.netrc or ~/.authinfo, by server
+    /// and username.
+    #[arg(long)]
+    pub oauth2_token_netrc: bool,
+
+    /// On servers without UIDPLUS, or rejecting UID EXPUNGE, delete messages
+    /// by unflagging the other `\Deleted` ones during a plain EXPUNGE, then
+    /// flagging them again.
+    #[arg(long, alias = "expunge-fallback")]
+    pub uidplus_fallback: bool,
+
+    /// What deleting a message does (default: expunge).
+    #[arg(long, value_enum)]
+    pub delete_mode: Option<DeleteMode>,
+
+    /// The mailbox messages are moved to with the trash delete mode
+    /// (default: the one with the `\Trash` special use).
+    #[arg(long)]
+    pub trash_mailbox: Option<String>,
+
+    /// How many times to reconnect when the connection drops (default: 3, 0 disables).
+    #[arg(long)]
+    pub reconnect_attempts: Option<u32>,
+
+    /// Seconds to wait before the first reconnect, doubled after each failure (default: 1).
+    #[arg(long)]
+    pub reconnect_delay: Option<u64>,
+
+    /// Seconds to connect, set up TLS and log in, 0 disables it (default: 30).
+    #[arg(long)]
+    pub connect_timeout: Option<u64>,
+
+    /// Seconds to wait for the server during a command, 0 disables it (default: 300).
+    #[arg(long)]
+    pub command_timeout: Option<u64>,
+
+    /// Seconds the whole run may last, from the first connection.
+    #[arg(long)]
+    pub total_timeout: Option<u64>,
+
+    /// How many IMAP sessions to open to process mailboxes in parallel (default: 1).
+    #[arg(short = 'j', long)]
+    pub concurrency: Option<usize>,
+
+    /// A PEM file with extra CA certificates to trust for TLS.
+    #[arg(long)]
+    pub tls_ca_file: Option<PathBuf>,
+
+    /// A PEM file with the client certificate for mutual TLS.
+    #[arg(long)]
+    pub tls_client_cert: Option<PathBuf>,
+
+    /// A PEM file with the private key of the client certificate.
+    #[arg(long)]
+    pub tls_client_key: Option<PathBuf>,
+
+    /// Only accept a server certificate with this SHA-256 fingerprint.
+    #[arg(long)]
+    pub tls_pin_sha256: Option<String>,
+
+    /// Do not verify the server certificate at all, for lab servers only.
+    #[arg(long)]
+    pub insecure_skip_verify: bool,
+
+    /// Whether the connection must be encrypted (default: never for
+    /// plaintext mode, require otherwise).
+    #[arg(long, value_enum)]
+    pub tls_policy: Option<TlsPolicy>,
+
+    /// Allow sending a password or token over an unencrypted connection,
+    /// or taking the PREAUTH session of one.
+    #[arg(long)]
+    pub allow_plaintext_auth: bool,
+
+    /// Command speaking IMAP on its stdin and stdout, used instead of
+    /// connecting to the server, e.g. "ssh host /usr/lib/dovecot/imap".
+    #[arg(long)]
+    pub tunnel: Option<String>,
+
+    /// Proxy to connect through, as "socks5://[user:pass@]host[:port]" or
+    /// "http://[user:pass@]host[:port]".
+    #[arg(long)]
+    pub proxy: Option<String>,
 }
 
 #[cfg(test)]
@@ -81,9 +223,9 @@ mod tests {
 
     #[test]
     fn default_config_path() {
-        // No arguments, config should use default value
+        // No arguments, the configuration files are discovered when loading
         let generic = get_generic_from_args(["test"]);
-        assert_eq!(generic.config, Some(PathBuf::from(".imap-tools.toml")));
+        assert_eq!(generic.config, None);
     }
 
     #[test]
@@ -125,6 +267,13 @@ mod tests {
         assert!(generic.dry_run);
     }
 
+    #[test]
+    fn debug_flag() {
+        let generic = get_generic_from_args(["test", "-d", "--debug-file", "imap.log"]);
+        assert!(generic.debug);
+        assert_eq!(generic.debug_file, Some(PathBuf::from("imap.log")));
+    }
+
     #[test]
     fn port_option() {
         let generic = get_generic_from_args(["test", "--port", "993"]);
@@ -0,0 +1,40 @@
+/// SASL EXTERNAL authenticator (RFC 4422) for use with
+/// [`async_imap::Client::authenticate`].
+///
+/// The identity comes from the TLS client certificate, the only thing sent
+/// is the optional authorization identity, empty to act as the certificate
+/// owner.
+pub struct ExternalAuth {
+    /// Authorization identity, may be empty.
+    pub authzid: String,
+}
+
+impl async_imap::Authenticator for ExternalAuth {
+    type Response = Vec<u8>;
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, _challenge), ret)
+    )]
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        self.authzid.clone().into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_imap::Authenticator as _;
+
+    use super::ExternalAuth;
+
+    #[test]
+    fn external_process_format() {
+        let mut auth = ExternalAuth {
+            authzid: String::new(),
+        };
+        assert!(
+            auth.process(&[]).is_empty(),
+            "an empty authzid acts as the certificate owner"
+        );
+    }
+}
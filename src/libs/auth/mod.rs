@@ -1,20 +1,27 @@
 mod cram_md5;
+mod external;
+mod oauth2;
+mod oauthbearer;
 mod plain;
 mod scram;
 mod xoauth2;
 
-use std::str::FromStr;
+use std::{borrow::Cow, collections::HashSet, str::FromStr};
 
 use exn::bail;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Serialize};
 
-pub use self::{cram_md5::CramMd5Auth, plain::PlainAuth, scram::ScramAuth, xoauth2::XOAuth2Auth};
+pub use self::{
+    cram_md5::CramMd5Auth, external::ExternalAuth, oauth2::OAuth2Config,
+    oauthbearer::OAuthBearerAuth, plain::PlainAuth, scram::ScramAuth, xoauth2::XOAuth2Auth,
+};
 
 #[derive(Debug, derive_more::Display)]
 /// Error type for auth-method parsing.
 pub enum AuthError {
     #[display(
-        "Invalid auth method, expects: login, plain, cram-md5, scram-sha-1, scram-sha-256, xoauth2"
+        "Invalid auth method, expects: auto, login, plain, cram-md5, scram-sha-1, scram-sha-256, xoauth2, oauthbearer, external"
     )]
     InvalidMethod,
     #[display("Building SCRAM client configuration")]
@@ -48,6 +55,16 @@ pub enum AuthMethod {
     /// SASL XOAUTH2 — bearer-token authentication (Gmail, Office 365).
     #[value(name = "xoauth2")]
     XOAuth2,
+    /// SASL OAUTHBEARER — standard bearer-token authentication (RFC 7628).
+    #[value(name = "oauthbearer")]
+    OAuthBearer,
+    /// SASL EXTERNAL — identity from the TLS client certificate (RFC 4422).
+    #[value(name = "external")]
+    External,
+    /// The strongest of the above that the server offers, for the
+    /// credentials at hand.
+    #[value(name = "auto")]
+    Auto,
 }
 
 impl AuthMethod {
@@ -56,7 +73,70 @@ impl AuthMethod {
     /// Used in config validation to decide whether `password`/`password-command`
     /// must be set.
     pub const fn requires_password(self) -> bool {
-        !matches!(self, Self::XOAuth2)
+        !matches!(
+            self,
+            Self::XOAuth2 | Self::OAuthBearer | Self::External | Self::Auto
+        )
+    }
+
+    /// Returns `true` for the mechanisms that send the password or token as
+    /// is, which must not go over an unencrypted connection.
+    pub const fn sends_cleartext_credentials(self) -> bool {
+        matches!(
+            self,
+            Self::Login | Self::Plain | Self::XOAuth2 | Self::OAuthBearer
+        )
+    }
+
+    /// The name of the mechanism, as the server knows it.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Login => "LOGIN",
+            Self::Plain => "PLAIN",
+            Self::CramMd5 => "CRAM-MD5",
+            Self::ScramSha1 => "SCRAM-SHA-1",
+            Self::ScramSha256 => "SCRAM-SHA-256",
+            Self::XOAuth2 => "XOAUTH2",
+            Self::OAuthBearer => "OAUTHBEARER",
+            Self::External => "EXTERNAL",
+            Self::Auto => "AUTO",
+        }
+    }
+
+    /// Pick the mechanism for `auto`, from the server `capabilities`
+    /// (upper-case, like `AUTH=PLAIN`).
+    ///
+    /// OAUTHBEARER or XOAUTH2 when there is an `OAuth2` token, else the
+    /// strongest password
+    /// mechanism: SCRAM-SHA-256, SCRAM-SHA-1, CRAM-MD5, PLAIN when the
+    /// password may be sent in `cleartext`, and LOGIN as a last resort.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(capabilities), ret)
+    )]
+    pub fn negotiate(
+        capabilities: &HashSet<String>,
+        oauth2: bool,
+        password: bool,
+        cleartext: bool,
+    ) -> Option<Self> {
+        let offers = |method: Self| capabilities.contains(&format!("AUTH={}", method.name()));
+
+        if oauth2
+            && let Some(method) = [Self::OAuthBearer, Self::XOAuth2]
+                .into_iter()
+                .find(|&method| offers(method))
+        {
+            return Some(method);
+        }
+        if !password {
+            return None;
+        }
+        [Self::ScramSha256, Self::ScramSha1, Self::CramMd5]
+            .into_iter()
+            .find(|&method| offers(method))
+            .or_else(|| (cleartext && offers(Self::Plain)).then_some(Self::Plain))
+            .or_else(|| (!capabilities.contains("LOGINDISABLED")).then_some(Self::Login))
     }
 }
 
@@ -75,6 +155,9 @@ impl FromStr for AuthMethod {
             "scram-sha-1" => Self::ScramSha1,
             "scram-sha-256" => Self::ScramSha256,
             "xoauth2" => Self::XOAuth2,
+            "oauthbearer" => Self::OAuthBearer,
+            "external" => Self::External,
+            "auto" => Self::Auto,
             _ => {
                 bail!(AuthError::InvalidMethod);
             },
@@ -112,13 +195,40 @@ impl Serialize for AuthMethod {
             Self::ScramSha1 => "scram-sha-1",
             Self::ScramSha256 => "scram-sha-256",
             Self::XOAuth2 => "xoauth2",
+            Self::OAuthBearer => "oauthbearer",
+            Self::External => "external",
+            Self::Auto => "auto",
         })
     }
 }
 
+impl JsonSchema for AuthMethod {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        "AuthMethod".into()
+    }
+
+    /// The names taken by the configuration file.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(_generator))
+    )]
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        let names = <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .filter_map(|method| serde_json::to_value(method).ok())
+            .collect::<Vec<_>>();
+        json_schema!({ "type": "string", "enum": names })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![expect(clippy::expect_used, reason = "test")]
+    use std::collections::HashSet;
+
+    use rstest::rstest;
+
     use super::AuthMethod;
 
     #[test]
@@ -236,4 +346,91 @@ mod tests {
     fn auth_method_requires_password_xoauth2_false() {
         assert!(!AuthMethod::XOAuth2.requires_password());
     }
+
+    #[test]
+    fn auth_method_from_str_oauthbearer_and_external() {
+        assert_eq!(
+            "OAUTHBEARER"
+                .parse::<AuthMethod>()
+                .expect("\"OAUTHBEARER\" is a valid auth method"),
+            AuthMethod::OAuthBearer
+        );
+        assert_eq!(
+            "external"
+                .parse::<AuthMethod>()
+                .expect("\"external\" is a valid auth method"),
+            AuthMethod::External
+        );
+    }
+
+    #[test]
+    fn auth_method_serde_round_trip() {
+        for method in [AuthMethod::OAuthBearer, AuthMethod::External] {
+            let value = serde_json::to_value(method).expect("serialize");
+            assert_eq!(
+                serde_json::from_value::<AuthMethod>(value).expect("deserialize"),
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn auth_method_requires_password_oauthbearer_and_external_false() {
+        assert!(!AuthMethod::OAuthBearer.requires_password());
+        assert!(!AuthMethod::External.requires_password());
+    }
+
+    #[test]
+    fn auth_method_auto_requires_no_password() {
+        assert!(!AuthMethod::Auto.requires_password());
+    }
+
+    #[rstest]
+    #[case::scram_sha256(
+        "AUTH=PLAIN AUTH=SCRAM-SHA-1 AUTH=SCRAM-SHA-256",
+        true,
+        Some(AuthMethod::ScramSha256)
+    )]
+    #[case::scram_sha1(
+        "AUTH=PLAIN AUTH=CRAM-MD5 AUTH=SCRAM-SHA-1",
+        true,
+        Some(AuthMethod::ScramSha1)
+    )]
+    #[case::cram_md5("AUTH=PLAIN AUTH=CRAM-MD5", true, Some(AuthMethod::CramMd5))]
+    #[case::plain_over_tls("AUTH=PLAIN", true, Some(AuthMethod::Plain))]
+    #[case::no_plain_in_clear("AUTH=PLAIN", false, Some(AuthMethod::Login))]
+    #[case::login("IMAP4REV1", true, Some(AuthMethod::Login))]
+    #[case::login_disabled("AUTH=PLAIN LOGINDISABLED", false, None)]
+    fn auth_method_negotiate_password(
+        #[case] capabilities: &str,
+        #[case] cleartext: bool,
+        #[case] expected: Option<AuthMethod>,
+    ) {
+        let capabilities: HashSet<String> = capabilities.split(' ').map(str::to_owned).collect();
+        assert_eq!(
+            AuthMethod::negotiate(&capabilities, false, true, cleartext),
+            expected
+        );
+    }
+
+    #[test]
+    fn auth_method_negotiate_oauth2() {
+        let mut capabilities: HashSet<String> = ["AUTH=SCRAM-SHA-256", "AUTH=XOAUTH2"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(
+            AuthMethod::negotiate(&capabilities, true, true, true),
+            Some(AuthMethod::XOAuth2)
+        );
+        capabilities.insert("AUTH=OAUTHBEARER".to_owned());
+        assert_eq!(
+            AuthMethod::negotiate(&capabilities, true, true, true),
+            Some(AuthMethod::OAuthBearer)
+        );
+        assert_eq!(
+            AuthMethod::negotiate(&capabilities, false, false, true),
+            None
+        );
+    }
 }
@@ -0,0 +1,781 @@
+use std::{
+    env,
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use exn::{OptionExt as _, Result, ResultExt as _, bail};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::TcpStream,
+};
+
+use crate::libs::proxy::Proxy;
+
+/// How long before its expiry an access token is refreshed.
+const EXPIRY_MARGIN: i64 = 60;
+
+/// How long the token endpoint may take to connect and answer, on top of
+/// the `connect-timeout` of the IMAP connection it is fetched for.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, derive_more::Display)]
+pub enum OAuth2Error {
+    #[display("oauth2 needs a provider or a token-url")]
+    NoTokenUrl,
+    #[display("Invalid token URL {url}")]
+    Url { url: String },
+    #[display("Token URL {url} needs https support, build with the rustls or native-tls feature")]
+    #[cfg(not(feature = "__tls"))]
+    NoTls { url: String },
+    #[display("Connecting to the token endpoint {url}")]
+    Connect { url: String },
+    #[display("Parsing the proxy URL")]
+    ProxyUrl,
+    #[display("Connecting to proxy {proxy}")]
+    ProxyConnect { proxy: Proxy },
+    #[display("Opening a tunnel to the token endpoint {url} through proxy {proxy}")]
+    ProxyHandshake { url: String, proxy: Proxy },
+    #[display("The token endpoint {url} did not answer within {} seconds", HTTP_TIMEOUT.as_secs())]
+    Timeout { url: String },
+    #[display("Setting up TLS with the token endpoint {url}")]
+    #[cfg(feature = "__tls")]
+    Tls { url: String },
+    #[display("Talking to the token endpoint {url}")]
+    Request { url: String },
+    #[display("Malformed HTTP response from the token endpoint {url}")]
+    Response { url: String },
+    #[display("The token endpoint {url} answered {status}: {body}")]
+    Status {
+        url: String,
+        status: u16,
+        body: String,
+    },
+    #[display("Parsing the answer of the token endpoint {url}")]
+    Json { url: String },
+    #[display("Writing the token cache {file:?}")]
+    CacheWrite { file: PathBuf },
+}
+impl std::error::Error for OAuth2Error {}
+
+/// Well known token endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum OAuth2Provider {
+    Google,
+    Microsoft,
+}
+
+impl OAuth2Provider {
+    /// The token endpoint of the provider.
+    pub const fn token_url(self) -> &'static str {
+        match self {
+            Self::Google => "https://oauth2.googleapis.com/token",
+            Self::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+        }
+    }
+}
+
+/// The `[oauth2]` section: what is needed to trade a refresh token for
+/// access tokens, without any external command.
+#[derive(Clone, Deserialize, Serialize, derive_more::Debug, schemars::JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OAuth2Config {
+    /// Sets the `token-url`.
+    #[serde(default)]
+    pub provider: Option<OAuth2Provider>,
+    /// Overrides the token endpoint of the provider.
+    #[serde(default)]
+    pub token_url: Option<String>,
+    pub client_id: String,
+    #[serde(default)]
+    #[debug(skip)]
+    pub client_secret: Option<String>,
+    #[debug(skip)]
+    pub refresh_token: String,
+    /// Only needed by some providers, space separated.
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Where access tokens are kept between runs, defaults to
+    /// `$XDG_CACHE_HOME/imap-tools/oauth2-<username>.json`.
+    #[serde(default)]
+    pub cache_file: Option<PathBuf>,
+}
+
+/// An access token as kept in the cache file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedToken {
+    /// Which endpoint and client the token is for, a cache written for
+    /// another configuration is ignored.
+    token_url: String,
+    client_id: String,
+    access_token: String,
+    /// Unix time.
+    expires_at: i64,
+    /// Set when the provider rotated the refresh token.
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+/// What the token endpoint answers.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<i64>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+impl OAuth2Config {
+    /// Return a valid access token, from the cache when it does not expire
+    /// soon, else from the token endpoint, reached through `proxy` if set.
+    /// `username` names the default cache file.
+    ///
+    /// # Errors
+    /// Returns an error if the token endpoint cannot be reached or refuses
+    /// the refresh token, or if the cache cannot be written.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn token(&self, username: &str, proxy: Option<&str>) -> Result<String, OAuth2Error> {
+        let token_url = self.token_url()?;
+        let cache_file = self
+            .cache_file
+            .clone()
+            .or_else(|| default_cache_file(username));
+        let cached = cache_file
+            .as_deref()
+            .and_then(read_cache)
+            .filter(|cached| cached.token_url == token_url && cached.client_id == self.client_id);
+
+        let now = chrono::Utc::now().timestamp();
+        if let Some(ref cached) = cached
+            && cached.expires_at - EXPIRY_MARGIN > now
+        {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                expires_at = cached.expires_at,
+                "using the cached access token"
+            );
+            return Ok(cached.access_token.clone());
+        }
+
+        let refresh_token = cached
+            .and_then(|cached| cached.refresh_token)
+            .unwrap_or_else(|| self.refresh_token.clone());
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("client_id", self.client_id.as_str()),
+            ("refresh_token", refresh_token.as_str()),
+        ];
+        if let Some(ref secret) = self.client_secret {
+            form.push(("client_secret", secret));
+        }
+        if let Some(ref scope) = self.scope {
+            form.push(("scope", scope));
+        }
+
+        let (status, body) = post_form(token_url, &form, proxy).await?;
+        if status != 200 {
+            bail!(OAuth2Error::Status {
+                url: token_url.to_owned(),
+                status,
+                body,
+            });
+        }
+        let response: TokenResponse =
+            serde_json::from_str(&body).or_raise(|| OAuth2Error::Json {
+                url: token_url.to_owned(),
+            })?;
+
+        if let Some(file) = cache_file {
+            // Without an expiry, the token is used for this run only
+            let cached = CachedToken {
+                token_url: token_url.to_owned(),
+                client_id: self.client_id.clone(),
+                access_token: response.access_token.clone(),
+                expires_at: response.expires_in.map_or(now, |secs| now + secs),
+                refresh_token: response.refresh_token.or(Some(refresh_token)),
+            };
+            write_cache(&file, &cached).or_raise(|| OAuth2Error::CacheWrite { file })?;
+        }
+
+        Ok(response.access_token)
+    }
+
+    /// The token endpoint, from `token-url` or the provider.
+    fn token_url(&self) -> Result<&str, OAuth2Error> {
+        self.token_url
+            .as_deref()
+            .or_else(|| self.provider.map(OAuth2Provider::token_url))
+            .ok_or_raise(|| OAuth2Error::NoTokenUrl)
+    }
+}
+
+/// `$XDG_CACHE_HOME/imap-tools/oauth2-<username>.json`, or under
+/// `~/.cache` when it is not set.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn default_cache_file(username: &str) -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    let name: String = username
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "@.-_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Some(dir.join("imap-tools").join(format!("oauth2-{name}.json")))
+}
+
+/// Read the cache, a missing or unreadable one is just ignored.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
+fn read_cache(file: &Path) -> Option<CachedToken> {
+    let content = fs::read_to_string(file).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Write the cache, readable by the owner only, replacing the previous one
+/// at once.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(cached), err(level = "debug"))
+)]
+fn write_cache(file: &Path, cached: &CachedToken) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = file.with_extension("tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _};
+
+        options.mode(0o600);
+        let mut out = options.open(&temp)?;
+        out.set_permissions(fs::Permissions::from_mode(0o600))?;
+        out.write_all(serde_json::to_string(cached)?.as_bytes())?;
+    }
+    #[cfg(not(unix))]
+    {
+        let mut out = options.open(&temp)?;
+        out.write_all(serde_json::to_string(cached)?.as_bytes())?;
+    }
+    fs::rename(temp, file)
+}
+
+/// The parts of an `http://` or `https://` URL.
+#[derive(Debug, PartialEq, Eq)]
+struct Url<'a> {
+    https: bool,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn parse_url(url: &str) -> Option<Url<'_>> {
+    let (https, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else {
+        (false, url.strip_prefix("http://")?)
+    };
+    let (authority, path) = rest.find('/').map_or((rest, "/"), |at| rest.split_at(at));
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, if https { 443 } else { 80 }),
+    };
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    (!host.is_empty()).then_some(Url {
+        https,
+        host,
+        port,
+        path,
+    })
+}
+
+/// `application/x-www-form-urlencoded` encoding of one value.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(value)))]
+fn form_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut out, b| {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(char::from(b));
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+        out
+    })
+}
+
+/// A connection to the token endpoint, encrypted or not.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// POST `form` to `url`, through `proxy` if set, and return the status and
+/// body of the answer.
+///
+/// The token is fetched while connecting to the IMAP server, so the
+/// `connect-timeout` bounds this too.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(form), err(level = "debug"))
+)]
+async fn post_form(
+    url: &str,
+    form: &[(&str, &str)],
+    proxy: Option<&str>,
+) -> Result<(u16, String), OAuth2Error> {
+    let parsed = parse_url(url).ok_or_raise(|| OAuth2Error::Url {
+        url: url.to_owned(),
+    })?;
+    let body = form
+        .iter()
+        .map(|&(key, value)| format!("{key}={}", form_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-www-form-urlencoded\r\nAccept: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        parsed.path,
+        parsed.host,
+        body.len()
+    );
+    let raw = tokio::time::timeout(HTTP_TIMEOUT, exchange(&parsed, url, &request, proxy))
+        .await
+        .or_raise(|| OAuth2Error::Timeout {
+            url: url.to_owned(),
+        })??;
+
+    parse_response(&raw).ok_or_raise(|| OAuth2Error::Response {
+        url: url.to_owned(),
+    })
+}
+
+/// Connect to the endpoint of `parsed`, send `request` and read the answer
+/// until the endpoint closes the connection.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(request), err(level = "debug"))
+)]
+async fn exchange(
+    parsed: &Url<'_>,
+    url: &str,
+    request: &str,
+    proxy: Option<&str>,
+) -> Result<Vec<u8>, OAuth2Error> {
+    let tcp = if let Some(proxy) = proxy {
+        let proxy = Proxy::parse(proxy).or_raise(|| OAuth2Error::ProxyUrl)?;
+        let mut tcp = TcpStream::connect((proxy.host.as_str(), proxy.port))
+            .await
+            .or_raise(|| OAuth2Error::ProxyConnect {
+                proxy: proxy.clone(),
+            })?;
+        proxy
+            .handshake(&mut tcp, parsed.host, parsed.port)
+            .await
+            .or_raise(|| OAuth2Error::ProxyHandshake {
+                url: url.to_owned(),
+                proxy: proxy.clone(),
+            })?;
+        tcp
+    } else {
+        TcpStream::connect((parsed.host, parsed.port))
+            .await
+            .or_raise(|| OAuth2Error::Connect {
+                url: url.to_owned(),
+            })?
+    };
+    let mut stream: Box<dyn Connection> = if parsed.https {
+        connect_tls(tcp, parsed.host, url).await?
+    } else {
+        Box::new(tcp)
+    };
+
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .or_raise(|| OAuth2Error::Request {
+            url: url.to_owned(),
+        })?;
+    stream.flush().await.or_raise(|| OAuth2Error::Request {
+        url: url.to_owned(),
+    })?;
+
+    let mut raw = vec![];
+    // Some servers close TLS without a close_notify, what was read is fine
+    if let Err(err) = stream.read_to_end(&mut raw).await
+        && (err.kind() != io::ErrorKind::UnexpectedEof || raw.is_empty())
+    {
+        return Err(err).or_raise(|| OAuth2Error::Request {
+            url: url.to_owned(),
+        });
+    }
+    Ok(raw)
+}
+
+#[cfg(feature = "native-tls")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(tcp), err(level = "debug"))
+)]
+async fn connect_tls(
+    tcp: TcpStream,
+    host: &str,
+    url: &str,
+) -> Result<Box<dyn Connection>, OAuth2Error> {
+    let connector = native_tls::TlsConnector::new().or_raise(|| OAuth2Error::Tls {
+        url: url.to_owned(),
+    })?;
+    let tls = tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, tcp)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))
+        .or_raise(|| OAuth2Error::Tls {
+            url: url.to_owned(),
+        })?;
+    Ok(Box::new(tls))
+}
+
+#[cfg(feature = "rustls")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(tcp), err(level = "debug"))
+)]
+async fn connect_tls(
+    tcp: TcpStream,
+    host: &str,
+    url: &str,
+) -> Result<Box<dyn Connection>, OAuth2Error> {
+    use std::sync::Arc;
+
+    use tokio_rustls::rustls;
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs().certs {
+        // A bad system certificate only matters if the endpoint needs it
+        let _ = roots.add(cert);
+    }
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name =
+        rustls::pki_types::ServerName::try_from(host.to_owned()).or_raise(|| OAuth2Error::Url {
+            url: url.to_owned(),
+        })?;
+    let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(name, tcp)
+        .await
+        .or_raise(|| OAuth2Error::Tls {
+            url: url.to_owned(),
+        })?;
+    Ok(Box::new(tls))
+}
+
+#[cfg(not(feature = "__tls"))]
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(_tcp)))]
+#[cfg_attr(
+    not(feature = "tracing"),
+    expect(clippy::unused_async, reason = "async like with tls")
+)]
+async fn connect_tls(
+    _tcp: TcpStream,
+    _host: &str,
+    url: &str,
+) -> Result<Box<dyn Connection>, OAuth2Error> {
+    bail!(OAuth2Error::NoTls {
+        url: url.to_owned(),
+    })
+}
+
+/// Split `bytes` at the first CRLF.
+fn split_crlf(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let at = bytes.windows(2).position(|w| w == b"\r\n")?;
+    Some((bytes.get(..at)?, bytes.get(at + 2..)?))
+}
+
+/// The status and body of an HTTP/1.1 response, chunked or not.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(raw), ret)
+)]
+fn parse_response(raw: &[u8]) -> Option<(u16, String)> {
+    let (status_line, mut rest) = split_crlf(raw)?;
+    let status = String::from_utf8_lossy(status_line)
+        .split(' ')
+        .nth(1)?
+        .parse()
+        .ok()?;
+
+    let mut chunked = false;
+    loop {
+        let (line, after) = split_crlf(rest)?;
+        rest = after;
+        if line.is_empty() {
+            break;
+        }
+        let line = String::from_utf8_lossy(line).to_ascii_lowercase();
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim() == "transfer-encoding"
+            && value.contains("chunked")
+        {
+            chunked = true;
+        }
+    }
+
+    let body = if chunked {
+        let mut body = vec![];
+        loop {
+            let (size, after) = split_crlf(rest)?;
+            let size = String::from_utf8_lossy(size);
+            let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(after.get(..size)?);
+            rest = after.get(size..)?.strip_prefix(b"\r\n")?;
+        }
+        body
+    } else {
+        rest.to_vec()
+    };
+    Some((status, String::from_utf8_lossy(&body).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use std::{
+        io::{BufRead as _, BufReader, Read as _},
+        net::TcpListener,
+        thread,
+    };
+
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    /// A token endpoint answering each connection with the next of
+    /// `answers`, returning the requests it got. It also acts as an HTTP
+    /// proxy tunnelling to itself, the CONNECT lines being returned too.
+    fn token_endpoint(answers: Vec<String>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let url = format!(
+            "http://127.0.0.1:{}/token",
+            listener.local_addr().expect("addr").port()
+        );
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for answer in answers {
+                let (stream, _) = listener.accept().expect("accept");
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                let mut connect = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).expect("read header");
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().expect("length");
+                    }
+                    if line.starts_with("CONNECT ") {
+                        connect = Some(line.trim_end().to_owned());
+                    }
+                    if line == "\r\n" {
+                        let Some(connect) = connect.take() else {
+                            break;
+                        };
+                        requests.push(connect);
+                        reader
+                            .get_mut()
+                            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                            .expect("write established");
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).expect("read body");
+                requests.push(String::from_utf8(body).expect("utf-8"));
+                reader
+                    .get_mut()
+                    .write_all(answer.as_bytes())
+                    .expect("write answer");
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn config(url: &str, cache_file: PathBuf) -> OAuth2Config {
+        OAuth2Config {
+            provider: None,
+            token_url: Some(url.to_owned()),
+            client_id: "client".to_owned(),
+            client_secret: Some("s3cret".to_owned()),
+            refresh_token: "1//refresh".to_owned(),
+            scope: None,
+            cache_file: Some(cache_file),
+        }
+    }
+
+    #[tokio::test]
+    async fn token_is_refreshed_then_cached() {
+        let body = r#"{"access_token":"ya29.first","expires_in":3600,"token_type":"Bearer"}"#;
+        let (url, endpoint) = token_endpoint(vec![format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )]);
+        let dir = tempfile::tempdir().expect("temp dir");
+        let cache_file = dir.path().join("sub").join("token.json");
+        let config = config(&url, cache_file.clone());
+
+        assert_eq!(
+            config.token("alice", None).await.expect("refresh"),
+            "ya29.first"
+        );
+        // The endpoint is gone, this comes from the cache
+        assert_eq!(
+            config.token("alice", None).await.expect("cached"),
+            "ya29.first"
+        );
+
+        let requests = endpoint.join().expect("endpoint");
+        assert_snapshot!(requests.join("\n"), @"grant_type=refresh_token&client_id=client&refresh_token=1%2F%2Frefresh&client_secret=s3cret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let mode = fs::metadata(&cache_file)
+                .expect("cache")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600, "only the owner may read the cache");
+        }
+    }
+
+    #[tokio::test]
+    async fn expiring_token_is_refreshed_with_the_rotated_refresh_token() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let cache_file = dir.path().join("token.json");
+        let (url, endpoint) = token_endpoint(vec![
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1d\r\n{\"access_token\":\"ya29.second\"\r\n1\r\n}\r\n0\r\n\r\n".to_owned(),
+        ]);
+        write_cache(&cache_file, &CachedToken {
+            token_url: url.clone(),
+            client_id: "client".to_owned(),
+            access_token: "ya29.stale".to_owned(),
+            expires_at: chrono::Utc::now().timestamp() + 10,
+            refresh_token: Some("rotated".to_owned()),
+        })
+        .expect("write cache");
+
+        let config = config(&url, cache_file);
+        assert_eq!(
+            config.token("alice", None).await.expect("refresh"),
+            "ya29.second"
+        );
+        let requests = endpoint.join().expect("endpoint");
+        assert!(
+            requests
+                .iter()
+                .all(|request| request.contains("refresh_token=rotated")),
+            "unexpected requests: {requests:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn token_is_fetched_through_the_proxy() {
+        let body = r#"{"access_token":"ya29.proxied"}"#;
+        let (url, endpoint) = token_endpoint(vec![format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )]);
+        let proxy = url.replace("/token", "");
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config = config(
+            "http://imap.example.com/token",
+            dir.path().join("token.json"),
+        );
+
+        assert_eq!(
+            config.token("alice", Some(&proxy)).await.expect("refresh"),
+            "ya29.proxied"
+        );
+        let requests = endpoint.join().expect("endpoint");
+        assert_eq!(
+            requests.first().map(String::as_str),
+            Some("CONNECT imap.example.com:80 HTTP/1.1")
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_refresh_token() {
+        let body =
+            r#"{"error":"invalid_grant","error_description":"Token has been expired or revoked."}"#;
+        let (url, endpoint) = token_endpoint(vec![format!(
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )]);
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config = config(&url, dir.path().join("token.json"));
+
+        let err = config.token("alice", None).await.expect_err("refused");
+        drop(endpoint.join());
+        assert!(
+            err.to_string().ends_with(r#"answered 400: {"error":"invalid_grant","error_description":"Token has been expired or revoked."}"#),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn url_parsing() {
+        assert_eq!(
+            parse_url("https://oauth2.googleapis.com/token"),
+            Some(Url {
+                https: true,
+                host: "oauth2.googleapis.com",
+                port: 443,
+                path: "/token",
+            })
+        );
+        assert_eq!(
+            parse_url("http://[::1]:8080"),
+            Some(Url {
+                https: false,
+                host: "::1",
+                port: 8080,
+                path: "/",
+            })
+        );
+        assert_eq!(parse_url("ftp://example.com/"), None);
+    }
+
+    #[test]
+    fn provider_presets() {
+        let config: OAuth2Config = serde_json::from_str(
+            r#"{"provider": "microsoft", "client-id": "id", "refresh-token": "rt"}"#,
+        )
+        .expect("parse");
+        assert_eq!(
+            config.token_url().expect("token url"),
+            "https://login.microsoftonline.com/common/oauth2/v2.0/token"
+        );
+        assert_eq!(
+            OAuth2Provider::Google.token_url(),
+            "https://oauth2.googleapis.com/token"
+        );
+    }
+}
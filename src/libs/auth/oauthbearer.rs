@@ -0,0 +1,70 @@
+/// SASL OAUTHBEARER authenticator (RFC 7628) for use with
+/// [`async_imap::Client::authenticate`].
+///
+/// Encodes the GS2 header and the bearer token; async-imap handles the
+/// base64 wrapping. When the token is refused the server sends a JSON error
+/// as challenge, which is answered with the dummy `\x01` so that it can
+/// finish with a tagged NO.
+pub struct OAuthBearerAuth {
+    /// IMAP username (typically an email address).
+    pub user: String,
+    /// Server host name.
+    pub host: String,
+    /// Server port.
+    pub port: u16,
+    /// `OAuth2` access token.
+    pub token: String,
+    /// Whether the token was already sent.
+    pub sent: bool,
+}
+
+impl async_imap::Authenticator for OAuthBearerAuth {
+    type Response = Vec<u8>;
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, _challenge))
+    )]
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        if self.sent {
+            return b"\x01".to_vec();
+        }
+        self.sent = true;
+        // `,` and `=` must be escaped in the GS2 authzid (RFC 5801)
+        let user = self.user.replace('=', "=3D").replace(',', "=2C");
+        format!(
+            "n,a={user},\x01host={}\x01port={}\x01auth=Bearer {}\x01\x01",
+            self.host, self.port, self.token
+        )
+        .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_imap::Authenticator as _;
+
+    use super::OAuthBearerAuth;
+
+    #[test]
+    fn oauthbearer_process_format() {
+        let mut auth = OAuthBearerAuth {
+            user: "alice,ops@example.com".to_owned(),
+            host: "imap.example.com".to_owned(),
+            port: 993,
+            token: "ya29.TOKEN".to_owned(),
+            sent: false,
+        };
+        let response = auth.process(&[]);
+        assert_eq!(
+            response,
+            b"n,a=alice=2Cops@example.com,\x01host=imap.example.com\x01port=993\x01auth=Bearer ya29.TOKEN\x01\x01",
+            "SASL OAUTHBEARER format must be exact"
+        );
+        let response = auth.process(br#"{"status":"invalid_token"}"#);
+        assert_eq!(
+            response, b"\x01",
+            "an error challenge gets the dummy answer"
+        );
+    }
+}
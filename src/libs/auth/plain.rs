@@ -2,8 +2,10 @@ use async_imap::Authenticator;
 
 /// SASL PLAIN authenticator for use with [`async_imap::Client::authenticate`].
 ///
-/// Encodes `\0authcid\0passwd`; async-imap handles base64 wrapping.
+/// Encodes `authzid\0authcid\0passwd`; async-imap handles base64 wrapping.
 pub struct PlainAuth {
+    /// User to act as, empty to act as `user`.
+    pub authzid: String,
     /// IMAP username.
     pub user: String,
     /// Password.
@@ -18,8 +20,10 @@ impl Authenticator for PlainAuth {
         tracing::instrument(level = "trace", skip(self, _challenge), ret)
     )]
     fn process(&mut self, _challenge: &[u8]) -> Self::Response {
-        let mut r = Vec::with_capacity(self.user.len() + self.password.len() + 2);
-        r.push(0); // empty authzid
+        let mut r =
+            Vec::with_capacity(self.authzid.len() + self.user.len() + self.password.len() + 2);
+        r.extend_from_slice(self.authzid.as_bytes());
+        r.push(0);
         r.extend_from_slice(self.user.as_bytes());
         r.push(0);
         r.extend_from_slice(self.password.as_bytes());
@@ -36,6 +40,7 @@ mod tests {
     #[test]
     fn plain_process_format() {
         let mut auth = PlainAuth {
+            authzid: String::new(),
             user: "alice@example.com".to_owned(),
             password: "secret".to_owned(),
         };
@@ -45,4 +50,18 @@ mod tests {
             "SASL PLAIN must be NUL-delimited: <empty-authzid> NUL authcid NUL passwd"
         );
     }
+
+    #[test]
+    fn plain_process_with_authzid() {
+        let mut auth = PlainAuth {
+            authzid: "bob@example.com".to_owned(),
+            user: "admin".to_owned(),
+            password: "secret".to_owned(),
+        };
+        assert_eq!(
+            auth.process(&[]),
+            b"bob@example.com\x00admin\x00secret",
+            "the authzid comes first"
+        );
+    }
 }
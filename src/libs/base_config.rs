@@ -1,16 +1,28 @@
 use std::{
+    env,
     fmt::Write as _,
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
     process::{Command, ExitStatus},
+    sync::{Arc, OnceLock},
 };
 
 use exn::{OptionExt as _, Result, ResultExt as _, bail};
+use futures::future::BoxFuture;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use shell_words::split;
 
-use crate::libs::{args::Generic, auth::AuthMethod, mode::Mode, render::RendererArg};
+use crate::libs::{
+    args::Generic,
+    auth::{AuthMethod, OAuth2Config},
+    config_file,
+    mode::{DeleteMode, Mode, TlsPolicy},
+    netrc,
+    render::RendererArg,
+};
 
-#[derive(Debug, derive_more::Display)]
+#[derive(Debug, Clone, derive_more::Display)]
 pub enum CommandType {
     #[display("password")]
     Password,
@@ -20,21 +32,30 @@ pub enum CommandType {
 
 #[derive(Debug, derive_more::Display)]
 pub enum BaseConfigError {
-    #[cfg_attr(not(test), display("Parsing config file {file:?}"))]
-    #[cfg_attr(test, display("Parsing config file"))]
-    ParseFile { file: PathBuf },
+    #[display("Loading configuration")]
+    Load,
     #[display("The server must be set")]
     NoServer,
     #[display("The username must be set")]
     NoUsername,
-    #[display("The password or password command must be set")]
+    #[display(
+        "The password, password-command, password-file, password-env or password-netrc must be set"
+    )]
     NoPassword,
     #[display(
-        "password and password-command must not be set when auth = \"xoauth2\" (use oauth2-command instead)"
+        "password sources must not be set when auth = \"xoauth2\" or \"oauthbearer\" (use oauth2-command or oauth2-token-* instead)"
     )]
     Oauth2Password,
-    #[display("oauth2-command must be set when auth = \"xoauth2\"")]
+    #[display(
+        "oauth2-command, oauth2-token-file, oauth2-token-env, oauth2-token-netrc or an [oauth2] section must be set when auth = \"xoauth2\" or \"oauthbearer\""
+    )]
     Oauth2NoCommand,
+    #[display("Refreshing the OAuth2 access token")]
+    Oauth2Refresh,
+    #[display("tls-client-cert and tls-client-key must be set when auth = \"external\"")]
+    ExternalNoCert,
+    #[display("A password or OAuth2 token source must be set when auth = \"auto\"")]
+    NoCredentials,
     #[display("Parsing {command_type} command {command}")]
     ParsingCommand {
         command_type: CommandType,
@@ -52,13 +73,31 @@ pub enum BaseConfigError {
         stdout: String,
         stderr: String,
     },
+    #[display("tls-client-cert and tls-client-key must be set together")]
+    TlsClientCertKey,
     #[display("{command_type} command output is not valid UTF-8")]
     PasswordCommandOutput { command_type: CommandType },
+    #[display("Reading the {command_type} file {file:?}")]
+    SecretFile {
+        command_type: CommandType,
+        file: PathBuf,
+    },
+    #[display("Reading the {command_type} environment variable {var}")]
+    SecretEnv {
+        command_type: CommandType,
+        var: String,
+    },
+    #[display("No {command_type} for {server:?} and the username in ~/.netrc or ~/.authinfo")]
+    SecretNetrc {
+        command_type: CommandType,
+        server: String,
+    },
 }
 impl std::error::Error for BaseConfigError {}
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
+#[expect(clippy::struct_excessive_bools, reason = "independent options")]
 pub struct BaseConfig {
     pub renderer: Option<RendererArg>,
 
@@ -73,6 +112,15 @@ pub struct BaseConfig {
 
     pub(self) password_command: Option<String>,
 
+    #[serde(default)]
+    pub(self) password_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub(self) password_env: Option<String>,
+
+    #[serde(default)]
+    pub(self) password_netrc: bool,
+
     #[serde(default)]
     pub mode: Option<Mode>,
 
@@ -86,6 +134,157 @@ pub struct BaseConfig {
     pub auth: Option<AuthMethod>,
 
     pub(self) oauth2_command: Option<String>,
+
+    #[serde(default)]
+    pub(self) oauth2_token_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub(self) oauth2_token_env: Option<String>,
+
+    #[serde(default)]
+    pub(self) oauth2_token_netrc: bool,
+
+    #[serde(default)]
+    pub authorize_as: Option<String>,
+
+    #[serde(default)]
+    pub(self) oauth2: Option<OAuth2Config>,
+
+    #[serde(default, alias = "expunge-fallback")]
+    pub uidplus_fallback: bool,
+
+    #[serde(default)]
+    pub delete_mode: Option<DeleteMode>,
+
+    #[serde(default)]
+    pub trash_mailbox: Option<String>,
+
+    #[serde(default)]
+    pub reconnect_attempts: Option<u32>,
+
+    #[serde(default)]
+    pub reconnect_delay: Option<u64>,
+
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+
+    #[serde(default)]
+    pub command_timeout: Option<u64>,
+
+    #[serde(default)]
+    pub total_timeout: Option<u64>,
+
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+
+    #[serde(default)]
+    pub tls_ca_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub tls_client_cert: Option<PathBuf>,
+
+    #[serde(default)]
+    pub tls_client_key: Option<PathBuf>,
+
+    #[serde(default)]
+    pub tls_pin_sha256: Option<String>,
+
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+
+    #[serde(default)]
+    pub tls_policy: Option<TlsPolicy>,
+
+    #[serde(default)]
+    pub allow_plaintext_auth: bool,
+
+    #[serde(default)]
+    pub tunnel: Option<String>,
+
+    #[serde(default)]
+    pub proxy: Option<String>,
+
+    #[serde(default)]
+    pub debug_file: Option<PathBuf>,
+
+    #[serde(skip)]
+    pub(self) secret_cache: SecretCache,
+}
+
+/// The password and `OAuth2` token once they have been computed, shared by
+/// every clone of a `BaseConfig` so that they are only fetched once per
+/// process.
+#[derive(Clone, Default)]
+pub struct SecretCache(Arc<Secrets>);
+
+/// The secrets of a [`SecretCache`].
+#[derive(Default)]
+struct Secrets {
+    password: OnceLock<String>,
+    oauth2: OnceLock<String>,
+}
+
+impl std::fmt::Debug for SecretCache {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretCache").finish_non_exhaustive()
+    }
+}
+
+impl SecretCache {
+    /// Return the cached secret of `command_type`, or compute it with `init`
+    /// and remember it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, init), err(level = "info"))
+    )]
+    fn get_or_try_init(
+        &self,
+        command_type: &CommandType,
+        init: impl FnOnce() -> Result<String, BaseConfigError>,
+    ) -> Result<String, BaseConfigError> {
+        if let Some(secret) = self.get(command_type) {
+            return Ok(secret);
+        }
+        Ok(self.set(command_type, init()?))
+    }
+
+    /// The cached secret of `command_type`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn get(&self, command_type: &CommandType) -> Option<String> {
+        self.cell(command_type).get().cloned()
+    }
+
+    /// Remember `secret`, unless another run was first, and return the
+    /// secret kept.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, secret))
+    )]
+    fn set(&self, command_type: &CommandType, secret: String) -> String {
+        self.cell(command_type).get_or_init(|| secret).clone()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn cell(&self, command_type: &CommandType) -> &OnceLock<String> {
+        match *command_type {
+            CommandType::Password => &self.0.password,
+            CommandType::Oauth2 => &self.0.oauth2,
+        }
+    }
+}
+
+/// Where a password or token comes from.
+#[derive(Debug, Clone, Copy)]
+enum SecretSource<'a> {
+    Literal(&'a str),
+    Command(&'a str),
+    File(&'a Path),
+    Env(&'a str),
+    Netrc,
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -104,15 +303,12 @@ impl BaseConfig {
         #[cfg(feature = "tracing")]
         tracing::trace!(?args);
 
-        let config = if let Some(ref config) = args.config {
-            serde_any::from_file(config)
-                .map_err(SerdeAnyWrapper)
-                .or_raise(|| BaseConfigError::ParseFile {
-                    file: config.clone(),
-                })?
-        } else {
-            Self::default()
-        };
+        // The imap commands share the files of the other commands
+        let config: Self =
+            config_file::load(args.config.as_deref(), args.account.as_deref(), None, &[
+                "extra", "filters",
+            ])
+            .or_raise(|| BaseConfigError::Load)?;
 
         config.apply_args(args)
     }
@@ -121,11 +317,14 @@ impl BaseConfig {
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self, args), ret, err(level = "info"))
     )]
+    #[expect(clippy::too_many_lines, reason = "one block per argument")]
     pub fn apply_args(mut self, args: &Generic) -> Result<Self, BaseConfigError> {
         if let Some(ref server) = args.server {
             self.server = Some(server.clone());
         }
 
+        self.secret_cache = args.secret_cache.clone();
+
         if let Some(port) = args.port {
             self.port = Some(port);
         }
@@ -142,43 +341,96 @@ impl BaseConfig {
             self.password_command = Some(password_command.clone());
         }
 
+        if let Some(ref password_file) = args.password_file {
+            self.password_file = Some(password_file.clone());
+        }
+
+        if let Some(ref password_env) = args.password_env {
+            self.password_env = Some(password_env.clone());
+        }
+
+        if args.password_netrc {
+            self.password_netrc = args.password_netrc;
+        }
+
         if args.dry_run {
             self.dry_run = args.dry_run;
         }
 
+        if args.debug {
+            self.debug = args.debug;
+        }
+
+        if let Some(ref debug_file) = args.debug_file {
+            self.debug_file = Some(debug_file.clone());
+        }
+
         if args.mode.is_some() {
             self.mode.clone_from(&args.mode);
         } else {
             self.mode.get_or_insert_with(Mode::default);
         }
 
-        if self.server.is_none() {
-            bail!(BaseConfigError::NoServer);
+        if let Some(ref tunnel) = args.tunnel {
+            self.tunnel = Some(tunnel.clone());
         }
 
-        if self.username.is_none() {
-            bail!(BaseConfigError::NoUsername);
+        if let Some(ref proxy) = args.proxy {
+            self.proxy = Some(proxy.clone());
+        }
+
+        // Through a tunnel, the server may greet with PREAUTH and need none
+        // of these, they are checked when connecting instead.
+        if self.server.is_none() && self.tunnel.is_none() {
+            bail!(BaseConfigError::NoServer);
         }
 
         if let Some(auth) = args.auth {
             self.auth = Some(auth);
         }
+        let auth = self.auth.unwrap_or_default();
+
+        // With EXTERNAL, the client certificate tells who we are.
+        if self.username.is_none() && self.tunnel.is_none() && auth != AuthMethod::External {
+            bail!(BaseConfigError::NoUsername);
+        }
+
+        if let Some(ref authorize_as) = args.authorize_as {
+            self.authorize_as = Some(authorize_as.clone());
+        }
 
         if let Some(ref oauth2_command) = args.oauth2_command {
             self.oauth2_command = Some(oauth2_command.clone());
         }
 
-        let auth = self.auth.unwrap_or_default();
-        if auth.requires_password() {
-            if self.password.is_none() && self.password_command.is_none() {
+        if let Some(ref oauth2_token_file) = args.oauth2_token_file {
+            self.oauth2_token_file = Some(oauth2_token_file.clone());
+        }
+
+        if let Some(ref oauth2_token_env) = args.oauth2_token_env {
+            self.oauth2_token_env = Some(oauth2_token_env.clone());
+        }
+
+        if args.oauth2_token_netrc {
+            self.oauth2_token_netrc = args.oauth2_token_netrc;
+        }
+
+        if auth == AuthMethod::Auto {
+            if !self.has_password() && !self.has_oauth2() && self.tunnel.is_none() {
+                bail!(BaseConfigError::NoCredentials);
+            }
+        } else if auth.requires_password() {
+            if !self.has_password() && self.tunnel.is_none() {
                 bail!(BaseConfigError::NoPassword);
             }
+        } else if auth == AuthMethod::External {
+            // Checked with the client certificate below
         } else {
-            // XOAuth2
-            if self.password.is_some() || self.password_command.is_some() {
+            // XOAuth2 and OAuthBearer
+            if self.has_password() {
                 bail!(BaseConfigError::Oauth2Password);
             }
-            if self.oauth2_command.is_none() {
+            if !self.has_oauth2() {
                 bail!(BaseConfigError::Oauth2NoCommand);
             }
         }
@@ -187,116 +439,278 @@ impl BaseConfig {
             self.renderer = Some(renderer);
         }
 
+        if args.uidplus_fallback {
+            self.uidplus_fallback = args.uidplus_fallback;
+        }
+
+        if let Some(delete_mode) = args.delete_mode {
+            self.delete_mode = Some(delete_mode);
+        }
+
+        if let Some(ref trash_mailbox) = args.trash_mailbox {
+            self.trash_mailbox = Some(trash_mailbox.clone());
+        }
+
+        if let Some(reconnect_attempts) = args.reconnect_attempts {
+            self.reconnect_attempts = Some(reconnect_attempts);
+        }
+
+        if let Some(reconnect_delay) = args.reconnect_delay {
+            self.reconnect_delay = Some(reconnect_delay);
+        }
+
+        if let Some(connect_timeout) = args.connect_timeout {
+            self.connect_timeout = Some(connect_timeout);
+        }
+
+        if let Some(command_timeout) = args.command_timeout {
+            self.command_timeout = Some(command_timeout);
+        }
+
+        if let Some(total_timeout) = args.total_timeout {
+            self.total_timeout = Some(total_timeout);
+        }
+
+        if let Some(concurrency) = args.concurrency {
+            self.concurrency = Some(concurrency);
+        }
+
+        if let Some(ref tls_ca_file) = args.tls_ca_file {
+            self.tls_ca_file = Some(tls_ca_file.clone());
+        }
+
+        if let Some(ref tls_client_cert) = args.tls_client_cert {
+            self.tls_client_cert = Some(tls_client_cert.clone());
+        }
+
+        if let Some(ref tls_client_key) = args.tls_client_key {
+            self.tls_client_key = Some(tls_client_key.clone());
+        }
+
+        if self.tls_client_cert.is_some() != self.tls_client_key.is_some() {
+            bail!(BaseConfigError::TlsClientCertKey);
+        }
+
+        if auth == AuthMethod::External && self.tls_client_cert.is_none() {
+            bail!(BaseConfigError::ExternalNoCert);
+        }
+
+        if let Some(ref tls_pin_sha256) = args.tls_pin_sha256 {
+            self.tls_pin_sha256 = Some(tls_pin_sha256.clone());
+        }
+
+        if args.insecure_skip_verify {
+            self.insecure_skip_verify = args.insecure_skip_verify;
+        }
+
+        if let Some(tls_policy) = args.tls_policy {
+            self.tls_policy = Some(tls_policy);
+        }
+
+        if args.allow_plaintext_auth {
+            self.allow_plaintext_auth = args.allow_plaintext_auth;
+        }
+
         Ok(self)
     }
 
+    /// Whether a password source is set.
+    pub fn has_password(&self) -> bool {
+        !self.password_sources().is_empty()
+    }
+
+    /// Whether an `OAuth2` token source or refresh token is set.
+    pub fn has_oauth2(&self) -> bool {
+        self.oauth2.is_some() || !self.oauth2_sources().is_empty()
+    }
+
+    /// The password sources that are set, the first one is used.
+    fn password_sources(&self) -> Vec<SecretSource<'_>> {
+        [
+            self.password.as_deref().map(SecretSource::Literal),
+            self.password_command.as_deref().map(SecretSource::Command),
+            self.password_file.as_deref().map(SecretSource::File),
+            self.password_env.as_deref().map(SecretSource::Env),
+            self.password_netrc.then_some(SecretSource::Netrc),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// The `OAuth2` token sources that are set, the first one is used.
+    fn oauth2_sources(&self) -> Vec<SecretSource<'_>> {
+        [
+            self.oauth2_command.as_deref().map(SecretSource::Command),
+            self.oauth2_token_file.as_deref().map(SecretSource::File),
+            self.oauth2_token_env.as_deref().map(SecretSource::Env),
+            self.oauth2_token_netrc.then_some(SecretSource::Netrc),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
     )]
-    /// Figure out the password from literal or command
+    /// Figure out the password from the first source that is set: the
+    /// literal, the command, the file, the environment variable or the
+    /// netrc files. This only happens once.
     /// # Errors
     /// Many errors can happen
     pub fn password(&self) -> Result<String, BaseConfigError> {
-        #[expect(clippy::needless_borrowed_reference, reason = "ok")]
-        match (&self.password, &self.password_command) {
-            (&Some(ref pass), _) => Ok(pass.clone()),
-            (_, &Some(ref command)) => {
-                let command_vec = split(command).or_raise(|| BaseConfigError::ParsingCommand {
-                    command_type: CommandType::Password,
-                    command: command.clone(),
-                })?;
-                let (exe, args) =
-                    command_vec
-                        .split_first()
-                        .ok_or_raise(|| BaseConfigError::CommandEmpty {
-                            command_type: CommandType::Password,
-                        })?;
-                let output = Command::new(exe).args(args).output().or_raise(|| {
-                    BaseConfigError::CommandExec {
-                        command_type: CommandType::Password,
-                    }
-                })?;
+        self.secret_cache
+            .get_or_try_init(&CommandType::Password, || self.fetch_password())
+    }
 
-                if !output.status.success() {
-                    bail!(BaseConfigError::CommandFail {
-                        command_type: CommandType::Password,
-                        command: command.clone(),
-                        status: output.status,
-                        stdout: indent_output("STDOUT: ", &output.stdout),
-                        stderr: indent_output("STDERR: ", &output.stderr),
-                    })
-                }
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    fn fetch_password(&self) -> Result<String, BaseConfigError> {
+        let Some(source) = self.password_sources().first().copied() else {
+            bail!(BaseConfigError::NoPassword)
+        };
+        self.fetch_secret(CommandType::Password, source)
+    }
 
-                let mut password = String::from_utf8(output.stdout).or_raise(|| {
-                    BaseConfigError::PasswordCommandOutput {
-                        command_type: CommandType::Password,
-                    }
-                })?;
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    /// Get the `OAuth2` access token from the `[oauth2]` refresh token, or
+    /// from the first token source that is set, which is also the fallback
+    /// when refreshing fails. This only happens once.
+    ///
+    /// # Errors
+    /// Returns an error if refreshing fails without a source to fall back
+    /// to, or if the source fails.
+    pub async fn oauth2_token(&self) -> Result<String, BaseConfigError> {
+        if let Some(token) = self.secret_cache.get(&CommandType::Oauth2) {
+            return Ok(token);
+        }
+        let token = self.fetch_oauth2_token().await?;
+        Ok(self.secret_cache.set(&CommandType::Oauth2, token))
+    }
 
-                // Strip trailing newline added by most password commands
-                if password.ends_with('\n') {
-                    password.pop();
-                    if password.ends_with('\r') {
-                        password.pop();
-                    }
-                }
-                Ok(password)
-            },
-            _ => bail!(BaseConfigError::NoPassword),
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    async fn fetch_oauth2_token(&self) -> Result<String, BaseConfigError> {
+        let source = self.oauth2_sources().first().copied();
+        if let Some(ref oauth2) = self.oauth2 {
+            // Boxed as dyn, the future is too deep for the compiler to tell
+            // it is Send through the commands awaiting it.
+            let token: BoxFuture<'_, _> = Box::pin(oauth2.token(
+                self.username.as_deref().unwrap_or_default(),
+                self.proxy.as_deref(),
+            ));
+            match token.await {
+                Ok(token) => return Ok(token),
+                Err(err) if source.is_some() => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        ?err,
+                        "refreshing the OAuth2 token failed, using the next token source"
+                    );
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                },
+                Err(err) => return Err(err.raise(BaseConfigError::Oauth2Refresh)),
+            }
         }
+
+        let Some(source) = source else {
+            bail!(BaseConfigError::Oauth2NoCommand)
+        };
+        self.fetch_secret(CommandType::Oauth2, source)
     }
 
+    /// The secret of `command_type` from `source`, without the trailing
+    /// newline of commands and files.
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    /// Execute `oauth2-command` and return its stdout as the `OAuth2` access token.
-    ///
-    /// # Errors
-    /// Returns an error if the command is missing, fails to parse, fails to execute,
-    /// or produces non-UTF-8 output.
-    pub fn oauth2_token(&self) -> Result<String, BaseConfigError> {
-        match self.oauth2_command {
-            None => bail!(BaseConfigError::Oauth2NoCommand),
-            Some(ref command) => {
-                let args = split(command).or_raise(|| BaseConfigError::ParsingCommand {
-                    command_type: CommandType::Oauth2,
-                    command: command.clone(),
+    fn fetch_secret(
+        &self,
+        command_type: CommandType,
+        source: SecretSource<'_>,
+    ) -> Result<String, BaseConfigError> {
+        let secret = match source {
+            SecretSource::Literal(secret) => return Ok(secret.to_owned()),
+            SecretSource::Command(command) => {
+                let command_vec = split(command).or_raise(|| BaseConfigError::ParsingCommand {
+                    command_type: command_type.clone(),
+                    command: command.to_owned(),
                 })?;
                 let (exe, args) =
-                    args.split_first()
+                    command_vec
+                        .split_first()
                         .ok_or_raise(|| BaseConfigError::CommandEmpty {
-                            command_type: CommandType::Oauth2,
+                            command_type: command_type.clone(),
                         })?;
                 let output = Command::new(exe).args(args).output().or_raise(|| {
                     BaseConfigError::CommandExec {
-                        command_type: CommandType::Oauth2,
+                        command_type: command_type.clone(),
                     }
                 })?;
+
                 if !output.status.success() {
                     bail!(BaseConfigError::CommandFail {
-                        command_type: CommandType::Oauth2,
-                        command: command.clone(),
+                        command_type,
+                        command: command.to_owned(),
                         status: output.status,
                         stdout: indent_output("STDOUT: ", &output.stdout),
                         stderr: indent_output("STDERR: ", &output.stderr),
                     })
                 }
-                let mut token = String::from_utf8(output.stdout).or_raise(|| {
+
+                String::from_utf8(output.stdout).or_raise(|| {
                     BaseConfigError::PasswordCommandOutput {
-                        command_type: CommandType::Oauth2,
+                        command_type: command_type.clone(),
                     }
-                })?;
-                if token.ends_with('\n') {
-                    token.pop();
-                    if token.ends_with('\r') {
-                        token.pop();
-                    }
-                }
-                Ok(token)
+                })?
             },
+            SecretSource::File(file) => {
+                fs::read_to_string(file).or_raise(|| BaseConfigError::SecretFile {
+                    command_type: command_type.clone(),
+                    file: file.to_owned(),
+                })?
+            },
+            SecretSource::Env(var) => {
+                return env::var(var).or_raise(|| BaseConfigError::SecretEnv {
+                    command_type,
+                    var: var.to_owned(),
+                });
+            },
+            SecretSource::Netrc => {
+                let server = self.server.as_deref().unwrap_or_default();
+                return netrc::find(
+                    &netrc::files(),
+                    server,
+                    self.port.unwrap_or(143),
+                    self.username.as_deref(),
+                )
+                .ok_or_raise(|| BaseConfigError::SecretNetrc {
+                    command_type,
+                    server: server.to_owned(),
+                });
+            },
+        };
+
+        // Strip the trailing newline of most password commands and files
+        let mut secret = secret;
+        if secret.ends_with('\n') {
+            secret.pop();
+            if secret.ends_with('\r') {
+                secret.pop();
+            }
         }
+        Ok(secret)
     }
 }
 
@@ -347,6 +761,7 @@ mod tests {
     }
 
     #[test]
+    #[expect(clippy::too_many_lines, reason = "big snapshot")]
     fn new_with_args_minimal_config() {
         // Create a minimal args with required fields only
         let args = Generic {
@@ -374,6 +789,9 @@ mod tests {
                     "password123",
                 ),
                 password_command: None,
+                password_file: None,
+                password_env: None,
+                password_netrc: false,
                 mode: Some(
                     AutoTls,
                 ),
@@ -381,6 +799,31 @@ mod tests {
                 dry_run: false,
                 auth: None,
                 oauth2_command: None,
+                oauth2_token_file: None,
+                oauth2_token_env: None,
+                oauth2_token_netrc: false,
+                authorize_as: None,
+                oauth2: None,
+                uidplus_fallback: false,
+                delete_mode: None,
+                trash_mailbox: None,
+                reconnect_attempts: None,
+                reconnect_delay: None,
+                connect_timeout: None,
+                command_timeout: None,
+                total_timeout: None,
+                concurrency: None,
+                tls_ca_file: None,
+                tls_client_cert: None,
+                tls_client_key: None,
+                tls_pin_sha256: None,
+                insecure_skip_verify: false,
+                tls_policy: None,
+                allow_plaintext_auth: false,
+                tunnel: None,
+                proxy: None,
+                debug_file: None,
+                secret_cache: SecretCache { .. },
             }
             "#);
         } else {
@@ -398,6 +841,9 @@ mod tests {
                     "password123",
                 ),
                 password_command: None,
+                password_file: None,
+                password_env: None,
+                password_netrc: false,
                 mode: Some(
                     Plaintext,
                 ),
@@ -405,6 +851,31 @@ mod tests {
                 dry_run: false,
                 auth: None,
                 oauth2_command: None,
+                oauth2_token_file: None,
+                oauth2_token_env: None,
+                oauth2_token_netrc: false,
+                authorize_as: None,
+                oauth2: None,
+                uidplus_fallback: false,
+                delete_mode: None,
+                trash_mailbox: None,
+                reconnect_attempts: None,
+                reconnect_delay: None,
+                connect_timeout: None,
+                command_timeout: None,
+                total_timeout: None,
+                concurrency: None,
+                tls_ca_file: None,
+                tls_client_cert: None,
+                tls_client_key: None,
+                tls_pin_sha256: None,
+                insecure_skip_verify: false,
+                tls_policy: None,
+                allow_plaintext_auth: false,
+                tunnel: None,
+                proxy: None,
+                debug_file: None,
+                secret_cache: SecretCache { .. },
             }
             "#);
         }
@@ -422,11 +893,25 @@ mod tests {
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            The server must be set, at src/libs/base_config.rs:156:13,
+            The server must be set, at src/libs/base_config.rs:385:13,
         )
         ");
     }
 
+    #[test]
+    fn new_with_args_tunnel_needs_no_server_nor_credentials() {
+        let args = Generic {
+            tunnel: Some("ssh host /usr/lib/dovecot/imap".to_owned()),
+            ..Default::default()
+        };
+
+        let config = BaseConfig::new(&args).expect("tunnel alone is enough");
+        assert_eq!(
+            config.tunnel.as_deref(),
+            Some("ssh host /usr/lib/dovecot/imap")
+        );
+    }
+
     #[test]
     fn new_with_args_missing_username_error() {
         let args = Generic {
@@ -439,11 +924,117 @@ mod tests {
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            The username must be set, at src/libs/base_config.rs:160:13,
+            The username must be set, at src/libs/base_config.rs:395:13,
         )
         ");
     }
 
+    #[test]
+    fn new_with_args_auto_auth_needs_credentials() {
+        let args = Generic {
+            server: Some("imap.example.com".to_owned()),
+            username: Some("user@example.com".to_owned()),
+            auth: Some(AuthMethod::Auto),
+            ..Default::default()
+        };
+
+        let result: Result<BaseConfig, BaseConfigError> = BaseConfig::new(&args);
+        assert!(result.is_err());
+        assert_debug_snapshot!(result, @r#"
+        Err(
+            A password or OAuth2 token source must be set when auth = "auto", at src/libs/base_config.rs:420:17,
+        )
+        "#);
+
+        let args = Generic {
+            oauth2_command: Some("oauth2l fetch".to_owned()),
+            ..args
+        };
+        BaseConfig::new(&args).expect("the oauth2-command is enough");
+    }
+
+    #[test]
+    fn new_with_args_external_auth_needs_client_cert() {
+        let args = Generic {
+            server: Some("imap.example.com".to_owned()),
+            auth: Some(AuthMethod::External),
+            ..Default::default()
+        };
+
+        let result: Result<BaseConfig, BaseConfigError> = BaseConfig::new(&args);
+        assert!(result.is_err());
+        assert_debug_snapshot!(result, @r#"
+        Err(
+            tls-client-cert and tls-client-key must be set when auth = "external", at src/libs/base_config.rs:495:13,
+        )
+        "#);
+
+        let args = Generic {
+            tls_client_cert: Some(PathBuf::from("client.pem")),
+            tls_client_key: Some(PathBuf::from("client.key")),
+            ..args
+        };
+        BaseConfig::new(&args).expect("the client certificate is enough");
+    }
+
+    #[test]
+    fn new_with_args_client_cert_without_key_error() {
+        let args = Generic {
+            server: Some("imap.example.com".to_owned()),
+            username: Some("user@example.com".to_owned()),
+            password: Some("password123".to_owned()),
+            tls_client_cert: Some(PathBuf::from("client.pem")),
+            ..Default::default()
+        };
+
+        let result: Result<BaseConfig, BaseConfigError> = BaseConfig::new(&args);
+        assert!(result.is_err());
+        assert_debug_snapshot!(result, @"
+        Err(
+            tls-client-cert and tls-client-key must be set together, at src/libs/base_config.rs:491:13,
+        )
+        ");
+    }
+
+    #[tokio::test]
+    async fn oauth2_section_falls_back_to_command() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = closed.local_addr().expect("addr").port();
+        drop(closed);
+        let dir = tempfile::tempdir().expect("temp dir");
+        let (_config_dir, config_file) = write_temp_config(&format!(
+            r#"
+server = "imap.gmail.com"
+username = "user@gmail.com"
+auth = "xoauth2"
+oauth2-command = "echo from-command"
+
+[oauth2]
+provider = "google"
+token-url = "http://127.0.0.1:{port}/token"
+client-id = "id.apps.googleusercontent.com"
+client-secret = "s3cret"
+refresh-token = "1//refresh"
+cache-file = {:?}
+"#,
+            dir.path().join("token.json")
+        ));
+        let args = Generic {
+            config: Some(config_file),
+            ..Default::default()
+        };
+
+        let config = BaseConfig::new(&args).expect("the [oauth2] section is enough");
+        assert!(
+            !format!("{config:?}").contains("s3cret"),
+            "secrets must not be printed"
+        );
+        assert_eq!(
+            config.oauth2_token().await.expect("fallback token"),
+            "from-command"
+        );
+    }
+
     #[test]
     fn password_fn_command_execution() {
         let args = Generic {
@@ -480,8 +1071,8 @@ mod tests {
         assert!(result.is_err());
         assert_debug_snapshot!(result, @r#"
         Err(
-            Parsing password command echo "secret_password, at src/libs/base_config.rs:205:50
-            `-- missing closing quote, at src/libs/base_config.rs:205:50,
+            Parsing password command echo "secret_password, at src/libs/base_config.rs:646:50
+            `-- missing closing quote, at src/libs/base_config.rs:646:50,
         )
         "#);
     }
@@ -503,8 +1094,8 @@ mod tests {
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            Executing password command, at src/libs/base_config.rs:215:68
-            `-- No such file or directory (os error 2), at src/libs/base_config.rs:215:68,
+            Executing password command, at src/libs/base_config.rs:656:68
+            `-- No such file or directory (os error 2), at src/libs/base_config.rs:656:68,
         )
         ");
     }
@@ -526,7 +1117,7 @@ mod tests {
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            password command is empty, at src/libs/base_config.rs:212:26,
+            password command is empty, at src/libs/base_config.rs:653:26,
         )
         ");
     }
@@ -550,6 +1141,71 @@ mod tests {
         assert_snapshot!(password.trim(), @"secret_password");
     }
 
+    #[tokio::test]
+    async fn password_and_token_from_files_are_cached_apart() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let password_file = dir.path().join("password");
+        let token_file = dir.path().join("token");
+        std::fs::write(&password_file, "file_password\n").expect("write password");
+        std::fs::write(&token_file, "file_token\r\n").expect("write token");
+        let args = Generic {
+            server: Some("imap.example.com".to_owned()),
+            username: Some("user@example.com".to_owned()),
+            auth: Some(AuthMethod::Auto),
+            password_file: Some(password_file),
+            oauth2_token_file: Some(token_file),
+            ..Default::default()
+        };
+
+        let config = BaseConfig::new(&args).expect("files are enough");
+        assert_eq!(config.password().expect("password"), "file_password");
+        assert_eq!(config.oauth2_token().await.expect("token"), "file_token");
+        assert_eq!(config.password().expect("cached password"), "file_password");
+    }
+
+    #[test]
+    fn runs_sharing_a_secret_cache_fetch_the_password_once() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let fetches = dir.path().join("fetches");
+        let mut args = Generic {
+            server: Some("imap.example.com".to_owned()),
+            username: Some("admin@example.com".to_owned()),
+            password_command: Some(format!(
+                "sh -c \"echo fetch >> '{}'; echo admin_password\"",
+                fetches.display()
+            )),
+            ..Default::default()
+        };
+
+        for user in ["alice", "bob"] {
+            args.authorize_as = Some(user.to_owned());
+            let config = BaseConfig::new(&args).expect("password command is enough");
+            assert_eq!(config.password().expect("password"), "admin_password");
+        }
+        assert_eq!(
+            std::fs::read_to_string(&fetches).expect("fetches"),
+            "fetch\n"
+        );
+    }
+
+    #[test]
+    fn password_env_must_be_set() {
+        let args = Generic {
+            server: Some("imap.example.com".to_owned()),
+            username: Some("user@example.com".to_owned()),
+            password_env: Some("IMAP_TOOLS_TEST_UNSET_PASSWORD".to_owned()),
+            ..Default::default()
+        };
+
+        let config = BaseConfig::new(&args).expect("password-env is a password source");
+        assert_debug_snapshot!(config.password(), @"
+        Err(
+            Reading the password environment variable IMAP_TOOLS_TEST_UNSET_PASSWORD, at src/libs/base_config.rs:685:38
+            `-- environment variable not found, at src/libs/base_config.rs:685:38,
+        )
+        ");
+    }
+
     #[test]
     fn password_error_when_missing() {
         let args = Generic {
@@ -563,7 +1219,7 @@ mod tests {
         assert!(config.is_err());
         assert_debug_snapshot!( config, @"
         Err(
-            The password or password command must be set, at src/libs/base_config.rs:174:17,
+            The password, password-command, password-file, password-env or password-netrc must be set, at src/libs/base_config.rs:424:17,
         )
         ");
     }
@@ -587,14 +1243,16 @@ mod tests {
             config,
             @"
         Err(
-            Parsing config file, at src/libs/base_config.rs:110:18
-            `-- TOML deserialize error: newline in string found at line 2, at src/libs/base_config.rs:110:18,
+            Loading configuration, at src/libs/base_config.rs:311:14
+            |-- Reading config file, at src/libs/config_file.rs:203:14
+            `-- TOML deserialize error: newline in string found at line 2, at src/libs/config_file.rs:203:14,
         )
         "
         );
     }
 
     #[test]
+    #[expect(clippy::too_many_lines, reason = "big snapshot")]
     fn config_loading_from_file() {
         let config_content = r#"
         server = "imap.example.com"
@@ -628,6 +1286,9 @@ mod tests {
                     "password123",
                 ),
                 password_command: None,
+                password_file: None,
+                password_env: None,
+                password_netrc: false,
                 mode: Some(
                     AutoTls,
                 ),
@@ -635,6 +1296,31 @@ mod tests {
                 dry_run: true,
                 auth: None,
                 oauth2_command: None,
+                oauth2_token_file: None,
+                oauth2_token_env: None,
+                oauth2_token_netrc: false,
+                authorize_as: None,
+                oauth2: None,
+                uidplus_fallback: false,
+                delete_mode: None,
+                trash_mailbox: None,
+                reconnect_attempts: None,
+                reconnect_delay: None,
+                connect_timeout: None,
+                command_timeout: None,
+                total_timeout: None,
+                concurrency: None,
+                tls_ca_file: None,
+                tls_client_cert: None,
+                tls_client_key: None,
+                tls_pin_sha256: None,
+                insecure_skip_verify: false,
+                tls_policy: None,
+                allow_plaintext_auth: false,
+                tunnel: None,
+                proxy: None,
+                debug_file: None,
+                secret_cache: SecretCache { .. },
             }
             "#);
         } else {
@@ -652,6 +1338,9 @@ mod tests {
                     "password123",
                 ),
                 password_command: None,
+                password_file: None,
+                password_env: None,
+                password_netrc: false,
                 mode: Some(
                     Plaintext,
                 ),
@@ -659,12 +1348,38 @@ mod tests {
                 dry_run: true,
                 auth: None,
                 oauth2_command: None,
+                oauth2_token_file: None,
+                oauth2_token_env: None,
+                oauth2_token_netrc: false,
+                authorize_as: None,
+                oauth2: None,
+                uidplus_fallback: false,
+                delete_mode: None,
+                trash_mailbox: None,
+                reconnect_attempts: None,
+                reconnect_delay: None,
+                connect_timeout: None,
+                command_timeout: None,
+                total_timeout: None,
+                concurrency: None,
+                tls_ca_file: None,
+                tls_client_cert: None,
+                tls_client_key: None,
+                tls_pin_sha256: None,
+                insecure_skip_verify: false,
+                tls_policy: None,
+                allow_plaintext_auth: false,
+                tunnel: None,
+                proxy: None,
+                debug_file: None,
+                secret_cache: SecretCache { .. },
             }
             "#);
         }
     }
 
     #[test]
+    #[expect(clippy::too_many_lines, reason = "big snapshot")]
     fn arg_overrides_file_config() {
         let config_content = r#"
         server = "imap.example.com"
@@ -711,6 +1426,9 @@ mod tests {
                     "override_password",
                 ),
                 password_command: None,
+                password_file: None,
+                password_env: None,
+                password_netrc: false,
                 mode: Some(
                     Tls,
                 ),
@@ -718,6 +1436,31 @@ mod tests {
                 dry_run: true,
                 auth: None,
                 oauth2_command: None,
+                oauth2_token_file: None,
+                oauth2_token_env: None,
+                oauth2_token_netrc: false,
+                authorize_as: None,
+                oauth2: None,
+                uidplus_fallback: false,
+                delete_mode: None,
+                trash_mailbox: None,
+                reconnect_attempts: None,
+                reconnect_delay: None,
+                connect_timeout: None,
+                command_timeout: None,
+                total_timeout: None,
+                concurrency: None,
+                tls_ca_file: None,
+                tls_client_cert: None,
+                tls_client_key: None,
+                tls_pin_sha256: None,
+                insecure_skip_verify: false,
+                tls_policy: None,
+                allow_plaintext_auth: false,
+                tunnel: None,
+                proxy: None,
+                debug_file: None,
+                secret_cache: SecretCache { .. },
             }
             "#);
         } else {
@@ -735,6 +1478,9 @@ mod tests {
                     "override_password",
                 ),
                 password_command: None,
+                password_file: None,
+                password_env: None,
+                password_netrc: false,
                 mode: Some(
                     Plaintext,
                 ),
@@ -742,6 +1488,31 @@ mod tests {
                 dry_run: true,
                 auth: None,
                 oauth2_command: None,
+                oauth2_token_file: None,
+                oauth2_token_env: None,
+                oauth2_token_netrc: false,
+                authorize_as: None,
+                oauth2: None,
+                uidplus_fallback: false,
+                delete_mode: None,
+                trash_mailbox: None,
+                reconnect_attempts: None,
+                reconnect_delay: None,
+                connect_timeout: None,
+                command_timeout: None,
+                total_timeout: None,
+                concurrency: None,
+                tls_ca_file: None,
+                tls_client_cert: None,
+                tls_client_key: None,
+                tls_pin_sha256: None,
+                insecure_skip_verify: false,
+                tls_policy: None,
+                allow_plaintext_auth: false,
+                tunnel: None,
+                proxy: None,
+                debug_file: None,
+                secret_cache: SecretCache { .. },
             }
             "#);
         }
@@ -1,27 +1,28 @@
-use std::{fmt::Debug, path::PathBuf};
+use std::{collections::BTreeMap, fmt::Debug};
 
 use exn::{Result, ResultExt as _};
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_value::Value;
 
 use crate::libs::{
-    args::Generic,
-    base_config::{BaseConfig, SerdeAnyWrapper},
-    filters::Filters,
+    args::Generic, base_config::BaseConfig, config_file, filter::Filter, filters::Filters,
 };
 
 #[derive(Debug, derive_more::Display)]
 pub enum ConfigError {
-    #[cfg_attr(not(test), display("Parsing config file {file:?}"))]
-    #[cfg_attr(test, display("Parsing config file"))]
-    Parsing { file: PathBuf },
+    #[display("Loading configuration")]
+    Load,
     #[display("Applying CLI args to configuration")]
     ApplyArgs,
 }
 
 impl std::error::Error for ConfigError {}
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
+#[schemars(rename = "Config_for_{T}")]
 pub struct Config<T>
 where
     T: Clone + Debug + Serialize + Send + Sync,
@@ -56,22 +57,18 @@ where
         feature = "tracing",
         tracing::instrument(level = "trace", skip(args), ret, err(level = "info"))
     )]
-    /// Creates from a file and arguments
+    /// Creates from a file and arguments, with the `[section]` of the command
+    /// over the shared settings
     /// # Errors
     /// Many errors can happen
-    pub fn new(args: &Generic) -> Result<Self, ConfigError> {
+    pub fn new(args: &Generic, section: Option<&str>) -> Result<Self, ConfigError> {
         #[cfg(feature = "tracing")]
         tracing::trace!(?args);
 
-        let mut config = if let Some(ref config) = args.config {
-            serde_any::from_file(config)
-                .map_err(SerdeAnyWrapper)
-                .or_raise(|| ConfigError::Parsing {
-                    file: config.clone(),
-                })?
-        } else {
-            Self::default()
-        };
+        let mut config: Self =
+            config_file::load(args.config.as_deref(), args.account.as_deref(), section, &[
+            ])
+            .or_raise(|| ConfigError::Load)?;
 
         config.base = config
             .base
@@ -82,6 +79,217 @@ where
     }
 }
 
+/// A problem found in a setting by [`Config::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The setting, like `filters[2].include-re`, empty when the problem is
+    /// with the settings as a whole.
+    pub setting: String,
+    /// Some text of the configuration file close to the problem.
+    pub needle: Option<String>,
+    /// What is wrong.
+    pub message: String,
+}
+
+/// The checks of the `extra` of a command.
+pub trait CheckExtra: DeserializeOwned {
+    /// Whether every mailbox needs an `extra`, from its filter or the top.
+    const REQUIRED: bool = false;
+
+    /// The problems of the `extra` setting `value`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(value), ret)
+    )]
+    fn problems(value: &Value) -> Vec<String> {
+        match value.clone().deserialize_into::<Self>() {
+            Ok(extra) => extra.check(),
+            Err(err) => vec![err.to_string()],
+        }
+    }
+
+    /// The problems of an `extra` that could be read.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn check(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+impl CheckExtra for Value {}
+
+impl<T> Config<T>
+where
+    T: CheckExtra + Clone + Debug + Serialize + Send + Sync,
+{
+    /// Every problem of the `settings` of a command, without stopping at the
+    /// first one like loading them does.
+    ///
+    /// Each setting is checked on its own, each filter and its regexes too,
+    /// then the settings as a whole with the `args`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(settings, args), ret)
+    )]
+    pub fn check(settings: &BTreeMap<String, Value>, args: &Generic) -> Vec<Problem> {
+        let mut problems = vec![];
+        for (key, value) in settings {
+            match key.as_str() {
+                "extra" => {
+                    for message in T::problems(value) {
+                        problems.push(Problem {
+                            setting: key.clone(),
+                            needle: Some(key.clone()),
+                            message,
+                        });
+                    }
+                },
+                "filters" => Self::check_filters(value, &mut problems),
+                _ => {
+                    let single = Value::Map(BTreeMap::from([(
+                        Value::String(key.clone()),
+                        value.clone(),
+                    )]));
+                    if let Err(err) = single.deserialize_into::<BaseConfig>() {
+                        problems.push(Problem {
+                            setting: key.clone(),
+                            needle: Some(key.clone()),
+                            message: err.to_string(),
+                        });
+                    }
+                },
+            }
+        }
+
+        if T::REQUIRED && !settings.contains_key("extra") {
+            let unset = settings
+                .get("filters")
+                .is_none_or(|filters| match *filters {
+                    Value::Seq(ref filters) => {
+                        filters.is_empty()
+                            || filters.iter().any(|filter| match *filter {
+                                Value::Map(ref fields) => {
+                                    !fields.contains_key(&Value::String("extra".to_owned()))
+                                },
+                                _ => false,
+                            })
+                    },
+                    _ => true,
+                });
+            if unset {
+                problems.push(Problem {
+                    setting: "extra".to_owned(),
+                    needle: None,
+                    message: "extra must be set, at the top or in each filter".to_owned(),
+                });
+            }
+        }
+
+        if problems.is_empty() {
+            let table = settings
+                .iter()
+                .map(|(key, value)| (Value::String(key.clone()), value.clone()))
+                .collect();
+            let message = match Value::Map(table).deserialize_into::<Self>() {
+                Ok(config) => config
+                    .base
+                    .apply_args(args)
+                    .err()
+                    .map(|err| err.to_string()),
+                Err(err) => Some(err.to_string()),
+            };
+            if let Some(message) = message {
+                problems.push(Problem {
+                    setting: String::new(),
+                    needle: None,
+                    message,
+                });
+            }
+        }
+        problems
+    }
+
+    /// The problems of each of the `filters`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(filters, problems))
+    )]
+    fn check_filters(filters: &Value, problems: &mut Vec<Problem>) {
+        let Value::Seq(ref filters) = *filters else {
+            problems.push(Problem {
+                setting: "filters".to_owned(),
+                needle: Some("filters".to_owned()),
+                message: "filters must be a list of filters".to_owned(),
+            });
+            return;
+        };
+
+        for (index, filter) in filters.iter().enumerate() {
+            let setting = format!("filters[{index}]");
+            let needle = first_string(filter);
+            let mut bad_regex = false;
+            if let Value::Map(ref fields) = *filter {
+                for key in ["include-re", "exclude-re"] {
+                    let res =
+                        fields
+                            .get(&Value::String(key.to_owned()))
+                            .map_or_else(Vec::new, |value| match *value {
+                                Value::String(ref re) => vec![re],
+                                Value::Seq(ref res) => res
+                                    .iter()
+                                    .filter_map(|re| match *re {
+                                        Value::String(ref re) => Some(re),
+                                        _ => None,
+                                    })
+                                    .collect(),
+                                _ => vec![],
+                            });
+                    for re in res {
+                        if let Err(err) = Regex::new(re) {
+                            bad_regex = true;
+                            // The last line of the error tells what is wrong
+                            let message = err.to_string();
+                            let message = message.lines().last().unwrap_or_default();
+                            problems.push(Problem {
+                                setting: format!("{setting}.{key}"),
+                                needle: Some(re.clone()),
+                                message: message.trim_start_matches("error: ").to_owned(),
+                            });
+                        }
+                    }
+                }
+                if let Some(extra) = fields.get(&Value::String("extra".to_owned())) {
+                    for message in T::problems(extra) {
+                        problems.push(Problem {
+                            setting: format!("{setting}.extra"),
+                            needle: needle.clone(),
+                            message,
+                        });
+                    }
+                }
+            }
+            // A bad regex already tells why the filter cannot be read
+            if !bad_regex && let Err(err) = filter.clone().deserialize_into::<Filter<Value>>() {
+                problems.push(Problem {
+                    setting,
+                    needle,
+                    message: err.to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// The first string in `value`, to find it in the configuration file.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn first_string(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref string) => Some(string.clone()),
+        Value::Seq(ref values) => values.iter().find_map(first_string),
+        Value::Map(ref map) => map.values().find_map(first_string),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![expect(clippy::expect_used, reason = "test")]
@@ -109,6 +317,7 @@ mod tests {
     }
 
     #[test]
+    #[expect(clippy::too_many_lines, reason = "big snapshot")]
     fn new_with_args_minimal_config() {
         // Create a minimal args with required fields only
         let args = Generic {
@@ -119,7 +328,7 @@ mod tests {
         };
 
         let config: Config<()> =
-            Config::new(&args).expect("minimal config args should produce valid config");
+            Config::new(&args, None).expect("minimal config args should produce valid config");
 
         if cfg!(feature = "__tls") {
             assert_debug_snapshot!(config, @r#"
@@ -137,6 +346,9 @@ mod tests {
                         "password123",
                     ),
                     password_command: None,
+                    password_file: None,
+                    password_env: None,
+                    password_netrc: false,
                     mode: Some(
                         AutoTls,
                     ),
@@ -144,6 +356,31 @@ mod tests {
                     dry_run: false,
                     auth: None,
                     oauth2_command: None,
+                    oauth2_token_file: None,
+                    oauth2_token_env: None,
+                    oauth2_token_netrc: false,
+                    authorize_as: None,
+                    oauth2: None,
+                    uidplus_fallback: false,
+                    delete_mode: None,
+                    trash_mailbox: None,
+                    reconnect_attempts: None,
+                    reconnect_delay: None,
+                    connect_timeout: None,
+                    command_timeout: None,
+                    total_timeout: None,
+                    concurrency: None,
+                    tls_ca_file: None,
+                    tls_client_cert: None,
+                    tls_client_key: None,
+                    tls_pin_sha256: None,
+                    insecure_skip_verify: false,
+                    tls_policy: None,
+                    allow_plaintext_auth: false,
+                    tunnel: None,
+                    proxy: None,
+                    debug_file: None,
+                    secret_cache: SecretCache { .. },
                 },
                 extra: None,
                 filters: None,
@@ -165,6 +402,9 @@ mod tests {
                         "password123",
                     ),
                     password_command: None,
+                    password_file: None,
+                    password_env: None,
+                    password_netrc: false,
                     mode: Some(
                         Plaintext,
                     ),
@@ -172,6 +412,31 @@ mod tests {
                     dry_run: false,
                     auth: None,
                     oauth2_command: None,
+                    oauth2_token_file: None,
+                    oauth2_token_env: None,
+                    oauth2_token_netrc: false,
+                    authorize_as: None,
+                    oauth2: None,
+                    uidplus_fallback: false,
+                    delete_mode: None,
+                    trash_mailbox: None,
+                    reconnect_attempts: None,
+                    reconnect_delay: None,
+                    connect_timeout: None,
+                    command_timeout: None,
+                    total_timeout: None,
+                    concurrency: None,
+                    tls_ca_file: None,
+                    tls_client_cert: None,
+                    tls_client_key: None,
+                    tls_pin_sha256: None,
+                    insecure_skip_verify: false,
+                    tls_policy: None,
+                    allow_plaintext_auth: false,
+                    tunnel: None,
+                    proxy: None,
+                    debug_file: None,
+                    secret_cache: SecretCache { .. },
                 },
                 extra: None,
                 filters: None,
@@ -188,12 +453,12 @@ mod tests {
             ..Default::default()
         };
 
-        let result: Result<Config<()>, ConfigError> = Config::new(&args);
+        let result: Result<Config<()>, ConfigError> = Config::new(&args, None);
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            Applying CLI args to configuration, at src/libs/config.rs:79:14
-            `-- The server must be set, at src/libs/base_config.rs:156:13,
+            Applying CLI args to configuration, at src/libs/config.rs:76:14
+            `-- The server must be set, at src/libs/base_config.rs:385:13,
         )
         ");
     }
@@ -206,12 +471,12 @@ mod tests {
             ..Default::default()
         };
 
-        let result: Result<Config<()>, ConfigError> = Config::new(&args);
+        let result: Result<Config<()>, ConfigError> = Config::new(&args, None);
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            Applying CLI args to configuration, at src/libs/config.rs:79:14
-            `-- The username must be set, at src/libs/base_config.rs:160:13,
+            Applying CLI args to configuration, at src/libs/config.rs:76:14
+            `-- The username must be set, at src/libs/base_config.rs:395:13,
         )
         ");
     }
@@ -226,7 +491,7 @@ mod tests {
         };
 
         let config: Config<()> =
-            Config::new(&args).expect("config with password-command should be constructable");
+            Config::new(&args, None).expect("config with password-command should be constructable");
 
         // Mock the command execution with a fake password output
         let password = config
@@ -245,16 +510,16 @@ mod tests {
             ..Default::default()
         };
 
-        let config: Config<()> =
-            Config::new(&args).expect("config with unparseable command should be constructable");
+        let config: Config<()> = Config::new(&args, None)
+            .expect("config with unparseable command should be constructable");
 
         // Mock the command execution with a fake password output
         let result = config.base.password();
         assert!(result.is_err());
         assert_debug_snapshot!(result, @r#"
         Err(
-            Parsing password command echo "secret_password, at src/libs/base_config.rs:205:50
-            `-- missing closing quote, at src/libs/base_config.rs:205:50,
+            Parsing password command echo "secret_password, at src/libs/base_config.rs:646:50
+            `-- missing closing quote, at src/libs/base_config.rs:646:50,
         )
         "#);
     }
@@ -269,15 +534,15 @@ mod tests {
         };
 
         let config: Config<()> =
-            Config::new(&args).expect("config with failing command should be constructable");
+            Config::new(&args, None).expect("config with failing command should be constructable");
 
         // Mock the command execution with a fake password output
         let result = config.base.password();
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            Executing password command, at src/libs/base_config.rs:215:68
-            `-- No such file or directory (os error 2), at src/libs/base_config.rs:215:68,
+            Executing password command, at src/libs/base_config.rs:656:68
+            `-- No such file or directory (os error 2), at src/libs/base_config.rs:656:68,
         )
         ");
     }
@@ -292,14 +557,14 @@ mod tests {
         };
 
         let config: Config<()> =
-            Config::new(&args).expect("config with empty command should be constructable");
+            Config::new(&args, None).expect("config with empty command should be constructable");
 
         // Mock the command execution with a fake password output
         let result = config.base.password();
         assert!(result.is_err());
         assert_debug_snapshot!(result, @"
         Err(
-            password command is empty, at src/libs/base_config.rs:212:26,
+            password command is empty, at src/libs/base_config.rs:653:26,
         )
         ");
     }
@@ -314,7 +579,7 @@ mod tests {
         };
 
         let config: Config<()> =
-            Config::new(&args).expect("config with static password should be valid");
+            Config::new(&args, None).expect("config with static password should be valid");
 
         // Mock the command execution with a fake password output
         let password = config
@@ -332,13 +597,13 @@ mod tests {
             ..Default::default()
         };
 
-        let config: Result<Config<()>, ConfigError> = Config::new(&args);
+        let config: Result<Config<()>, ConfigError> = Config::new(&args, None);
 
         assert!(config.is_err());
         assert_debug_snapshot!(config, @"
         Err(
-            Applying CLI args to configuration, at src/libs/config.rs:79:14
-            `-- The password or password command must be set, at src/libs/base_config.rs:174:17,
+            Applying CLI args to configuration, at src/libs/config.rs:76:14
+            `-- The password, password-command, password-file, password-env or password-netrc must be set, at src/libs/base_config.rs:424:17,
         )
         ");
     }
@@ -356,17 +621,19 @@ mod tests {
             ..Default::default()
         };
 
-        let config: Result<Config<()>, ConfigError> = Config::new(&args);
+        let config: Result<Config<()>, ConfigError> = Config::new(&args, None);
         assert!(config.is_err());
         assert_debug_snapshot!(config, @"
         Err(
-            Parsing config file, at src/libs/config.rs:69:18
-            `-- TOML deserialize error: newline in string found at line 2, at src/libs/config.rs:69:18,
+            Loading configuration, at src/libs/config.rs:71:14
+            |-- Reading config file, at src/libs/config_file.rs:203:14
+            `-- TOML deserialize error: newline in string found at line 2, at src/libs/config_file.rs:203:14,
         )
         ");
     }
 
     #[test]
+    #[expect(clippy::too_many_lines, reason = "big snapshot")]
     fn config_loading_from_file() {
         let config_content = r#"
         server = "imap.example.com"
@@ -383,7 +650,8 @@ mod tests {
             ..Default::default()
         };
 
-        let config: Config<()> = Config::new(&args).expect("config from valid file should parse");
+        let config: Config<()> =
+            Config::new(&args, None).expect("config from valid file should parse");
         if cfg!(feature = "__tls") {
             assert_debug_snapshot!(config, @r#"
             Config {
@@ -400,6 +668,9 @@ mod tests {
                         "password123",
                     ),
                     password_command: None,
+                    password_file: None,
+                    password_env: None,
+                    password_netrc: false,
                     mode: Some(
                         AutoTls,
                     ),
@@ -407,6 +678,31 @@ mod tests {
                     dry_run: true,
                     auth: None,
                     oauth2_command: None,
+                    oauth2_token_file: None,
+                    oauth2_token_env: None,
+                    oauth2_token_netrc: false,
+                    authorize_as: None,
+                    oauth2: None,
+                    uidplus_fallback: false,
+                    delete_mode: None,
+                    trash_mailbox: None,
+                    reconnect_attempts: None,
+                    reconnect_delay: None,
+                    connect_timeout: None,
+                    command_timeout: None,
+                    total_timeout: None,
+                    concurrency: None,
+                    tls_ca_file: None,
+                    tls_client_cert: None,
+                    tls_client_key: None,
+                    tls_pin_sha256: None,
+                    insecure_skip_verify: false,
+                    tls_policy: None,
+                    allow_plaintext_auth: false,
+                    tunnel: None,
+                    proxy: None,
+                    debug_file: None,
+                    secret_cache: SecretCache { .. },
                 },
                 extra: None,
                 filters: None,
@@ -428,6 +724,9 @@ mod tests {
                         "password123",
                     ),
                     password_command: None,
+                    password_file: None,
+                    password_env: None,
+                    password_netrc: false,
                     mode: Some(
                         Plaintext,
                     ),
@@ -435,6 +734,31 @@ mod tests {
                     dry_run: true,
                     auth: None,
                     oauth2_command: None,
+                    oauth2_token_file: None,
+                    oauth2_token_env: None,
+                    oauth2_token_netrc: false,
+                    authorize_as: None,
+                    oauth2: None,
+                    uidplus_fallback: false,
+                    delete_mode: None,
+                    trash_mailbox: None,
+                    reconnect_attempts: None,
+                    reconnect_delay: None,
+                    connect_timeout: None,
+                    command_timeout: None,
+                    total_timeout: None,
+                    concurrency: None,
+                    tls_ca_file: None,
+                    tls_client_cert: None,
+                    tls_client_key: None,
+                    tls_pin_sha256: None,
+                    insecure_skip_verify: false,
+                    tls_policy: None,
+                    allow_plaintext_auth: false,
+                    tunnel: None,
+                    proxy: None,
+                    debug_file: None,
+                    secret_cache: SecretCache { .. },
                 },
                 extra: None,
                 filters: None,
@@ -444,6 +768,7 @@ mod tests {
     }
 
     #[test]
+    #[expect(clippy::too_many_lines, reason = "big snapshot")]
     fn arg_overrides_file_config() {
         let config_content = r#"
         server = "imap.example.com"
@@ -475,7 +800,7 @@ mod tests {
         };
 
         let config: Config<()> =
-            Config::new(&args).expect("config with CLI arg overrides should be valid");
+            Config::new(&args, None).expect("config with CLI arg overrides should be valid");
         if cfg!(feature = "__tls") {
             assert_debug_snapshot!(config, @r#"
             Config {
@@ -492,6 +817,9 @@ mod tests {
                         "override_password",
                     ),
                     password_command: None,
+                    password_file: None,
+                    password_env: None,
+                    password_netrc: false,
                     mode: Some(
                         Tls,
                     ),
@@ -499,6 +827,31 @@ mod tests {
                     dry_run: true,
                     auth: None,
                     oauth2_command: None,
+                    oauth2_token_file: None,
+                    oauth2_token_env: None,
+                    oauth2_token_netrc: false,
+                    authorize_as: None,
+                    oauth2: None,
+                    uidplus_fallback: false,
+                    delete_mode: None,
+                    trash_mailbox: None,
+                    reconnect_attempts: None,
+                    reconnect_delay: None,
+                    connect_timeout: None,
+                    command_timeout: None,
+                    total_timeout: None,
+                    concurrency: None,
+                    tls_ca_file: None,
+                    tls_client_cert: None,
+                    tls_client_key: None,
+                    tls_pin_sha256: None,
+                    insecure_skip_verify: false,
+                    tls_policy: None,
+                    allow_plaintext_auth: false,
+                    tunnel: None,
+                    proxy: None,
+                    debug_file: None,
+                    secret_cache: SecretCache { .. },
                 },
                 extra: None,
                 filters: None,
@@ -520,6 +873,9 @@ mod tests {
                         "override_password",
                     ),
                     password_command: None,
+                    password_file: None,
+                    password_env: None,
+                    password_netrc: false,
                     mode: Some(
                         Plaintext,
                     ),
@@ -527,6 +883,31 @@ mod tests {
                     dry_run: true,
                     auth: None,
                     oauth2_command: None,
+                    oauth2_token_file: None,
+                    oauth2_token_env: None,
+                    oauth2_token_netrc: false,
+                    authorize_as: None,
+                    oauth2: None,
+                    uidplus_fallback: false,
+                    delete_mode: None,
+                    trash_mailbox: None,
+                    reconnect_attempts: None,
+                    reconnect_delay: None,
+                    connect_timeout: None,
+                    command_timeout: None,
+                    total_timeout: None,
+                    concurrency: None,
+                    tls_ca_file: None,
+                    tls_client_cert: None,
+                    tls_client_key: None,
+                    tls_pin_sha256: None,
+                    insecure_skip_verify: false,
+                    tls_policy: None,
+                    allow_plaintext_auth: false,
+                    tunnel: None,
+                    proxy: None,
+                    debug_file: None,
+                    secret_cache: SecretCache { .. },
                 },
                 extra: None,
                 filters: None,
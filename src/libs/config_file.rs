@@ -0,0 +1,824 @@
+use std::{
+    collections::BTreeMap,
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+use exn::{Result, ResultExt as _, bail};
+use serde::de::DeserializeOwned;
+use serde_any::Format;
+use serde_value::Value;
+
+use crate::libs::base_config::{BaseConfig, SerdeAnyWrapper};
+
+/// The table of the named accounts.
+const ACCOUNTS: &str = "accounts";
+
+/// The files read before the one holding this setting.
+const INCLUDE: &str = "include";
+
+/// The sections of the commands, with their own `extra` and `filters`.
+const SECTIONS: [&str; 3] = ["archive", "clean", "find-dups"];
+
+/// The configuration file used when `--config` is not given.
+const LOCAL_FILE: &str = ".imap-tools.toml";
+
+/// The names of the configuration file in the system and user directories.
+const DISCOVERED: [&str; 4] = ["config.toml", "config.yaml", "config.yml", "config.json"];
+
+/// The prefix of the environment variables setting a `BaseConfig` field.
+const ENV_PREFIX: &str = "IMAP_TOOLS_";
+
+/// The environment variables of command line arguments, not settings.
+const ENV_ARGS: [&str; 2] = ["IMAP_TOOLS_CONFIG", "IMAP_TOOLS_ACCOUNT"];
+
+#[derive(Debug, derive_more::Display)]
+pub enum ConfigFileError {
+    #[cfg_attr(not(test), display("Reading config file {file:?}"))]
+    #[cfg_attr(test, display("Reading config file"))]
+    Read { file: PathBuf },
+    #[display("The configuration is not a table")]
+    NotATable,
+    #[display("The setting {key:?} is not a string")]
+    Key { key: Value },
+    #[display("include must be a file or a list of files")]
+    IncludeList,
+    #[cfg_attr(not(test), display("Including {file:?}"))]
+    #[cfg_attr(test, display("Including a file"))]
+    Include { file: PathBuf },
+    #[cfg_attr(not(test), display("{file:?} includes itself"))]
+    #[cfg_attr(test, display("A file includes itself"))]
+    IncludeCycle { file: PathBuf },
+    #[display("accounts must hold one table per account")]
+    Accounts,
+    #[display("[{section}] must be a table")]
+    Section { section: String },
+    #[display(
+        "The configuration has the accounts {accounts:?}, select one with --account or use --all-accounts"
+    )]
+    NoAccount { accounts: Vec<String> },
+    #[display("Unknown account {account:?}, the configuration has {accounts:?}")]
+    UnknownAccount {
+        account: String,
+        accounts: Vec<String>,
+    },
+    #[display("Invalid value of the environment variable {var}")]
+    Env { var: String },
+    #[display("Invalid settings")]
+    Deserialize,
+}
+impl std::error::Error for ConfigFileError {}
+
+/// Where a setting comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A configuration file, in `table` when not at the top of the file.
+    File {
+        file: PathBuf,
+        table: Option<String>,
+    },
+    /// An `IMAP_TOOLS_*` environment variable.
+    Env { var: String },
+    /// A command line argument.
+    Args,
+}
+
+impl fmt::Display for Source {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::File {
+                ref file,
+                table: None,
+            } => write!(f, "{}", file.display()),
+            Self::File {
+                ref file,
+                table: Some(ref table),
+            } => write!(f, "{} [{table}]", file.display()),
+            Self::Env { ref var } => write!(f, "${var}"),
+            Self::Args => write!(f, "command line"),
+        }
+    }
+}
+
+/// Settings by name, with where they come from.
+pub type Sources = BTreeMap<String, Source>;
+
+type Settings = BTreeMap<String, (Value, Source)>;
+
+/// The settings of the top of the configuration or of an account, and of
+/// their command sections.
+#[derive(Debug, Default)]
+struct Scope {
+    settings: Settings,
+    sections: BTreeMap<String, Settings>,
+}
+
+impl Scope {
+    /// Split a `table` of `file` into settings and command sections.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(table), err(level = "info"))
+    )]
+    fn new(
+        table: BTreeMap<Value, Value>,
+        file: &Path,
+        name: Option<&str>,
+    ) -> Result<Self, ConfigFileError> {
+        let source = |section: Option<&str>| Source::File {
+            file: file.to_owned(),
+            table: match (name, section) {
+                (Some(name), Some(section)) => Some(format!("{name}.{section}")),
+                (name, section) => name.or(section).map(ToOwned::to_owned),
+            },
+        };
+
+        let mut scope = Self::default();
+        for (key, value) in table {
+            let key = key_string(key)?;
+            if SECTIONS.contains(&key.as_str()) {
+                let Value::Map(content) = value else {
+                    bail!(ConfigFileError::Section { section: key });
+                };
+                let mut settings = Settings::new();
+                for (setting, value) in content {
+                    settings.insert(key_string(setting)?, (value, source(Some(&key))));
+                }
+                scope.sections.insert(key, settings);
+            } else {
+                scope.settings.insert(key, (value, source(None)));
+            }
+        }
+        Ok(scope)
+    }
+
+    /// Put the settings of `other` over ours.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, other))
+    )]
+    fn merge(&mut self, other: Self) {
+        self.settings.extend(other.settings);
+        for (name, settings) in other.sections {
+            self.sections.entry(name).or_default().extend(settings);
+        }
+    }
+
+    /// Our settings, with those of `section` over them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn resolve(mut self, section: Option<&str>) -> Settings {
+        if let Some(section) = section
+            && let Some(settings) = self.sections.remove(section)
+        {
+            self.settings.extend(settings);
+        }
+        self.settings
+    }
+}
+
+/// All the configuration files, merged.
+#[derive(Debug, Default)]
+struct Layers {
+    shared: Scope,
+    accounts: BTreeMap<String, Scope>,
+    /// Whether the last file, with its includes, has accounts, so that one
+    /// must be selected. The accounts of the files before it, like the
+    /// system one, are only used when selected.
+    needs_account: bool,
+}
+
+impl Layers {
+    /// Read `file` after the files it includes. `stack` holds the files
+    /// being read, to stop on include cycles.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(stack), err(level = "info"))
+    )]
+    fn read(file: &Path, stack: &mut Vec<PathBuf>) -> Result<Self, ConfigFileError> {
+        let value: Value = serde_any::from_file(file)
+            .map_err(SerdeAnyWrapper)
+            .or_raise(|| ConfigFileError::Read {
+                file: file.to_owned(),
+            })?;
+        let Value::Map(mut table) = value else {
+            bail!(ConfigFileError::NotATable);
+        };
+
+        let mut layers = Self::default();
+        let includes = match table.remove(&Value::String(INCLUDE.to_owned())) {
+            None => vec![],
+            Some(Value::String(include)) => vec![Value::String(include)],
+            Some(Value::Seq(includes)) => includes,
+            Some(_) => bail!(ConfigFileError::IncludeList),
+        };
+        for include in includes {
+            let Value::String(include) = include else {
+                bail!(ConfigFileError::IncludeList);
+            };
+            let include = file.parent().unwrap_or_else(|| Path::new("")).join(include);
+            let canonical = include.canonicalize().unwrap_or_else(|_| include.clone());
+            if stack.contains(&canonical) {
+                bail!(ConfigFileError::IncludeCycle { file: include });
+            }
+            stack.push(canonical);
+            let layer = Self::read(&include, stack)
+                .or_raise(|| ConfigFileError::Include { file: include })?;
+            stack.pop();
+            layers.merge(layer);
+        }
+
+        let mut own = Self::default();
+        if let Some(accounts) = table.remove(&Value::String(ACCOUNTS.to_owned())) {
+            let Value::Map(accounts) = accounts else {
+                bail!(ConfigFileError::Accounts);
+            };
+            for (name, account) in accounts {
+                let (Value::String(name), Value::Map(account)) = (name, account) else {
+                    bail!(ConfigFileError::Accounts);
+                };
+                let scope = Scope::new(account, file, Some(&format!("{ACCOUNTS}.{name}")))?;
+                own.accounts.insert(name, scope);
+            }
+        }
+        own.shared = Scope::new(table, file, None)?;
+        layers.merge(own);
+        Ok(layers)
+    }
+
+    /// Read `files` in turn, each one over the previous ones.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", err(level = "info"))
+    )]
+    fn read_all(files: &[PathBuf]) -> Result<Self, ConfigFileError> {
+        let mut layers = Self::default();
+        for file in files {
+            let mut stack = vec![file.canonicalize().unwrap_or_else(|_| file.clone())];
+            let layer = Self::read(file, &mut stack)?;
+            layers.needs_account = !layer.accounts.is_empty();
+            layers.merge(layer);
+        }
+        Ok(layers)
+    }
+
+    /// Put the settings of `other` over ours.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, other))
+    )]
+    fn merge(&mut self, other: Self) {
+        self.shared.merge(other.shared);
+        for (name, scope) in other.accounts {
+            self.accounts.entry(name).or_default().merge(scope);
+        }
+    }
+
+    /// The settings of the command of `section` for `account`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    fn resolve(
+        mut self,
+        account: Option<&str>,
+        section: Option<&str>,
+    ) -> Result<Settings, ConfigFileError> {
+        let names = self.accounts.keys().cloned().collect::<Vec<_>>();
+        let account = match account {
+            Some(account) => {
+                let Some(scope) = self.accounts.remove(account) else {
+                    bail!(ConfigFileError::UnknownAccount {
+                        account: account.to_owned(),
+                        accounts: names,
+                    });
+                };
+                Some(scope)
+            },
+            None if self.needs_account => bail!(ConfigFileError::NoAccount { accounts: names }),
+            None => None,
+        };
+
+        let mut settings = self.shared.resolve(section);
+        if let Some(account) = account {
+            settings.extend(account.resolve(section));
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", err(level = "info"))
+)]
+fn key_string(key: Value) -> Result<String, ConfigFileError> {
+    match key {
+        Value::String(key) => Ok(key),
+        key => bail!(ConfigFileError::Key { key }),
+    }
+}
+
+/// The directories of the system and user configuration files, from the
+/// least to the most important.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn config_dirs() -> Vec<PathBuf> {
+    // Tests must not depend on the configuration of the machine
+    if cfg!(test) {
+        return vec![];
+    }
+    let user = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    [Some(PathBuf::from("/etc")), user]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("imap-tools"))
+        .collect()
+}
+
+/// The configuration files, from the least to the most important: the one
+/// found in each of `dirs`, then the one of the current directory, or only
+/// `file` when given.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn files(file: Option<&Path>, dirs: &[PathBuf]) -> Vec<PathBuf> {
+    if let Some(file) = file {
+        return vec![file.to_owned()];
+    }
+    let mut files = dirs
+        .iter()
+        .filter_map(|dir| {
+            DISCOVERED
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+        })
+        .collect::<Vec<_>>();
+    if !cfg!(test) && Path::new(LOCAL_FILE).is_file() {
+        files.push(LOCAL_FILE.into());
+    }
+    files
+}
+
+/// The settings of the `IMAP_TOOLS_*` variables among `vars`.
+///
+/// A value is taken as a string when the setting is one, else it is read
+/// like a TOML value, so `IMAP_TOOLS_PORT=993` and
+/// `IMAP_TOOLS_OAUTH2='{ provider = "google", ... }'` work. The variables
+/// not named after a `BaseConfig` setting are left alone.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(vars), err(level = "info"))
+)]
+fn env_settings(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Settings, ConfigFileError> {
+    let schema = schemars::schema_for!(BaseConfig);
+    let known = schema
+        .get("properties")
+        .and_then(serde_json::Value::as_object);
+    let mut settings = Settings::new();
+    for (var, raw) in vars {
+        let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if ENV_ARGS.contains(&var.as_str()) {
+            continue;
+        }
+        let key = name.to_lowercase().replace('_', "-");
+        if !known.is_some_and(|known| known.contains_key(&key)) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(var, "not a setting, ignored");
+            continue;
+        }
+        let fits = |value: &Value| {
+            Value::Map(BTreeMap::from([(
+                Value::String(key.clone()),
+                value.clone(),
+            )]))
+            .deserialize_into::<BaseConfig>()
+        };
+
+        let mut value = Value::String(raw.clone());
+        if fits(&value).is_err() {
+            value = serde_any::from_str::<BTreeMap<String, Value>>(
+                &format!("value = {raw}"),
+                Format::Toml,
+            )
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or(value);
+            fits(&value).or_raise(|| ConfigFileError::Env { var: var.clone() })?;
+        }
+        settings.insert(key, (value, Source::Env { var }));
+    }
+    Ok(settings)
+}
+
+/// The names of the accounts of the configuration files, sorted.
+///
+/// # Errors
+/// Returns an error if a file cannot be read.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", ret, err(level = "info"))
+)]
+pub fn accounts(file: Option<&Path>) -> Result<Vec<String>, ConfigFileError> {
+    let layers = Layers::read_all(&files(file, &config_dirs()))?;
+    Ok(layers.accounts.into_keys().collect())
+}
+
+/// Load the configuration for the command of `section`, see [`load_sourced`].
+///
+/// # Errors
+/// See [`load_sourced`].
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", err(level = "info"))
+)]
+pub fn load<C: DeserializeOwned>(
+    file: Option<&Path>,
+    account: Option<&str>,
+    section: Option<&str>,
+    ignore: &[&str],
+) -> Result<C, ConfigFileError> {
+    load_sourced(file, account, section, ignore).map(|(config, _)| config)
+}
+
+/// Load the configuration for the command of `section`, and where each
+/// setting comes from.
+///
+/// The files are read in turn, each one over the previous ones:
+/// `/etc/imap-tools/config.toml`, `$XDG_CONFIG_HOME/imap-tools/config.toml`,
+/// then `.imap-tools.toml`, or only `file` when given. A file is read after
+/// the files of its `include` list, relative to it.
+///
+/// The settings are then taken from the top of the files, the `[section]`
+/// of the command, the `[accounts.<account>]` table and its own `section`,
+/// and finally the `IMAP_TOOLS_*` environment variables, each setting
+/// replacing the previous one as a whole, `extra` and `filters` included.
+/// The `ignore` settings are left out.
+///
+/// # Errors
+/// Returns an error if a file cannot be read, the settings cannot be
+/// deserialized, or when the account is missing or unknown.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", err(level = "info"))
+)]
+pub fn load_sourced<C: DeserializeOwned>(
+    file: Option<&Path>,
+    account: Option<&str>,
+    section: Option<&str>,
+    ignore: &[&str],
+) -> Result<(C, Sources), ConfigFileError> {
+    load_layers(
+        &files(file, &config_dirs()),
+        env::vars(),
+        account,
+        section,
+        ignore,
+    )
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(vars), err(level = "info"))
+)]
+fn load_layers<C: DeserializeOwned>(
+    files: &[PathBuf],
+    vars: impl IntoIterator<Item = (String, String)>,
+    account: Option<&str>,
+    section: Option<&str>,
+    ignore: &[&str],
+) -> Result<(C, Sources), ConfigFileError> {
+    let mut settings = Layers::read_all(files)?.resolve(account, section)?;
+    settings.extend(env_settings(vars)?);
+    for key in ignore {
+        settings.remove(*key);
+    }
+
+    let mut sources = Sources::new();
+    let mut table = BTreeMap::new();
+    for (key, (value, source)) in settings {
+        table.insert(Value::String(key.clone()), value);
+        sources.insert(key, source);
+    }
+    let config = Value::Map(table)
+        .deserialize_into()
+        .or_raise(|| ConfigFileError::Deserialize)?;
+    Ok((config, sources))
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use std::{fs::File, io::Write as _};
+
+    use insta::{assert_debug_snapshot, assert_snapshot};
+
+    use super::*;
+    use crate::libs::config::Config;
+
+    const CONFIG: &str = r#"
+username = "shared@example.com"
+server = "imap.example.com"
+
+[accounts.work]
+server = "imap.work.example"
+
+[accounts.home]
+username = "me@home.example"
+"#;
+
+    fn write_config() -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let file = dir.path().join("config.toml");
+        File::create(&file)
+            .expect("create")
+            .write_all(CONFIG.as_bytes())
+            .expect("write");
+        (dir, file)
+    }
+
+    #[test]
+    fn account_over_shared_settings() {
+        let (_dir, file) = write_config();
+        assert_debug_snapshot!(accounts(Some(&file)).expect("accounts"), @r#"
+        [
+            "home",
+            "work",
+        ]
+        "#);
+        let work: BaseConfig = load(Some(&file), Some("work"), None, &[]).expect("work account");
+        let home: BaseConfig = load(Some(&file), Some("home"), None, &[]).expect("home account");
+        assert_debug_snapshot!(
+            [(work.server, work.username), (home.server, home.username)],
+            @r#"
+        [
+            (
+                Some(
+                    "imap.work.example",
+                ),
+                Some(
+                    "shared@example.com",
+                ),
+            ),
+            (
+                Some(
+                    "imap.example.com",
+                ),
+                Some(
+                    "me@home.example",
+                ),
+            ),
+        ]
+        "#
+        );
+    }
+
+    #[test]
+    fn account_filters_replace_shared_ones() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let file = dir.path().join("config.toml");
+        File::create(&file)
+            .expect("create")
+            .write_all(
+                br#"
+server = "imap.example.com"
+[[filters]]
+pattern = "*"
+[accounts.work.extra]
+days = 30
+[[accounts.work.filters]]
+pattern = "Work/*"
+"#,
+            )
+            .expect("write");
+        let config: Config<Value> =
+            load(Some(&file), Some("work"), None, &[]).expect("work account");
+        assert_debug_snapshot!((config.extra, config.filters.map(|filters| filters.len())), @r#"
+        (
+            Some(
+                Map(
+                    {
+                        String(
+                            "days",
+                        ): I64(
+                            30,
+                        ),
+                    },
+                ),
+            ),
+            Some(
+                1,
+            ),
+        )
+        "#);
+        let base: BaseConfig = load(Some(&file), Some("work"), None, &["extra", "filters"])
+            .expect("base of work account");
+        assert_debug_snapshot!(base.server, @r#"
+        Some(
+            "imap.example.com",
+        )
+        "#);
+    }
+
+    #[test]
+    fn command_sections() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let file = dir.path().join("config.toml");
+        File::create(&file)
+            .expect("create")
+            .write_all(
+                br#"
+server = "imap.example.com"
+[[filters]]
+pattern = "*"
+[clean]
+extra = { "1M" = 30 }
+[archive.extra]
+days = 90
+[[archive.filters]]
+pattern = "Archive/*"
+"#,
+            )
+            .expect("write");
+        let summary = |section| {
+            let config: Config<Value> = load(Some(&file), None, section, &[]).expect("section");
+            let filters = config.filters.unwrap_or_default();
+            (
+                config.extra,
+                filters
+                    .into_iter()
+                    .map(|filter| filter.pattern)
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_debug_snapshot!([summary(Some("clean")), summary(Some("archive")), summary(None)], @r#"
+        [
+            (
+                Some(
+                    Map(
+                        {
+                            String(
+                                "1M",
+                            ): I64(
+                                30,
+                            ),
+                        },
+                    ),
+                ),
+                [
+                    Some(
+                        "*",
+                    ),
+                ],
+            ),
+            (
+                Some(
+                    Map(
+                        {
+                            String(
+                                "days",
+                            ): I64(
+                                90,
+                            ),
+                        },
+                    ),
+                ),
+                [
+                    Some(
+                        "Archive/*",
+                    ),
+                ],
+            ),
+            (
+                None,
+                [
+                    Some(
+                        "*",
+                    ),
+                ],
+            ),
+        ]
+        "#);
+    }
+
+    #[test]
+    fn includes_env_and_sources() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        File::create(dir.path().join("common.toml"))
+            .expect("create")
+            .write_all(b"server = \"imap.example.com\"\nport = 143\nusername = \"common\"\n")
+            .expect("write");
+        let file = dir.path().join("config.toml");
+        File::create(&file)
+            .expect("create")
+            .write_all(b"include = [\"common.toml\"]\nusername = \"alice\"\n")
+            .expect("write");
+        let vars = [
+            ("IMAP_TOOLS_PORT", "993"),
+            ("IMAP_TOOLS_PASSWORD", "1234"),
+            ("IMAP_TOOLS_CONFIG", "ignored.toml"),
+            ("IMAP_TOOLS_LOG", "not a setting"),
+            ("HOME", "/home/alice"),
+        ]
+        .map(|(var, value)| (var.to_owned(), value.to_owned()));
+
+        let (config, sources): (BaseConfig, _) =
+            load_layers(&[file], vars, None, None, &[]).expect("load");
+        let sources = sources
+            .into_iter()
+            .map(|(key, source)| {
+                let source = source
+                    .to_string()
+                    .replace(&*dir.path().to_string_lossy(), "");
+                format!("{key}: {source}")
+            })
+            .collect::<Vec<_>>();
+        assert_snapshot!(config.password().expect("password"), @"1234");
+        assert_debug_snapshot!((config.server, config.port, config.username, sources), @r#"
+        (
+            Some(
+                "imap.example.com",
+            ),
+            Some(
+                993,
+            ),
+            Some(
+                "alice",
+            ),
+            [
+                "password: $IMAP_TOOLS_PASSWORD",
+                "port: $IMAP_TOOLS_PORT",
+                "server: /common.toml",
+                "username: /config.toml",
+            ],
+        )
+        "#);
+
+        let err = load_layers::<BaseConfig>(
+            &[],
+            [("IMAP_TOOLS_PORT".to_owned(), "imap".to_owned())],
+            None,
+            None,
+            &[],
+        )
+        .expect_err("not a port");
+        assert_snapshot!(err.to_string(), @"Invalid value of the environment variable IMAP_TOOLS_PORT");
+    }
+
+    #[test]
+    fn discovered_files_unless_one_is_given() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let (system, user, empty) = (
+            dir.path().join("etc"),
+            dir.path().join("user"),
+            dir.path().join("empty"),
+        );
+        for (dir, name, content) in [
+            (
+                &system,
+                "config.toml",
+                "server = \"imap.example.com\"\n[accounts.admin]\nusername = \"admin\"\n",
+            ),
+            (&user, "config.yaml", "username: alice\n"),
+            (&user, "config.json", "{}"),
+        ] {
+            std::fs::create_dir_all(dir).expect("create dir");
+            std::fs::write(dir.join(name), content).expect("write");
+        }
+        let dirs = [system.clone(), user.clone(), empty];
+
+        let found = files(None, &dirs);
+        assert_eq!(found, [
+            system.join("config.toml"),
+            user.join("config.yaml")
+        ]);
+        let given = dir.path().join("given.toml");
+        assert_eq!(files(Some(&given), &dirs), [given]);
+
+        // The accounts of the system file are only used when selected
+        let (alice, _): (BaseConfig, _) =
+            load_layers(&found, [], None, None, &[]).expect("no account");
+        let (admin, _): (BaseConfig, _) =
+            load_layers(&found, [], Some("admin"), None, &[]).expect("admin account");
+        assert_eq!([alice.username, admin.username], [
+            Some("alice".to_owned()),
+            Some("admin".to_owned())
+        ]);
+    }
+
+    #[test]
+    fn account_must_be_known() {
+        let (_dir, file) = write_config();
+        let err = load::<BaseConfig>(Some(&file), None, None, &[]).expect_err("no account");
+        assert_snapshot!(err.to_string(), @r#"The configuration has the accounts ["home", "work"], select one with --account or use --all-accounts"#);
+        let err =
+            load::<BaseConfig>(Some(&file), Some("play"), None, &[]).expect_err("unknown account");
+        assert_snapshot!(err.to_string(), @r#"Unknown account "play", the configuration has ["home", "work"]"#);
+    }
+}
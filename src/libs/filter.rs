@@ -1,6 +1,7 @@
-use std::fmt::Debug;
+use std::{borrow::Cow, fmt::Debug};
 
 use regex::Regex;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Deserializer, Serialize, de};
 
 #[derive(Debug, Clone)]
@@ -98,11 +99,35 @@ where
     }
 }
 
+impl<T> JsonSchema for Filter<T>
+where
+    T: JsonSchema + Clone + Debug + Serialize,
+{
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        internal::Filter::<T>::schema_name()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_id() -> Cow<'static, str> {
+        internal::Filter::<T>::schema_id()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(generator))
+    )]
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        internal::Filter::<T>::json_schema(generator)
+    }
+}
+
 mod internal {
     use std::{fmt, fmt::Debug};
 
     use exn::{Result, ResultExt as _};
     use regex::{Regex, escape};
+    use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
     use serde::{Deserialize, Deserializer, Serialize, de};
 
     use super::Filter as RealFilter;
@@ -160,9 +185,27 @@ mod internal {
     }
     impl std::error::Error for FilterError {}
 
+    /// The schema of the fields taking a string or a list of strings.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(_generator))
+    )]
+    fn string_or_vec_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } },
+            ]
+        })
+    }
+
     /// Private structure without the regex
-    #[derive(Deserialize, Serialize, Debug)]
+    #[derive(Deserialize, Serialize, Debug, JsonSchema)]
     #[serde(deny_unknown_fields, rename_all = "kebab-case")]
+    #[schemars(
+        rename = "Filter_for_{T}",
+        description = "The mailboxes to work on, with their own extra"
+    )]
     pub struct Filter<T>
     where
         T: Clone + Debug,
@@ -171,12 +214,16 @@ mod internal {
         pub pattern: Option<String>,
         pub extra: Option<T>,
         #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+        #[schemars(schema_with = "string_or_vec_schema")]
         pub include: Option<Vec<String>>,
         #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+        #[schemars(schema_with = "string_or_vec_schema")]
         pub include_re: Option<Vec<String>>,
         #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+        #[schemars(schema_with = "string_or_vec_schema")]
         pub exclude: Option<Vec<String>>,
         #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+        #[schemars(schema_with = "string_or_vec_schema")]
         pub exclude_re: Option<Vec<String>>,
     }
 
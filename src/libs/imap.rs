@@ -4,21 +4,35 @@ compile_error!("features `openssl` and `rustls` are mutually exclusive — enabl
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     fmt::Debug,
+    sync::Once,
 };
 
-use async_imap::{Session, imap_proto::NameAttribute, types::Uid};
-use exn::{OptionExt as _, Result, ResultExt as _, bail};
+use async_imap::{
+    Session,
+    imap_proto::{NameAttribute, Response, Status},
+    types::{self, Fetch, Mailbox, Name, Quota, QuotaRoot, Uid, UnsolicitedResponse},
+};
+use exn::{Exn, Frame, OptionExt as _, Result, ResultExt as _, bail};
 use futures::TryStreamExt as _;
 use serde::Serialize;
-use tokio::net::TcpStream;
+use tokio::{net::TcpStream, time::sleep};
 
+#[cfg(feature = "__tls")]
+use crate::libs::tls;
 use crate::libs::{
-    auth::{AuthMethod, CramMd5Auth, PlainAuth, ScramAuth, XOAuth2Auth},
+    auth::{
+        AuthMethod, CramMd5Auth, ExternalAuth, OAuthBearerAuth, PlainAuth, ScramAuth, XOAuth2Auth,
+    },
     base_config::BaseConfig,
     config::Config,
     filter::Filter,
     filters::Filters,
-    mode::Mode,
+    mode::{DeleteMode, Mode, TlsPolicy},
+    proxy::Proxy,
+    timeout::{Elapsed, TimeoutStream, Timeouts},
+    transcript::{self, TranscriptStream},
+    tunnel::{PreAuthStream, TunnelStream},
+    untagged::{SetAside, SetAsideStream},
 };
 
 /// Marker trait for streams usable with async-imap.
@@ -28,6 +42,13 @@ impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + Debug> Asy
 /// Boxed async stream type alias.
 pub type ImapStream = Box<dyn AsyncStream>;
 
+/// Number of reconnection attempts when `reconnect-attempts` is not set.
+const DEFAULT_RECONNECT_ATTEMPTS: u32 = 3;
+
+/// Initial delay between reconnection attempts, in seconds, when
+/// `reconnect-delay` is not set. It doubles after each failed attempt.
+const DEFAULT_RECONNECT_DELAY: u64 = 1;
+
 #[derive(Debug, derive_more::Display)]
 /// Error type for IMAP operations.
 pub enum ImapError {
@@ -39,6 +60,22 @@ pub enum ImapError {
     ConnectBase,
     #[display("Connecting to {server} on port {port}")]
     Connect { server: String, port: u16 },
+    #[display("Connecting through tunnel")]
+    Tunnel,
+    #[display("Parsing the proxy URL")]
+    ProxyUrl,
+    #[display("Connecting to proxy {proxy}")]
+    ProxyConnect { proxy: Proxy },
+    #[display("Opening a tunnel to {server} on port {port} through proxy {proxy}")]
+    ProxyHandshake {
+        proxy: Proxy,
+        server: String,
+        port: u16,
+    },
+    #[display("Taking over the PREAUTH session")]
+    PreAuth,
+    #[display("Setting up the debug transcript")]
+    Transcript,
     #[display("Setting up TLS connection")]
     TlsSetup,
     #[display("Reading server greeting")]
@@ -51,26 +88,98 @@ pub enum ImapError {
     Login { method: &'static str },
     #[display("Initializing {method} SCRAM session")]
     ScramSession { method: &'static str },
+    #[display("{method} cannot act as another user, authorize-as needs another mechanism")]
+    AuthorizeAs { method: &'static str },
+    #[display("No usable authentication mechanism among the server's {offered:?}")]
+    NoMechanism { offered: Vec<String> },
     #[display("Generating OAuth2 token")]
     OAuth2Token,
     #[display(
-        "The server does not support the UIDPLUS capability, and all our operations need UIDs for safety"
+        "The server does not support the UIDPLUS capability, needed to delete messages safely, set uidplus-fallback to do it without"
     )]
     UidPlus,
+    #[display(
+        "The UIDVALIDITY of {mailbox:?} changed from {before} to {after}, its UIDs no longer mean the same messages"
+    )]
+    UidValidity {
+        mailbox: String,
+        before: u32,
+        after: u32,
+    },
+    #[display("Searching messages by UID")]
+    UidSearch,
+    #[display("Fetching messages by UID")]
+    UidFetch,
+    #[display("Listing mailboxes matching {pattern:?} in {reference:?}")]
+    List { reference: String, pattern: String },
+    #[display("Creating mailbox {mailbox:?}")]
+    Create { mailbox: String },
+    #[display("Deleting mailbox {mailbox:?}")]
+    Delete { mailbox: String },
+    #[display("Sending ID")]
+    Id,
+    #[display("Querying the quota roots of {mailbox:?}")]
+    QuotaRoot { mailbox: String },
     #[display("Querying IMAP capabilities")]
     ImapCapabilities,
     #[display("Selecting mailbox {mailbox:?}")]
     ImapSelect { mailbox: String },
+    #[display("Examining mailbox {mailbox:?}")]
+    ImapExamine { mailbox: String },
+    #[display("Connection to the IMAP server was lost")]
+    ConnectionLost,
+    #[display("Timed out during {operation}")]
+    Timeout { operation: &'static str },
+    #[display("Reconnecting to the IMAP server failed after {attempts} attempts")]
+    Reconnect { attempts: u32 },
     #[display("Storing message flags by UID")]
     UidStore,
+    #[display("Moving messages by UID to {mailbox:?}")]
+    UidMove { mailbox: String },
+    #[display("Copying messages by UID to {mailbox:?}")]
+    UidCopy { mailbox: String },
+    #[display("The messages may already be copied to {mailbox:?}, not starting over")]
+    Copied { mailbox: String },
+    #[display("Listing mailboxes to find the trash")]
+    ListTrash,
+    #[display("No mailbox has the \\Trash special use, set trash-mailbox")]
+    NoTrash,
     #[display("Streaming FETCH results")]
     Stream,
-    #[display("Closing mailbox")]
-    ImapClose,
+    #[display("Expunging messages by UID")]
+    UidExpunge,
+    #[display("Expunging mailbox")]
+    Expunge,
     #[display("Listing mailboxes with filter {filter}")]
     ImapList { filter: String },
     #[display("This filter did not return anything {filter}")]
     ImapListEmpty { filter: String },
+    #[display("Running {command}")]
+    Raw { command: String },
+    #[display("The server answered {response:?} to {command}")]
+    RawRefused { command: String, response: String },
+    #[display("Connection mode {mode:?} conflicts with tls-policy {policy:?}")]
+    #[cfg(feature = "__tls")]
+    TlsPolicyConflict { mode: Mode, policy: TlsPolicy },
+    #[display("The connection in mode {mode:?} is not encrypted, and tls-policy requires it")]
+    TlsRequired { mode: Mode },
+    #[display(
+        "The server refused STARTTLS, set tls-policy = \"opportunistic\" to continue in plaintext"
+    )]
+    #[cfg(feature = "__tls")]
+    StartTlsRefused,
+    #[display(
+        "Refusing to send {method} credentials over an unencrypted connection, set allow-plaintext-auth to do it anyway"
+    )]
+    PlaintextAuth { method: &'static str },
+    #[display(
+        "Refusing the PREAUTH session of an unencrypted connection, set allow-plaintext-auth to take it anyway"
+    )]
+    PlaintextPreAuth,
+    #[display("Querying capabilities before authenticating")]
+    PreAuthCapabilities,
+    #[display("The server does not allow LOGIN (LOGINDISABLED), use another auth method")]
+    LoginDisabled,
     #[display("Reading server greeting before STARTTLS")]
     #[cfg(feature = "__tls")]
     GreetingStarttls,
@@ -83,6 +192,15 @@ pub enum ImapError {
     #[display("Creating native TLS connector")]
     #[cfg(feature = "native-tls")]
     NativeTlsConnector,
+    #[display("Loading TLS options")]
+    #[cfg(feature = "__tls")]
+    TlsOptions,
+    #[display("Setting up the TLS client certificate")]
+    #[cfg(feature = "__tls")]
+    TlsClientAuth,
+    #[display("Checking the pinned certificate of {server}")]
+    #[cfg(feature = "__tls")]
+    TlsPin { server: String },
     #[display("Performing TLS handshake with {server}")]
     #[cfg(feature = "__tls")]
     TlsHandshake { server: String },
@@ -104,6 +222,26 @@ pub enum ImapError {
 // #[display()]
 impl std::error::Error for ImapError {}
 
+/// What was learned about the server while connecting, for `imap info`.
+#[derive(Debug, Default)]
+pub struct Handshake {
+    /// Ask for the capabilities before authenticating, even when the
+    /// mechanism does not need them.
+    pub diagnose: bool,
+    /// The greeting of the server.
+    pub greeting: Option<String>,
+    /// The capabilities before authenticating, sorted.
+    pub capabilities: Option<Vec<String>>,
+    /// The authentication mechanism used, or `PREAUTH`.
+    pub mechanism: Option<&'static str>,
+    /// The negotiated TLS version.
+    pub tls_version: Option<String>,
+    /// The negotiated TLS cipher suite.
+    pub tls_cipher: Option<String>,
+    /// A summary of each certificate the server sent, its own first.
+    pub certificates: Vec<String>,
+}
+
 /// The result of listing a mailbox, including optional command-specific extra data.
 #[derive(Clone, Debug)]
 pub struct ListResult<T>
@@ -123,6 +261,13 @@ where
     /// The underlying async-imap session.
     pub session: Session<ImapStream>,
 
+    /// The configuration used to open the session, kept to reconnect.
+    base: BaseConfig,
+
+    /// The currently selected mailbox and whether it was opened read-only,
+    /// restored after a reconnect.
+    selected: Option<(String, bool)>,
+
     /// Optional command-specific extra data from configuration.
     extra: Option<T>,
 
@@ -132,6 +277,16 @@ where
     /// Cache of previously fetched capabilities to avoid redundant round trips.
     cached_capabilities: HashMap<String, bool>,
 
+    /// The UIDVALIDITY of each mailbox when it was last opened, to notice
+    /// UIDs that no longer mean the same messages.
+    uid_validities: HashMap<String, u32>,
+
+    /// The trash mailbox, once found.
+    trash: Option<String>,
+
+    /// The untagged lines async-imap cannot parse, for [`Self::raw_command`].
+    set_aside: SetAside,
+
     /// Whether the session has been explicitly closed.
     closed: bool,
 }
@@ -165,7 +320,10 @@ where
     /// Returns an error if the LOGOUT command fails.
     pub async fn close(mut self) -> Result<(), ImapError> {
         self.closed = true;
-        self.session.logout().await.or_raise(|| ImapError::Logout)
+        timed_out(
+            self.session.logout().await.or_raise(|| ImapError::Logout),
+            "LOGOUT",
+        )
     }
 
     /// Connect and login to the IMAP server described by `base`.
@@ -176,128 +334,41 @@ where
         feature = "tracing",
         tracing::instrument(level = "trace", skip(base), ret, err(level = "info"))
     )]
-    #[expect(clippy::too_many_lines, reason = "many auth methods")]
     pub async fn connect_base(base: &BaseConfig) -> Result<Self, ImapError> {
         #[cfg(feature = "tracing")]
         tracing::trace!(?base);
 
-        let server = base.server.as_ref().ok_or_raise(|| ImapError::NoServer)?;
-
-        let port = base.port.unwrap_or(143);
-        let mode = base.mode.clone().unwrap_or_default();
-
-        let tcp = TcpStream::connect((server.as_str(), port))
-            .await
-            .or_raise(|| ImapError::Connect {
-                server: server.clone(),
-                port,
-            })?;
-
-        let (stream, greeting_consumed): (ImapStream, bool) =
-            build_stream(tcp, &mode, server, port)
-                .await
-                .or_raise(|| ImapError::TlsSetup)?;
-
-        let mut client = async_imap::Client::new(stream);
-
-        if !greeting_consumed {
-            client
-                .read_response()
-                .await
-                .or_raise(|| ImapError::Greeting)?;
-        }
-
-        let username = base
-            .username
-            .as_ref()
-            .ok_or_raise(|| ImapError::NoUsername)?;
-
-        let session =
-            match base.auth.unwrap_or_default() {
-                AuthMethod::Login => {
-                    let password = base.password().or_raise(|| ImapError::Password)?;
-                    client
-                        .login(username, password)
-                        .await
-                        .map_err(|(err, _client)| err)
-                        .or_raise(|| ImapError::Login { method: "LOGIN" })?
-                },
-                AuthMethod::Plain => {
-                    let password = base.password().or_raise(|| ImapError::Password)?;
-                    client
-                        .authenticate("PLAIN", PlainAuth {
-                            user: username.clone(),
-                            password,
-                        })
-                        .await
-                        .map_err(|(err, _client)| err)
-                        .or_raise(|| ImapError::Login { method: "PLAIN" })?
-                },
-                AuthMethod::CramMd5 => {
-                    let password = base.password().or_raise(|| ImapError::Password)?;
-                    client
-                        .authenticate("CRAM-MD5", CramMd5Auth {
-                            user: username.clone(),
-                            password,
-                        })
-                        .await
-                        .map_err(|(err, _client)| err)
-                        .or_raise(|| ImapError::Login { method: "CRAM-MD5" })?
-                },
-                AuthMethod::ScramSha1 => {
-                    let password = base.password().or_raise(|| ImapError::Password)?;
-                    let auth = ScramAuth::new(b"SCRAM-SHA-1", username.clone(), password)
-                        .or_raise(|| ImapError::ScramSession {
-                            method: "SCRAM-SHA-1",
-                        })?;
-                    client
-                        .authenticate("SCRAM-SHA-1", auth)
-                        .await
-                        .map_err(|(err, _client)| err)
-                        .or_raise(|| ImapError::Login {
-                            method: "SCRAM-SHA-1",
-                        })?
-                },
-                AuthMethod::ScramSha256 => {
-                    let password = base.password().or_raise(|| ImapError::Password)?;
-                    let auth = ScramAuth::new(b"SCRAM-SHA-256", username.clone(), password)
-                        .or_raise(|| ImapError::ScramSession {
-                            method: "SCRAM-SHA-256",
-                        })?;
-                    client
-                        .authenticate("SCRAM-SHA-256", auth)
-                        .await
-                        .map_err(|(err, _client)| err)
-                        .or_raise(|| ImapError::Login {
-                            method: "SCRAM-SHA-256",
-                        })?
-                },
-                AuthMethod::XOAuth2 => {
-                    let token = base.oauth2_token().or_raise(|| ImapError::OAuth2Token)?;
-                    client
-                        .authenticate("XOAUTH2", XOAuth2Auth {
-                            user: username.clone(),
-                            token,
-                        })
-                        .await
-                        .map_err(|(err, _client)| err)
-                        .or_raise(|| ImapError::Login { method: "XOAUTH2" })?
-                },
-            };
+        Self::connect_diagnose(base, &mut Handshake::default()).await
+    }
 
-        let mut ret = Self {
+    /// Connect and login to the IMAP server described by `base`, keeping
+    /// what was learned on the way in `handshake`, even when it fails.
+    ///
+    /// # Errors
+    /// Returns an error if the connection, TLS setup, or login fails.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(base, handshake), ret, err(level = "info"))
+    )]
+    pub async fn connect_diagnose(
+        base: &BaseConfig,
+        handshake: &mut Handshake,
+    ) -> Result<Self, ImapError> {
+        let set_aside = SetAside::default();
+        let session = open_session(base, handshake, &set_aside).await?;
+
+        Ok(Self {
             session,
+            base: base.clone(),
+            selected: None,
             extra: None,
             filters: None,
             cached_capabilities: HashMap::new(),
+            uid_validities: HashMap::new(),
+            trash: None,
+            set_aside,
             closed: false,
-        };
-
-        if !ret.has_capability("UIDPLUS").await? {
-            bail!(ImapError::UidPlus);
-        }
-
-        Ok(ret)
+        })
     }
 
     #[cfg_attr(
@@ -314,6 +385,7 @@ where
         {
             test_base.mode = Some("plaintext".parse().expect("plaintext is a valid mode"));
         }
+        test_base.allow_plaintext_auth = true;
         Self::connect_base(&test_base).await
     }
 
@@ -352,12 +424,14 @@ where
             return Ok(cached_result);
         }
 
-        let has_capability = self
-            .session
-            .capabilities()
-            .await
-            .or_raise(|| ImapError::ImapCapabilities)?
-            .has_str(cap.as_ref());
+        let has_capability = timed_out(
+            self.session
+                .capabilities()
+                .await
+                .or_raise(|| ImapError::ImapCapabilities),
+            "CAPABILITY",
+        )?
+        .has_str(cap.as_ref());
 
         self.cached_capabilities
             .insert(cap.as_ref().to_owned(), has_capability);
@@ -365,8 +439,39 @@ where
         Ok(has_capability)
     }
 
-    /// Select a mailbox, flag the given UID sequence as `\Deleted`, then CLOSE
-    /// (which expunges the flagged messages).
+    /// Make sure messages can be deleted by UID: with UIDPLUS, with MOVE
+    /// when the command `moves` them, like archive, or moves them to the
+    /// trash, or with the `uidplus-fallback` when it is set, which is then
+    /// reported. Dry runs delete nothing and need none.
+    ///
+    /// With MOVE and the trash delete mode, deleting messages in the trash
+    /// mailbox itself still needs UIDPLUS or the fallback, which is then
+    /// checked for that mailbox alone, see [`Self::check_expunge`].
+    ///
+    /// # Errors
+    /// Returns an error if the server does not support UIDPLUS and the
+    /// fallback is not set.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn check_uidplus(&mut self, moves: bool) -> Result<(), ImapError> {
+        if self.base.dry_run
+            || self.has_capability("UIDPLUS").await?
+            || ((moves || self.base.delete_mode == Some(DeleteMode::Trash))
+                && self.has_capability("MOVE").await?)
+        {
+            return Ok(());
+        }
+        if !self.base.uidplus_fallback {
+            bail!(ImapError::UidPlus);
+        }
+        warn_uidplus_fallback();
+        Ok(())
+    }
+
+    /// Select a mailbox read-write, remembering it so it can be re-selected
+    /// after a reconnect.
     ///
     /// # Errors
     /// Imap errors can happen
@@ -374,234 +479,1469 @@ where
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    pub async fn delete_uids(&mut self, mailbox: &str, sequence: &str) -> Result<(), ImapError> {
-        self.session
-            .select(mailbox)
-            .await
-            .or_raise(|| ImapError::ImapSelect {
+    pub async fn select(&mut self, mailbox: &str) -> Result<Mailbox, ImapError> {
+        let mbx = timed_out(
+            self.session
+                .select(mailbox)
+                .await
+                .or_raise(|| ImapError::ImapSelect {
+                    mailbox: mailbox.to_owned(),
+                }),
+            "SELECT",
+        )?;
+        self.check_alive(&mbx).await?;
+        if let Some(after) = mbx.uid_validity
+            && let Some(before) = self.uid_validities.insert(mailbox.to_owned(), after)
+            && before != after
+        {
+            bail!(ImapError::UidValidity {
                 mailbox: mailbox.to_owned(),
-            })?;
+                before,
+                after,
+            });
+        }
+        self.selected = Some((mailbox.to_owned(), false));
+        Ok(mbx)
+    }
 
-        {
-            let mut stream = self
-                .session
-                .uid_store(sequence, "+FLAGS (\\Deleted)")
+    /// Examine a mailbox (read-only select), remembering it so it can be
+    /// re-examined after a reconnect.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn examine(&mut self, mailbox: &str) -> Result<Mailbox, ImapError> {
+        let mbx = timed_out(
+            self.session
+                .examine(mailbox)
                 .await
-                .or_raise(|| ImapError::UidStore)?;
-            while stream
-                .try_next()
+                .or_raise(|| ImapError::ImapExamine {
+                    mailbox: mailbox.to_owned(),
+                }),
+            "EXAMINE",
+        )?;
+        self.check_alive(&mbx).await?;
+        if let Some(uid_validity) = mbx.uid_validity {
+            self.uid_validities.insert(mailbox.to_owned(), uid_validity);
+        }
+        self.selected = Some((mailbox.to_owned(), true));
+        Ok(mbx)
+    }
+
+    /// async-imap reports a connection closed during SELECT or EXAMINE as an
+    /// empty mailbox. Servers always send UIDVALIDITY, so when it is missing
+    /// make sure the session is still there.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, mbx), err(level = "info"))
+    )]
+    async fn check_alive(&mut self, mbx: &Mailbox) -> Result<(), ImapError> {
+        if mbx.uid_validity.is_none() {
+            self.session
+                .noop()
                 .await
-                .or_raise(|| ImapError::Stream)?
-                .is_some()
-            {}
+                .or_raise(|| ImapError::ConnectionLost)?;
         }
+        Ok(())
+    }
 
-        self.session
-            .close()
-            .await
-            .or_raise(|| ImapError::ImapClose)?;
+    /// Replace a broken session with a new one, retrying with exponential
+    /// backoff, then restore the previously selected mailbox.
+    ///
+    /// # Errors
+    /// Returns the last connection error once `reconnect-attempts` is exhausted.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn reconnect(&mut self) -> Result<(), ImapError> {
+        let attempts = self
+            .base
+            .reconnect_attempts
+            .unwrap_or(DEFAULT_RECONNECT_ATTEMPTS);
+        let mut delay = std::time::Duration::from_secs(
+            self.base.reconnect_delay.unwrap_or(DEFAULT_RECONNECT_DELAY),
+        );
+        let mut errors = vec![];
 
-        Ok(())
+        for attempt in 1..=attempts {
+            sleep(delay).await;
+            delay = delay.saturating_mul(2);
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(attempt, attempts, "reconnecting to IMAP server");
+            #[cfg(not(feature = "tracing"))]
+            let _ = attempt;
+
+            match open_session(&self.base, &mut Handshake::default(), &self.set_aside).await {
+                Ok(session) => {
+                    self.session = session;
+                    if let Some((mailbox, read_only)) = self.selected.take() {
+                        if read_only {
+                            self.examine(&mailbox).await?;
+                        } else {
+                            self.select(&mailbox).await?;
+                        }
+                    }
+                    return Ok(());
+                },
+                Err(err) => errors.push(err),
+            }
+        }
+
+        Err(Exn::raise_all(ImapError::Reconnect { attempts }, errors))
     }
 
-    /// Get a list of mailboxes given filters, returns a `BTreeMap` so it is
-    /// sorted and stable.
+    /// Run `op`, and if it fails because the connection dropped, reconnect
+    /// and run it again, up to `reconnect-attempts` times.
     ///
-    /// We use a map to be able to have generic filters at the beginning of the
-    /// configuration that are overwritten by more specific filters afterwards.
+    /// `op` must be safe to run again from the start, e.g. processing one
+    /// whole mailbox. It is not run again once messages may have been
+    /// copied without MOVE, see [`Self::move_uids`].
     ///
     /// # Errors
-    /// Many errors can happen
+    /// Returns the error from `op`, or `on_reconnect_error` if reconnecting
+    /// failed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err(level = "info"))
+    )]
+    pub async fn with_reconnect<R, E>(
+        &mut self,
+        mut op: impl AsyncFnMut(&mut Self) -> Result<R, E>,
+        on_reconnect_error: impl Fn() -> E,
+    ) -> Result<R, E>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let attempts = self
+            .base
+            .reconnect_attempts
+            .unwrap_or(DEFAULT_RECONNECT_ATTEMPTS);
+        let mut retries = 0;
+
+        loop {
+            match op(self).await {
+                Err(err)
+                    if retries < attempts
+                        && is_connection_lost(err.frame())
+                        && !is_copied(err.frame())
+                        && !matches!(elapsed(err.frame()), Some(Elapsed::Total(_))) =>
+                {
+                    retries += 1;
+
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(?err, "IMAP connection lost");
+
+                    if let Err(reconnect_err) = self.reconnect().await {
+                        return Err(Exn::raise_all(on_reconnect_error(), [
+                            err.raise(ImapError::ConnectionLost),
+                            reconnect_err,
+                        ]));
+                    }
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Select a mailbox, flag the given UID sequence as `\Deleted`, then
+    /// expunge exactly those UIDs.
+    ///
+    /// With the trash delete mode, they are moved to the trash mailbox
+    /// instead, see [`Self::destination`].
+    ///
+    /// Nothing is flagged or copied when they could not be expunged, see
+    /// [`Self::check_expunge`].
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn delete_uids(&mut self, mailbox: &str, sequence: &str) -> Result<(), ImapError> {
+        let destination = self.destination(mailbox).await?;
+        if destination.is_none() {
+            self.check_expunge().await?;
+        }
+        self.select(mailbox).await?;
+        if let Some(trash) = destination {
+            return self.move_uids(sequence, &trash).await;
+        }
+        self.uid_store(sequence, "+FLAGS (\\Deleted)").await?;
+        self.expunge_uids(sequence).await
+    }
+
+    /// Where deleting messages of `mailbox` moves them: the trash mailbox
+    /// with the trash delete mode, unless it is `mailbox` itself, or `None`
+    /// when they are expunged.
+    ///
+    /// The trash mailbox is `trash-mailbox`, or else the one with the
+    /// `\Trash` special use.
+    ///
+    /// # Errors
+    /// Returns an error if listing the mailboxes fails, or none is the trash.
     #[cfg_attr(
         feature = "tracing",
         tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
     )]
-    pub async fn list(&mut self) -> Result<BTreeMap<String, ListResult<T>>, ImapError> {
-        let mut mailboxes: BTreeMap<String, ListResult<T>> = BTreeMap::new();
+    pub async fn destination(&mut self, mailbox: &str) -> Result<Option<String>, ImapError> {
+        if self.base.delete_mode != Some(DeleteMode::Trash) {
+            return Ok(None);
+        }
+        if self.trash.is_none() {
+            self.trash = Some(match self.base.trash_mailbox {
+                Some(ref trash) => trash.clone(),
+                None => self.find_trash().await?,
+            });
+        }
+        Ok(self.trash.clone().filter(|trash| trash != mailbox))
+    }
 
-        for filter in self
-            .filters
-            .clone()
-            .unwrap_or_else(|| vec![Filter::default()])
-        {
-            let mut found = false;
+    /// The mailbox with the `\Trash` special use.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
+    )]
+    async fn find_trash(&mut self) -> Result<String, ImapError> {
+        let names = self
+            .list_names(Some(""), Some("*"))
+            .await
+            .or_raise(|| ImapError::ListTrash)?;
+        names
+            .iter()
+            .find(|name| name.attributes().contains(&NameAttribute::Trash))
+            .map(|name| name.name().to_owned())
+            .ok_or_raise(|| ImapError::NoTrash)
+    }
 
-            let names: Vec<_> = {
-                let stream = self
-                    .session
-                    .list(filter.reference.as_deref(), filter.pattern.as_deref())
+    /// Move the given UID sequence of the selected mailbox to `destination`
+    /// with UID MOVE, or without MOVE, copy them there then delete them.
+    ///
+    /// Once the copy may have happened, errors are raised as
+    /// [`ImapError::Copied`], so that [`Self::with_reconnect`] does not copy
+    /// them a second time.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn move_uids(&mut self, sequence: &str, destination: &str) -> Result<(), ImapError> {
+        if self.has_capability("MOVE").await? {
+            return timed_out(
+                self.session
+                    .uid_mv(sequence, destination)
                     .await
-                    .or_raise(|| ImapError::ImapList {
-                        filter: format!("{filter:?}"),
-                    })?;
-                stream.try_collect().await.or_raise(|| ImapError::Stream)?
-            };
-
-            for mailbox in names
-                .iter()
-                .filter(|mbx| !mbx.attributes().contains(&NameAttribute::NoSelect))
-                .filter(|mbx| {
-                    filter
-                        .include_re
-                        .as_ref()
-                        .is_none_or(|re| re.is_match(mbx.name()))
-                })
-                .filter(|mbx| {
-                    filter
-                        .exclude_re
-                        .as_ref()
-                        .is_none_or(|re| !re.is_match(mbx.name()))
-                })
-            {
-                found = true;
-                mailboxes.insert(mailbox.name().to_owned(), ListResult {
-                    extra: filter.extra.clone().or_else(|| self.extra.clone()),
-                });
-            }
+                    .or_raise(|| ImapError::UidMove {
+                        mailbox: destination.to_owned(),
+                    }),
+                "UID MOVE",
+            );
+        }
 
-            if !found {
-                bail!(ImapError::ImapListEmpty {
-                    filter: format!("{filter:?}")
-                });
+        self.check_expunge().await?;
+        let copied = || ImapError::Copied {
+            mailbox: destination.to_owned(),
+        };
+        timed_out(
+            self.session
+                .uid_copy(sequence, destination)
+                .await
+                .or_raise(|| ImapError::UidCopy {
+                    mailbox: destination.to_owned(),
+                }),
+            "UID COPY",
+        )
+        .map_err(|err| {
+            // The server may have copied them before the connection dropped
+            if is_connection_lost(err.frame()) {
+                err.raise(copied())
+            } else {
+                err
             }
+        })?;
+        async {
+            self.uid_store(sequence, "+FLAGS (\\Deleted)").await?;
+            self.expunge_uids(sequence).await
         }
+        .await
+        .or_raise(copied)
+    }
 
-        Ok(mailboxes)
+    /// Run `UID STORE` with `query` on the given UID sequence in the
+    /// selected mailbox.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    async fn uid_store(&mut self, sequence: &str, query: &str) -> Result<(), ImapError> {
+        timed_out(
+            async {
+                let mut stream = self
+                    .session
+                    .uid_store(sequence, query)
+                    .await
+                    .or_raise(|| ImapError::UidStore)?;
+                while stream
+                    .try_next()
+                    .await
+                    .or_raise(|| ImapError::Stream)?
+                    .is_some()
+                {}
+                Ok(())
+            }
+            .await,
+            "UID STORE",
+        )
     }
-}
 
-#[cfg_attr(
-    feature = "tracing",
-    tracing::instrument(
-        level = "trace",
-        skip(tcp, mode, server, port),
-        ret,
-        err(level = "debug")
-    )
-)]
-/// Wrap a raw `TcpStream` in the appropriate TLS layer (or leave as-is for plaintext),
-/// returning the opaque `ImapStream` and whether the server greeting has already been consumed.
-#[cfg_attr(
-    not(any(feature = "__tls", feature = "tracing")),
-    expect(clippy::unused_async, reason = "only needed when using tls")
-)]
-async fn build_stream(
-    tcp: TcpStream,
-    mode: &Mode,
+    /// Run `UID SEARCH` with `query` in the selected mailbox.
+    ///
+    /// # Errors
+    /// Imap errors can happen
     #[cfg_attr(
-        not(feature = "__tls"),
-        expect(unused_variables, reason = "only needed when using tls")
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
     )]
-    server: &str,
-    port: u16,
-) -> Result<(ImapStream, bool), ImapError> {
-    match *mode {
-        Mode::Plaintext => Ok((Box::new(tcp), false)),
-        #[cfg(feature = "__tls")]
-        Mode::Tls => {
-            let tls = wrap_tls(tcp, server)
+    pub async fn uid_search(&mut self, query: &str) -> Result<HashSet<Uid>, ImapError> {
+        timed_out(
+            self.session
+                .uid_search(query)
                 .await
-                .or_raise(|| ImapError::WrapTls)?;
-            Ok((tls, false))
+                .or_raise(|| ImapError::UidSearch),
+            "UID SEARCH",
+        )
+    }
+
+    /// Run `UID FETCH` with `query` on the given UID sequence in the selected
+    /// mailbox.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn uid_fetch(
+        &mut self,
+        sequence: &str,
+        query: &str,
+    ) -> Result<Vec<Fetch>, ImapError> {
+        timed_out(
+            async {
+                let stream = self
+                    .session
+                    .uid_fetch(sequence, query)
+                    .await
+                    .or_raise(|| ImapError::UidFetch)?;
+                stream.try_collect().await.or_raise(|| ImapError::Stream)
+            }
+            .await,
+            "UID FETCH",
+        )
+    }
+
+    /// Run `LIST` on `pattern` in `reference`, the pattern being sent as is.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn list_names(
+        &mut self,
+        reference: Option<&str>,
+        pattern: Option<&str>,
+    ) -> Result<Vec<Name>, ImapError> {
+        timed_out(
+            async {
+                let stream =
+                    self.session
+                        .list(reference, pattern)
+                        .await
+                        .or_raise(|| ImapError::List {
+                            reference: reference.unwrap_or_default().to_owned(),
+                            pattern: pattern.unwrap_or_default().to_owned(),
+                        })?;
+                stream.try_collect().await.or_raise(|| ImapError::Stream)
+            }
+            .await,
+            "LIST",
+        )
+    }
+
+    /// Create `mailbox`.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn create(&mut self, mailbox: &str) -> Result<(), ImapError> {
+        timed_out(
+            self.session
+                .create(mailbox)
+                .await
+                .or_raise(|| ImapError::Create {
+                    mailbox: mailbox.to_owned(),
+                }),
+            "CREATE",
+        )
+    }
+
+    /// Delete `mailbox`.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn delete(&mut self, mailbox: &str) -> Result<(), ImapError> {
+        timed_out(
+            self.session
+                .delete(mailbox)
+                .await
+                .or_raise(|| ImapError::Delete {
+                    mailbox: mailbox.to_owned(),
+                }),
+            "DELETE",
+        )
+    }
+
+    /// The capabilities of the server, by name, see [`capability_name`].
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
+    )]
+    pub async fn capability_names(&mut self) -> Result<Vec<String>, ImapError> {
+        let capabilities = timed_out(
+            self.session
+                .capabilities()
+                .await
+                .or_raise(|| ImapError::ImapCapabilities),
+            "CAPABILITY",
+        )?;
+        Ok(capabilities.iter().map(capability_name).collect())
+    }
+
+    /// Send `identification` with `ID`, and return the server's.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, identification), ret, err(level = "info"))
+    )]
+    pub async fn id(
+        &mut self,
+        identification: impl IntoIterator<Item = (&str, Option<&str>)> + Send,
+    ) -> Result<Option<HashMap<String, String>>, ImapError> {
+        timed_out(
+            self.session
+                .id(identification)
+                .await
+                .or_raise(|| ImapError::Id),
+            "ID",
+        )
+    }
+
+    /// The quota roots of `mailbox` and their quotas.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn quota_root(
+        &mut self,
+        mailbox: &str,
+    ) -> Result<(Vec<QuotaRoot>, Vec<Quota>), ImapError> {
+        timed_out(
+            self.session
+                .get_quota_root(mailbox)
+                .await
+                .or_raise(|| ImapError::QuotaRoot {
+                    mailbox: mailbox.to_owned(),
+                }),
+            "GETQUOTAROOT",
+        )
+    }
+
+    /// Make sure messages flagged `\Deleted` can then be expunged: with
+    /// UIDPLUS, or with the `uidplus-fallback`. Checked before flagging or
+    /// copying them, so that they are not left flagged for the next client
+    /// to expunge.
+    ///
+    /// # Errors
+    /// Returns an error if the server does not support UIDPLUS and the
+    /// fallback is not set.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn check_expunge(&mut self) -> Result<(), ImapError> {
+        if !self.base.uidplus_fallback && !self.has_capability("UIDPLUS").await? {
+            bail!(ImapError::UidPlus);
+        }
+        Ok(())
+    }
+
+    /// Run `UID EXPUNGE` on the given UID sequence in the selected mailbox, so
+    /// that messages flagged `\Deleted` by other clients are left alone.
+    ///
+    /// Without UIDPLUS, or if the server rejects the command, the
+    /// `uidplus-fallback` is used when it is set. Otherwise, when the server
+    /// rejects it, the messages are unflagged again.
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn expunge_uids(&mut self, sequence: &str) -> Result<(), ImapError> {
+        self.check_expunge().await?;
+        if !self.has_capability("UIDPLUS").await? {
+            return self.expunge_uids_without_uidplus(sequence).await;
+        }
+
+        // The streaming `uid_expunge` helper swallows the tagged status, so we
+        // check it ourselves to notice servers that reject the command.
+        match self
+            .session
+            .run_command_and_check_ok(format!("UID EXPUNGE {sequence}"))
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(async_imap::error::Error::No(_) | async_imap::error::Error::Bad(_))
+                if self.base.uidplus_fallback =>
+            {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(sequence, "UID EXPUNGE rejected, using uidplus-fallback");
+
+                self.expunge_uids_without_uidplus(sequence).await
+            },
+            Err(err @ (async_imap::error::Error::No(_) | async_imap::error::Error::Bad(_))) => {
+                let rejected = Exn::new(err).raise(ImapError::UidExpunge);
+                match self.uid_store(sequence, "-FLAGS.SILENT (\\Deleted)").await {
+                    Ok(()) => Err(rejected),
+                    Err(unflag) => Err(Exn::raise_all(ImapError::UidExpunge, [rejected, unflag])),
+                }
+            },
+            Err(err) => timed_out(Err(err).or_raise(|| ImapError::UidExpunge), "UID EXPUNGE"),
+        }
+    }
+
+    /// Expunge the given UID sequence, flagged `\Deleted`, with a plain
+    /// EXPUNGE: the other messages flagged `\Deleted` in the mailbox are
+    /// unflagged during the EXPUNGE, then flagged again.
+    ///
+    /// If the connection drops in between, those stay unflagged in the
+    /// mailbox, nothing else is lost.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    async fn expunge_uids_without_uidplus(&mut self, sequence: &str) -> Result<(), ImapError> {
+        warn_uidplus_fallback();
+
+        let others = self
+            .uid_search(&format!("DELETED NOT UID {sequence}"))
+            .await?;
+        let others = (!others.is_empty()).then(|| ids_list_to_collapsed_sequence(&others));
+
+        if let Some(ref others) = others {
+            self.uid_store(others, "-FLAGS.SILENT (\\Deleted)").await?;
+        }
+        let expunged = timed_out(
+            self.session
+                .run_command_and_check_ok("EXPUNGE")
+                .await
+                .or_raise(|| ImapError::Expunge),
+            "EXPUNGE",
+        );
+        if let Some(ref others) = others {
+            self.uid_store(others, "+FLAGS.SILENT (\\Deleted)").await?;
+        }
+        expunged
+    }
+
+    /// Get a list of mailboxes given filters, returns a `BTreeMap` so it is
+    /// sorted and stable.
+    ///
+    /// We use a map to be able to have generic filters at the beginning of the
+    /// configuration that are overwritten by more specific filters afterwards.
+    ///
+    /// # Errors
+    /// Many errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
+    )]
+    pub async fn list(&mut self) -> Result<BTreeMap<String, ListResult<T>>, ImapError> {
+        let mut mailboxes: BTreeMap<String, ListResult<T>> = BTreeMap::new();
+
+        for filter in self
+            .filters
+            .clone()
+            .unwrap_or_else(|| vec![Filter::default()])
+        {
+            let mut found = false;
+
+            let names = self
+                .list_names(filter.reference.as_deref(), filter.pattern.as_deref())
+                .await
+                .or_raise(|| ImapError::ImapList {
+                    filter: format!("{filter:?}"),
+                })?;
+
+            for mailbox in names
+                .iter()
+                .filter(|mbx| !mbx.attributes().contains(&NameAttribute::NoSelect))
+                .filter(|mbx| {
+                    filter
+                        .include_re
+                        .as_ref()
+                        .is_none_or(|re| re.is_match(mbx.name()))
+                })
+                .filter(|mbx| {
+                    filter
+                        .exclude_re
+                        .as_ref()
+                        .is_none_or(|re| !re.is_match(mbx.name()))
+                })
+            {
+                found = true;
+                mailboxes.insert(mailbox.name().to_owned(), ListResult {
+                    extra: filter.extra.clone().or_else(|| self.extra.clone()),
+                });
+            }
+
+            if !found {
+                bail!(ImapError::ImapListEmpty {
+                    filter: format!("{filter:?}")
+                });
+            }
+        }
+
+        Ok(mailboxes)
+    }
+
+    /// Run `command`, whose answer async-imap cannot parse, like NAMESPACE,
+    /// and return the untagged lines of the answer, as the server sent them.
+    ///
+    /// The command is sent by async-imap, with its own tag, and the answer
+    /// read by it up to the tagged line, untagged responses it had already
+    /// read included. The lines it has no parser for are kept away from it
+    /// by [`SetAsideStream`], and come before the response read with them.
+    ///
+    /// # Errors
+    /// Returns an error if the connection fails or the server does not
+    /// answer OK.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret, err(level = "info"))
+    )]
+    pub async fn raw_command(&mut self, command: &str) -> Result<Vec<String>, ImapError> {
+        let raise = || ImapError::Raw {
+            command: command.to_owned(),
+        };
+        // Lines set aside during earlier commands are not part of the answer
+        let _ = self.set_aside.take();
+        let tag = self.session.run_command(command).await.or_raise(raise)?;
+
+        let mut lines = vec![];
+        loop {
+            let response = self
+                .session
+                .read_response()
+                .await
+                .or_raise(raise)?
+                .ok_or_raise(raise)?;
+            lines.extend(self.set_aside.take());
+            let text = response_text(response.borrow_owner());
+            if response.request_id() != Some(&tag) {
+                lines.push(text);
+                continue;
+            }
+            if let Response::Done {
+                status: Status::Ok, ..
+            } = *response.parsed()
+            {
+                return Ok(lines);
+            }
+            bail!(ImapError::RawRefused {
+                command: command.to_owned(),
+                response: text
+                    .strip_prefix(&format!("{} ", tag.0))
+                    .unwrap_or(&text)
+                    .to_owned(),
+            });
+        }
+    }
+}
+
+/// The destination of the messages deleted without being moved to the
+/// trash, see [`Imap::destination`].
+pub const EXPUNGED: &str = "expunged";
+
+/// Warn, once per process and even without tracing, that messages are
+/// deleted without UID EXPUNGE.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+fn warn_uidplus_fallback() {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("UID EXPUNGE is not usable, using uidplus-fallback");
+
+        #[expect(clippy::print_stderr, reason = "must be seen even without logging")]
+        {
+            eprintln!(
+                "WARNING: UID EXPUNGE is not usable on this server, messages are deleted with uidplus-fallback, unflagging the other \\Deleted messages during each EXPUNGE"
+            );
+        }
+    });
+}
+
+/// Whether an error tree was caused by the IMAP connection going away, as
+/// opposed to the server refusing a command.
+fn is_connection_lost(frame: &Frame) -> bool {
+    matches!(
+        frame.error().downcast_ref::<async_imap::error::Error>(),
+        Some(&(async_imap::error::Error::Io(_) | async_imap::error::Error::ConnectionLost))
+    ) || frame.children().iter().any(is_connection_lost)
+}
+
+/// Whether an error tree went past copying messages, so that running it again
+/// would copy them twice.
+fn is_copied(frame: &Frame) -> bool {
+    matches!(
+        frame.error().downcast_ref::<ImapError>(),
+        Some(&ImapError::Copied { .. })
+    ) || frame.children().iter().any(is_copied)
+}
+
+/// The text of the NO the server answered, if it refused the command of an
+/// error tree.
+pub fn refusal(frame: &Frame) -> Option<&str> {
+    if let Some(err) = frame.error().downcast_ref::<async_imap::error::Error>()
+        && let async_imap::error::Error::No(ref reason) = *err
+    {
+        return Some(reason);
+    }
+    frame.children().iter().find_map(refusal)
+}
+
+/// The timeout an error tree was caused by, if any.
+fn elapsed(frame: &Frame) -> Option<Elapsed> {
+    if let Some(err) = frame.error().downcast_ref::<async_imap::error::Error>()
+        && let async_imap::error::Error::Io(ref err) = *err
+        && err.kind() == std::io::ErrorKind::TimedOut
+        && let Some(elapsed) = err.get_ref().and_then(|err| err.downcast_ref::<Elapsed>())
+    {
+        return Some(*elapsed);
+    }
+    frame.children().iter().find_map(elapsed)
+}
+
+/// Raise `ImapError::Timeout` over an error caused by a timeout, so that it
+/// can be told apart from the server refusing `operation`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(result))
+)]
+fn timed_out<R>(result: Result<R, ImapError>, operation: &'static str) -> Result<R, ImapError> {
+    result.map_err(|err| {
+        if elapsed(err.frame()).is_some() {
+            err.raise(ImapError::Timeout { operation })
+        } else {
+            err
+        }
+    })
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(base, handshake), err(level = "info"))
+)]
+/// Open a session to the server described by `base`, giving up after the
+/// connect timeout.
+async fn open_session(
+    base: &BaseConfig,
+    handshake: &mut Handshake,
+    set_aside: &SetAside,
+) -> Result<Session<ImapStream>, ImapError> {
+    let timeouts = Timeouts::new(base);
+    match timeouts.connect() {
+        Some(deadline) => tokio::time::timeout_at(
+            deadline,
+            Box::pin(connect_session(base, timeouts, handshake, set_aside)),
+        )
+        .await
+        .or_raise(|| ImapError::Timeout {
+            operation: "connect",
+        })?,
+        None => Box::pin(connect_session(base, timeouts, handshake, set_aside)).await,
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(base, handshake), err(level = "info"))
+)]
+/// Open a TCP connection to the server described by `base`, or start its
+/// tunnel command, set up TLS as requested, and authenticate, keeping what
+/// was learned on the way in `handshake`.
+#[expect(clippy::too_many_lines, reason = "many auth methods")]
+async fn connect_session(
+    base: &BaseConfig,
+    timeouts: Timeouts,
+    handshake: &mut Handshake,
+    set_aside: &SetAside,
+) -> Result<Session<ImapStream>, ImapError> {
+    let transport = if let Some(ref tunnel) = base.tunnel {
+        // The tunnel is trusted like an encrypted connection, it is either
+        // local or encrypted by its command, like ssh.
+        Transport {
+            stream: Box::new(TunnelStream::spawn(tunnel).or_raise(|| ImapError::Tunnel)?),
+            greeting_consumed: false,
+            encrypted: true,
+        }
+    } else {
+        let server = base.server.as_ref().ok_or_raise(|| ImapError::NoServer)?;
+
+        let port = base.port.unwrap_or(143);
+        let mode = base.mode.clone().unwrap_or_default();
+
+        let tcp = if let Some(ref proxy) = base.proxy {
+            let proxy = Proxy::parse(proxy).or_raise(|| ImapError::ProxyUrl)?;
+            let mut tcp = TcpStream::connect((proxy.host.as_str(), proxy.port))
+                .await
+                .or_raise(|| ImapError::ProxyConnect {
+                    proxy: proxy.clone(),
+                })?;
+            proxy.handshake(&mut tcp, server, port).await.or_raise(|| {
+                ImapError::ProxyHandshake {
+                    proxy: proxy.clone(),
+                    server: server.clone(),
+                    port,
+                }
+            })?;
+            tcp
+        } else {
+            TcpStream::connect((server.as_str(), port))
+                .await
+                .or_raise(|| ImapError::Connect {
+                    server: server.clone(),
+                    port,
+                })?
+        };
+
+        let policy = base
+            .tls_policy
+            .unwrap_or_else(|| TlsPolicy::default_for(&mode));
+
+        build_stream(tcp, &mode, policy, server, port, base, handshake)
+            .await
+            .or_raise(|| ImapError::TlsSetup)?
+    };
+
+    let stream: ImapStream = Box::new(TimeoutStream::new(transport.stream, timeouts));
+    let stream = match transcript::sink(base).or_raise(|| ImapError::Transcript)? {
+        Some(sink) => Box::new(TranscriptStream::new(stream, sink)),
+        None => stream,
+    };
+    let stream: ImapStream = Box::new(SetAsideStream::new(stream, set_aside.clone()));
+
+    let mut client = async_imap::Client::new(stream);
+
+    if !transport.greeting_consumed {
+        let greeting = client
+            .read_response()
+            .await
+            .or_raise(|| ImapError::Greeting)?
+            .ok_or_raise(|| ImapError::Greeting)?;
+        handshake.greeting = Some(response_text(greeting.borrow_owner()));
+
+        if let Response::Data {
+            status: Status::PreAuth,
+            ..
+        } = *greeting.parsed()
+        {
+            // Anyone on the path of a plaintext connection could have
+            // greeted with PREAUTH, and would then see everything
+            if !transport.encrypted && !base.allow_plaintext_auth {
+                bail!(ImapError::PlaintextPreAuth);
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::info!("the server greeted with PREAUTH, skipping authentication");
+            handshake.mechanism = Some("PREAUTH");
+
+            // This relies on async-imap sending LOGIN as a single line,
+            // quoting both strings, which it does for strings without
+            // CR or LF: a literal would make it wait for a continuation
+            // the server never sends. `into_inner` drops what async-imap
+            // read past the greeting, but the server sends nothing more
+            // before the first command.
+            return async_imap::Client::new(
+                Box::new(PreAuthStream::new(client.into_inner())) as ImapStream
+            )
+            .login("preauth", "preauth")
+            .await
+            .map_err(|(err, _client)| err)
+            .or_raise(|| ImapError::PreAuth);
+        }
+    }
+
+    let mut capabilities = None;
+    let auth = match base.auth.unwrap_or_default() {
+        AuthMethod::Auto => {
+            let caps = pre_auth_capabilities(&mut client).await?;
+            let auth = AuthMethod::negotiate(
+                &caps,
+                base.has_oauth2(),
+                base.has_password(),
+                transport.encrypted || base.allow_plaintext_auth,
+            )
+            .ok_or_raise(|| {
+                let mut offered: Vec<_> = caps
+                    .iter()
+                    .filter(|cap| cap.starts_with("AUTH=") || *cap == "LOGINDISABLED")
+                    .cloned()
+                    .collect();
+                offered.sort();
+                ImapError::NoMechanism { offered }
+            })?;
+            capabilities = Some(caps);
+            auth
+        },
+        auth => auth,
+    };
+    if handshake.diagnose && capabilities.is_none() {
+        capabilities = Some(pre_auth_capabilities(&mut client).await?);
+    }
+    if let Some(ref caps) = capabilities {
+        let mut caps: Vec<_> = caps.iter().cloned().collect();
+        caps.sort();
+        handshake.capabilities = Some(caps);
+    }
+    handshake.mechanism = Some(auth.name());
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(mechanism = auth.name(), "authenticating");
+
+    if !transport.encrypted && auth.sends_cleartext_credentials() && !base.allow_plaintext_auth {
+        bail!(ImapError::PlaintextAuth {
+            method: auth.name()
+        });
+    }
+
+    let login_disabled = match capabilities {
+        Some(ref caps) => caps.contains("LOGINDISABLED"),
+        None if auth == AuthMethod::Login => pre_auth_capabilities(&mut client)
+            .await?
+            .contains("LOGINDISABLED"),
+        None => false,
+    };
+    if auth == AuthMethod::Login && login_disabled {
+        bail!(ImapError::LoginDisabled);
+    }
+
+    let username = match base.username {
+        Some(ref username) => username.as_str(),
+        // The client certificate tells who we are
+        None if auth == AuthMethod::External => "",
+        None => bail!(ImapError::NoUsername),
+    };
+
+    // Dovecot and others let a master user act as another one with
+    // "user*master" when the mechanism has no authorization identity.
+    let (username, authzid) = match base.authorize_as {
+        None => (username.to_owned(), String::new()),
+        Some(ref target) => match auth {
+            AuthMethod::Plain | AuthMethod::External => (username.to_owned(), target.clone()),
+            AuthMethod::XOAuth2 | AuthMethod::OAuthBearer => {
+                bail!(ImapError::AuthorizeAs {
+                    method: auth.name()
+                })
+            },
+            _ => (format!("{target}*{username}"), String::new()),
+        },
+    };
+
+    let session = match auth {
+        AuthMethod::Login => {
+            let password = base.password().or_raise(|| ImapError::Password)?;
+            client
+                .login(username, password)
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login { method: "LOGIN" })?
+        },
+        AuthMethod::Plain => {
+            let password = base.password().or_raise(|| ImapError::Password)?;
+            client
+                .authenticate("PLAIN", PlainAuth {
+                    authzid,
+                    user: username,
+                    password,
+                })
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login { method: "PLAIN" })?
+        },
+        AuthMethod::CramMd5 => {
+            let password = base.password().or_raise(|| ImapError::Password)?;
+            client
+                .authenticate("CRAM-MD5", CramMd5Auth {
+                    user: username,
+                    password,
+                })
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login { method: "CRAM-MD5" })?
+        },
+        AuthMethod::ScramSha1 => {
+            let password = base.password().or_raise(|| ImapError::Password)?;
+            let auth = ScramAuth::new(b"SCRAM-SHA-1", username, password).or_raise(|| {
+                ImapError::ScramSession {
+                    method: "SCRAM-SHA-1",
+                }
+            })?;
+            client
+                .authenticate("SCRAM-SHA-1", auth)
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login {
+                    method: "SCRAM-SHA-1",
+                })?
+        },
+        AuthMethod::ScramSha256 => {
+            let password = base.password().or_raise(|| ImapError::Password)?;
+            let auth = ScramAuth::new(b"SCRAM-SHA-256", username, password).or_raise(|| {
+                ImapError::ScramSession {
+                    method: "SCRAM-SHA-256",
+                }
+            })?;
+            client
+                .authenticate("SCRAM-SHA-256", auth)
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login {
+                    method: "SCRAM-SHA-256",
+                })?
+        },
+        AuthMethod::XOAuth2 => {
+            let token = base
+                .oauth2_token()
+                .await
+                .or_raise(|| ImapError::OAuth2Token)?;
+            client
+                .authenticate("XOAUTH2", XOAuth2Auth {
+                    user: username,
+                    token,
+                })
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login { method: "XOAUTH2" })?
+        },
+        AuthMethod::OAuthBearer => {
+            let token = base
+                .oauth2_token()
+                .await
+                .or_raise(|| ImapError::OAuth2Token)?;
+            client
+                .authenticate("OAUTHBEARER", OAuthBearerAuth {
+                    user: username,
+                    host: base.server.clone().unwrap_or_default(),
+                    port: base.port.unwrap_or(143),
+                    token,
+                    sent: false,
+                })
+                .await
+                .map_err(|(err, _client)| err)
+                .or_raise(|| ImapError::Login {
+                    method: "OAUTHBEARER",
+                })?
         },
+        AuthMethod::External => client
+            .authenticate("EXTERNAL", ExternalAuth { authzid })
+            .await
+            .map_err(|(err, _client)| err)
+            .or_raise(|| ImapError::Login { method: "EXTERNAL" })?,
+        // Resolved to one of the above by the negotiation.
+        AuthMethod::Auto => bail!(ImapError::NoMechanism { offered: vec![] }),
+    };
+
+    Ok(session)
+}
+
+/// A connection ready to talk IMAP.
+#[derive(Debug)]
+struct Transport {
+    /// The stream, encrypted or not.
+    stream: ImapStream,
+    /// Whether the server greeting was already read, before STARTTLS.
+    greeting_consumed: bool,
+    /// Whether the stream is encrypted.
+    encrypted: bool,
+}
+
+impl Transport {
+    /// An unencrypted connection.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(stream))
+    )]
+    fn plain<S: AsyncStream + 'static>(stream: S, greeting_consumed: bool) -> Self {
+        Self {
+            stream: Box::new(stream),
+            greeting_consumed,
+            encrypted: false,
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "trace",
+        skip(tcp, mode, server, port, base, handshake),
+        ret,
+        err(level = "debug")
+    )
+)]
+/// Wrap a raw `TcpStream` in the appropriate TLS layer (or leave as-is for
+/// plaintext), and check the result against the TLS policy.
+#[cfg_attr(
+    not(any(feature = "__tls", feature = "tracing")),
+    expect(clippy::unused_async, reason = "only needed when using tls")
+)]
+#[cfg_attr(
+    not(feature = "__tls"),
+    expect(
+        clippy::needless_pass_by_ref_mut,
+        reason = "only needed when using tls"
+    )
+)]
+async fn build_stream(
+    tcp: TcpStream,
+    mode: &Mode,
+    policy: TlsPolicy,
+    #[cfg_attr(
+        not(feature = "__tls"),
+        expect(unused_variables, reason = "only needed when using tls")
+    )]
+    server: &str,
+    port: u16,
+    #[cfg_attr(
+        not(feature = "__tls"),
+        expect(unused_variables, reason = "only needed when using tls")
+    )]
+    base: &BaseConfig,
+    #[cfg_attr(
+        not(feature = "__tls"),
+        expect(unused_variables, reason = "only needed when using tls")
+    )]
+    handshake: &mut Handshake,
+) -> Result<Transport, ImapError> {
+    #[cfg(feature = "__tls")]
+    if policy == TlsPolicy::Never && matches!(*mode, Mode::Tls | Mode::StartTls | Mode::AutoTls) {
+        bail!(ImapError::TlsPolicyConflict {
+            mode: mode.clone(),
+            policy,
+        });
+    }
+
+    let transport = match *mode {
+        Mode::Plaintext => Transport::plain(tcp, false),
         #[cfg(feature = "__tls")]
-        Mode::StartTls => {
-            let mut plain_client = async_imap::Client::new(tcp);
-            plain_client
-                .read_response()
-                .await
-                .or_raise(|| ImapError::GreetingStarttls)?;
-            plain_client
-                .run_command_and_check_ok("STARTTLS", None)
+        Mode::Tls => Transport {
+            stream: wrap_tls(tcp, server, base, handshake)
                 .await
-                .or_raise(|| ImapError::StartTls)?;
-            let tcp_back: TcpStream = plain_client.into_inner();
-            let tls = wrap_tls(tcp_back, server)
-                .await
-                .or_raise(|| ImapError::WrapTls)?;
-            Ok((tls, true))
+                .or_raise(|| ImapError::WrapTls)?,
+            greeting_consumed: false,
+            encrypted: true,
         },
+        #[cfg(feature = "__tls")]
+        Mode::StartTls => starttls(tcp, server, base, handshake).await?,
         Mode::AutoTls => {
             #[cfg(feature = "__tls")]
             {
                 if port == 993 {
-                    let tls = wrap_tls(tcp, server)
-                        .await
-                        .or_raise(|| ImapError::WrapTls)?;
-                    Ok((tls, false))
+                    Transport {
+                        stream: wrap_tls(tcp, server, base, handshake)
+                            .await
+                            .or_raise(|| ImapError::WrapTls)?,
+                        greeting_consumed: false,
+                        encrypted: true,
+                    }
                 } else {
                     // Treat as StartTls
-                    let mut plain_client = async_imap::Client::new(tcp);
-                    plain_client
-                        .read_response()
-                        .await
-                        .or_raise(|| ImapError::GreetingStarttls)?;
-                    plain_client
-                        .run_command_and_check_ok("STARTTLS", None)
-                        .await
-                        .or_raise(|| ImapError::StartTls)?;
-                    let tcp_back: TcpStream = plain_client.into_inner();
-                    let tls = wrap_tls(tcp_back, server)
-                        .await
-                        .or_raise(|| ImapError::WrapTls)?;
-                    Ok((tls, true))
+                    starttls(tcp, server, base, handshake).await?
                 }
             }
             #[cfg(not(feature = "__tls"))]
             {
                 let _ = port;
-                Ok((Box::new(tcp), false))
+                Transport::plain(tcp, false)
             }
         },
         Mode::Auto => {
             #[cfg(feature = "__tls")]
             {
-                if port == 993 {
-                    let tls = wrap_tls(tcp, server)
-                        .await
-                        .or_raise(|| ImapError::WrapTls)?;
-                    return Ok((tls, false));
-                }
-                // Non-993: read greeting then attempt STARTTLS; fall back to plaintext if not supported
-                let mut plain_client = async_imap::Client::new(tcp);
-                plain_client
-                    .read_response()
-                    .await
-                    .or_raise(|| ImapError::Greeting)?;
-                // Try STARTTLS; if the server returns NO/BAD, fall back to plaintext
-                let starttls_ok = plain_client
-                    .run_command_and_check_ok("STARTTLS", None)
-                    .await
-                    .is_ok();
-                if starttls_ok {
-                    let tcp_back: TcpStream = plain_client.into_inner();
-                    let tls = wrap_tls(tcp_back, server)
-                        .await
-                        .or_raise(|| ImapError::WrapTls)?;
-                    Ok((tls, true))
+                if policy == TlsPolicy::Never {
+                    Transport::plain(tcp, false)
+                } else if port == 993 {
+                    Transport {
+                        stream: wrap_tls(tcp, server, base, handshake)
+                            .await
+                            .or_raise(|| ImapError::WrapTls)?,
+                        greeting_consumed: false,
+                        encrypted: true,
+                    }
                 } else {
-                    // Server does not support STARTTLS; use plaintext
-                    let stream: TcpStream = plain_client.into_inner();
-                    Ok((Box::new(stream), true))
+                    // Non-993: read greeting then attempt STARTTLS
+                    auto_starttls(tcp, policy, server, base, handshake).await?
                 }
             }
             #[cfg(not(feature = "__tls"))]
             {
                 let _ = port;
-                Ok((Box::new(tcp), false))
+                Transport::plain(tcp, false)
             }
         },
+    };
+
+    if policy == TlsPolicy::Require && !transport.encrypted {
+        bail!(ImapError::TlsRequired { mode: mode.clone() });
+    }
+
+    Ok(transport)
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "trace",
+        skip(tcp, server, base, handshake),
+        ret,
+        err(level = "debug")
+    )
+)]
+/// Read the greeting, send STARTTLS and wrap the connection in TLS.
+#[cfg(feature = "__tls")]
+async fn starttls(
+    tcp: TcpStream,
+    server: &str,
+    base: &BaseConfig,
+    handshake: &mut Handshake,
+) -> Result<Transport, ImapError> {
+    let mut plain_client = async_imap::Client::new(tcp);
+    handshake.greeting = plain_client
+        .read_response()
+        .await
+        .or_raise(|| ImapError::GreetingStarttls)?
+        .map(|greeting| response_text(greeting.borrow_owner()));
+    plain_client
+        .run_command_and_check_ok("STARTTLS", None)
+        .await
+        .or_raise(|| ImapError::StartTls)?;
+    upgrade_tls(plain_client, server, base, handshake).await
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "trace",
+        skip(tcp, server, base, handshake),
+        ret,
+        err(level = "debug")
+    )
+)]
+/// Read the greeting and try STARTTLS, falling back to plaintext when the
+/// server refuses it, unless the policy requires TLS.
+#[cfg(feature = "__tls")]
+async fn auto_starttls(
+    tcp: TcpStream,
+    policy: TlsPolicy,
+    server: &str,
+    base: &BaseConfig,
+    handshake: &mut Handshake,
+) -> Result<Transport, ImapError> {
+    let mut plain_client = async_imap::Client::new(tcp);
+    handshake.greeting = plain_client
+        .read_response()
+        .await
+        .or_raise(|| ImapError::Greeting)?
+        .map(|greeting| response_text(greeting.borrow_owner()));
+    match plain_client
+        .run_command_and_check_ok("STARTTLS", None)
+        .await
+    {
+        Ok(()) => upgrade_tls(plain_client, server, base, handshake).await,
+        Err(err) if policy == TlsPolicy::Require => Err(err)
+            .or_raise(|| ImapError::StartTls)
+            .or_raise(|| ImapError::StartTlsRefused),
+        Err(_err) => {
+            // Opportunistic: the server does not support STARTTLS, use plaintext
+            #[cfg(feature = "tracing")]
+            tracing::warn!(?_err, "STARTTLS failed, continuing in plaintext");
+
+            Ok(Transport::plain(plain_client.into_inner(), true))
+        },
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "trace",
+        skip(plain_client, server, base, handshake),
+        ret,
+        err(level = "debug")
+    )
+)]
+/// Wrap the connection in TLS once the server accepted STARTTLS.
+#[cfg(feature = "__tls")]
+async fn upgrade_tls(
+    plain_client: async_imap::Client<TcpStream>,
+    server: &str,
+    base: &BaseConfig,
+    handshake: &mut Handshake,
+) -> Result<Transport, ImapError> {
+    let tcp_back: TcpStream = plain_client.into_inner();
+    Ok(Transport {
+        stream: wrap_tls(tcp_back, server, base, handshake)
+            .await
+            .or_raise(|| ImapError::WrapTls)?,
+        greeting_consumed: true,
+        encrypted: true,
+    })
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(raw), ret)
+)]
+/// The first line of a response, from the bytes the server sent, which may
+/// be followed by the rest of the read buffer.
+fn response_text(raw: &[u8]) -> String {
+    let line = raw.split(|&b| b == b'\n').next().unwrap_or_default();
+    String::from_utf8_lossy(line).trim_end().to_owned()
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(client), ret, err(level = "debug"))
+)]
+/// Ask for the capabilities before authenticating, they can change after.
+async fn pre_auth_capabilities(
+    client: &mut async_imap::Client<ImapStream>,
+) -> Result<HashSet<String>, ImapError> {
+    let (sender, receiver) = async_channel::unbounded();
+    client
+        .run_command_and_check_ok("CAPABILITY", Some(sender))
+        .await
+        .or_raise(|| ImapError::PreAuthCapabilities)?;
+
+    let mut capabilities = HashSet::new();
+    while let Ok(response) = receiver.try_recv() {
+        if let UnsolicitedResponse::Other(ref data) = response
+            && let Response::Capabilities(ref caps) = *data.parsed()
+        {
+            capabilities.extend(caps.iter().map(|cap| capability_name(&cap.into())));
+        }
+    }
+    Ok(capabilities)
+}
+
+/// The name of a capability in upper-case, like `AUTH=PLAIN`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+pub fn capability_name(capability: &types::Capability) -> String {
+    match *capability {
+        types::Capability::Imap4rev1 => "IMAP4REV1".to_owned(),
+        types::Capability::Auth(ref mech) => format!("AUTH={}", mech.to_ascii_uppercase()),
+        types::Capability::Atom(ref atom) => atom.to_ascii_uppercase(),
     }
 }
 
 #[cfg_attr(
     feature = "tracing",
-    tracing::instrument(level = "trace", skip(tcp, server), ret, err(level = "debug"))
+    tracing::instrument(
+        level = "trace",
+        skip(tcp, server, base, handshake),
+        ret,
+        err(level = "debug")
+    )
 )]
 /// Wrap a `TcpStream` in a TLS layer using the OpenSSL backend.
 #[cfg(feature = "native-tls")]
-async fn wrap_tls(tcp: TcpStream, server: &str) -> Result<ImapStream, ImapError> {
-    let connector = native_tls::TlsConnector::new().or_raise(|| ImapError::NativeTlsConnector)?;
+async fn wrap_tls(
+    tcp: TcpStream,
+    server: &str,
+    base: &BaseConfig,
+    handshake: &mut Handshake,
+) -> Result<ImapStream, ImapError> {
+    let pin = base
+        .tls_pin_sha256
+        .as_deref()
+        .map(tls::parse_pin)
+        .transpose()
+        .or_raise(|| ImapError::TlsOptions)?;
+
+    let mut builder = native_tls::TlsConnector::builder();
+
+    if let Some(ref file) = base.tls_ca_file {
+        let certs = tls::pem_certificates(&tls::read_pem(file).or_raise(|| ImapError::TlsOptions)?);
+        if certs.is_empty() {
+            return Err(tls::TlsError::NoCertificate { file: file.clone() })
+                .or_raise(|| ImapError::TlsOptions);
+        }
+        for cert in certs {
+            builder.add_root_certificate(
+                native_tls::Certificate::from_pem(&cert).or_raise(|| ImapError::TlsOptions)?,
+            );
+        }
+    }
+
+    if let Some(ref cert) = base.tls_client_cert
+        && let Some(ref key) = base.tls_client_key
+    {
+        builder.identity(
+            native_tls::Identity::from_pkcs8(
+                &tls::read_pem(cert).or_raise(|| ImapError::TlsClientAuth)?,
+                &tls::read_pem(key).or_raise(|| ImapError::TlsClientAuth)?,
+            )
+            .or_raise(|| ImapError::TlsClientAuth)?,
+        );
+    }
+
+    // A pinned certificate is checked after the handshake, whoever signed it
+    if pin.is_some() || base.insecure_skip_verify {
+        if base.insecure_skip_verify {
+            tls::warn_insecure();
+        }
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+
+    let connector = builder.build().or_raise(|| ImapError::NativeTlsConnector)?;
     let connector = tokio_native_tls::TlsConnector::from(connector);
     let tls = connector
         .connect(server, tcp)
@@ -609,34 +1949,110 @@ async fn wrap_tls(tcp: TcpStream, server: &str) -> Result<ImapStream, ImapError>
         .or_raise(|| ImapError::TlsHandshake {
             server: server.to_owned(),
         })?;
+
+    // native-tls tells neither the version nor the cipher, and only gives
+    // the certificate of the server
+    handshake.certificates = tls
+        .get_ref()
+        .peer_certificate()
+        .ok()
+        .flatten()
+        .and_then(|cert| cert.to_der().ok())
+        .map(|der| tls::describe_certificate(&der))
+        .into_iter()
+        .collect();
+
+    if let Some(ref pin) = pin {
+        let der = tls
+            .get_ref()
+            .peer_certificate()
+            .ok()
+            .flatten()
+            .and_then(|cert| cert.to_der().ok())
+            .ok_or_raise(|| tls::TlsError::NoPeerCertificate)
+            .or_raise(|| ImapError::TlsPin {
+                server: server.to_owned(),
+            })?;
+        tls::check_pin(server, &der, pin).or_raise(|| ImapError::TlsPin {
+            server: server.to_owned(),
+        })?;
+    }
+
     Ok(Box::new(tls))
 }
 
 #[cfg_attr(
     feature = "tracing",
-    tracing::instrument(level = "trace", skip(tcp, server), ret, err(level = "debug"))
+    tracing::instrument(
+        level = "trace",
+        skip(tcp, server, base, handshake),
+        ret,
+        err(level = "debug")
+    )
 )]
 /// Wrap a `TcpStream` in a TLS layer using the rustls backend.
 #[cfg(feature = "rustls")]
-async fn wrap_tls(tcp: TcpStream, server: &str) -> Result<ImapStream, ImapError> {
+async fn wrap_tls(
+    tcp: TcpStream,
+    server: &str,
+    base: &BaseConfig,
+    handshake: &mut Handshake,
+) -> Result<ImapStream, ImapError> {
     use std::sync::Arc;
 
     use tokio_rustls::rustls;
 
-    let cert_result = rustls_native_certs::load_native_certs();
-    if !cert_result.errors.is_empty() {
-        return Err(ImapError::LoadNativeCerts {
-            errors: cert_result.errors,
-        })
-        .or_raise(|| ImapError::LoadingNativeCerts);
-    }
-    let mut roots = rustls::RootCertStore::empty();
-    for cert in cert_result.certs {
-        roots.add(cert).or_raise(|| ImapError::AddCerts)?;
-    }
-    let config = rustls::ClientConfig::builder()
-        .with_root_certificates(roots)
-        .with_no_client_auth();
+    let pin = base
+        .tls_pin_sha256
+        .as_deref()
+        .map(tls::parse_pin)
+        .transpose()
+        .or_raise(|| ImapError::TlsOptions)?;
+
+    let builder = rustls::ClientConfig::builder();
+
+    // A pinned certificate is checked after the handshake, whoever signed it
+    let builder = if pin.is_some() || base.insecure_skip_verify {
+        if base.insecure_skip_verify {
+            tls::warn_insecure();
+        }
+        let provider = Arc::clone(builder.crypto_provider());
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(tls::AcceptAnyCertificate(provider)))
+    } else {
+        let cert_result = rustls_native_certs::load_native_certs();
+        if !cert_result.errors.is_empty() {
+            return Err(ImapError::LoadNativeCerts {
+                errors: cert_result.errors,
+            })
+            .or_raise(|| ImapError::LoadingNativeCerts);
+        }
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in cert_result.certs {
+            roots.add(cert).or_raise(|| ImapError::AddCerts)?;
+        }
+        if let Some(ref file) = base.tls_ca_file {
+            for cert in tls::load_certificates(file).or_raise(|| ImapError::TlsOptions)? {
+                roots.add(cert).or_raise(|| ImapError::AddCerts)?;
+            }
+        }
+        builder.with_root_certificates(roots)
+    };
+
+    let config = if let Some(ref cert) = base.tls_client_cert
+        && let Some(ref key) = base.tls_client_key
+    {
+        builder
+            .with_client_auth_cert(
+                tls::load_certificates(cert).or_raise(|| ImapError::TlsClientAuth)?,
+                tls::load_private_key(key).or_raise(|| ImapError::TlsClientAuth)?,
+            )
+            .or_raise(|| ImapError::TlsClientAuth)?
+    } else {
+        builder.with_no_client_auth()
+    };
+
     let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
     let dns = rustls::pki_types::ServerName::try_from(server.to_owned()).or_raise(|| {
         ImapError::RustlsInvalidServer {
@@ -649,6 +2065,41 @@ async fn wrap_tls(tcp: TcpStream, server: &str) -> Result<ImapStream, ImapError>
         .or_raise(|| ImapError::TlsHandshake {
             server: server.to_owned(),
         })?;
+
+    let connection = &tls.get_ref().1;
+    handshake.tls_version = connection.protocol_version().map(|version| {
+        version
+            .as_str()
+            .map_or_else(|| format!("{version:?}"), str::to_owned)
+    });
+    handshake.tls_cipher = connection.negotiated_cipher_suite().map(|suite| {
+        suite
+            .suite()
+            .as_str()
+            .map_or_else(|| format!("{:?}", suite.suite()), str::to_owned)
+    });
+    handshake.certificates = connection
+        .peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .map(|der| tls::describe_certificate(der))
+        .collect();
+
+    if let Some(ref pin) = pin {
+        let der = tls
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(<[_]>::first)
+            .ok_or_raise(|| tls::TlsError::NoPeerCertificate)
+            .or_raise(|| ImapError::TlsPin {
+                server: server.to_owned(),
+            })?;
+        tls::check_pin(server, der, pin).or_raise(|| ImapError::TlsPin {
+            server: server.to_owned(),
+        })?;
+    }
+
     Ok(Box::new(tls))
 }
 
@@ -710,11 +2161,705 @@ pub fn ids_list_to_collapsed_sequence(ids: &HashSet<Uid>) -> String {
 
 #[cfg(test)]
 mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
     use std::collections::HashSet;
 
     use async_imap::types::Uid;
 
-    use super::ids_list_to_collapsed_sequence;
+    use super::{AuthMethod, Imap, ImapError, ids_list_to_collapsed_sequence};
+    use crate::{
+        libs::{base_config::BaseConfig, mode::DeleteMode},
+        test_helpers::{MockExchange, MockServer, test_base},
+    };
+
+    fn base_with_mode(port: u16, mode: &str) -> BaseConfig {
+        let mut base = test_base();
+        base.port = Some(port);
+        base.mode = Some(mode.parse().expect("valid mode"));
+        base
+    }
+
+    #[cfg(feature = "__tls")]
+    #[tokio::test]
+    async fn auto_mode_refuses_plaintext_fallback_by_default() {
+        let server =
+            MockServer::start(&[], vec![MockExchange::no("STARTTLS", "not supported")]).await;
+        let result = Imap::<()>::connect_base(&base_with_mode(server.port, "auto")).await;
+        server.join().await;
+        let err = result.expect_err("STARTTLS refused");
+        assert!(
+            format!("{err:?}").contains("The server refused STARTTLS"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[cfg(feature = "__tls")]
+    #[tokio::test]
+    async fn auto_mode_opportunistic_falls_back_to_plaintext() {
+        use crate::libs::mode::TlsPolicy;
+
+        let server =
+            MockServer::start(&[], vec![MockExchange::no("STARTTLS", "not supported")]).await;
+        let mut base = base_with_mode(server.port, "auto");
+        base.tls_policy = Some(TlsPolicy::Opportunistic);
+        base.allow_plaintext_auth = true;
+        let imap = Imap::<()>::connect_base(&base)
+            .await
+            .expect("plaintext fallback");
+        imap.close().await.expect("close");
+        server.join().await;
+    }
+
+    #[tokio::test]
+    async fn plaintext_login_refused_unless_allowed() {
+        let server = MockServer::start(&[], vec![]).await;
+        let result = Imap::<()>::connect_base(&base_with_mode(server.port, "plaintext")).await;
+        server.join().await;
+        let err = result.expect_err("plaintext LOGIN");
+        assert!(
+            format!("{err:?}").contains("Refusing to send LOGIN credentials"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    /// A tunnel command answering CAPABILITY, LOGIN and LOGOUT, greeting
+    /// with `greeting`.
+    fn tunnel_script(dir: &tempfile::TempDir, greeting: &str) -> String {
+        let script = dir.path().join("imapd.sh");
+        std::fs::write(
+            &script,
+            format!(
+                r#"printf '{greeting}\r\n'
+while read -r tag cmd rest; do
+  case "$cmd" in
+    CAPABILITY*) printf '* CAPABILITY IMAP4rev1 UIDPLUS\r\n%s OK done\r\n' "$tag" ;;
+    LOGIN*) printf '%s OK logged in\r\n' "$tag" ;;
+    LOGOUT*) printf '* BYE\r\n%s OK bye\r\n' "$tag"; exit 0 ;;
+    *) printf '%s BAD unexpected\r\n' "$tag" ;;
+  esac
+done
+"#
+            ),
+        )
+        .expect("write script");
+        format!("sh '{}'", script.display())
+    }
+
+    #[tokio::test]
+    async fn tunnel_with_preauth_needs_no_credentials() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let mut base = BaseConfig::default();
+        base.tunnel = Some(tunnel_script(&dir, "* PREAUTH ready"));
+        let imap = Imap::<()>::connect_base(&base).await.expect("preauth");
+        imap.close().await.expect("close");
+    }
+
+    /// A server greeting with PREAUTH, returning the lines it was sent.
+    async fn preauth_server() -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let port = listener.local_addr().expect("local addr").port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("accept");
+            let (reader, mut writer) = stream.into_split();
+            writer
+                .write_all(b"* PREAUTH ready\r\n")
+                .await
+                .expect("write greeting");
+            let mut received = vec![];
+            let mut lines = BufReader::new(reader).lines();
+            while let Some(line) = lines.next_line().await.expect("read line") {
+                let tag = line.split(' ').next().unwrap_or_default();
+                writer
+                    .write_all(format!("* BYE\r\n{tag} OK bye\r\n").as_bytes())
+                    .await
+                    .expect("write bye");
+                received.push(line);
+            }
+            received
+        });
+        (port, server)
+    }
+
+    #[tokio::test]
+    async fn plaintext_preauth_is_refused() {
+        let (port, server) = preauth_server().await;
+        let result = Imap::<()>::connect_base(&base_with_mode(port, "plaintext")).await;
+        let err = result.expect_err("unencrypted PREAUTH");
+        let received = server.await.expect("server");
+        assert!(
+            format!("{err:?}").contains("Refusing the PREAUTH session"),
+            "unexpected error: {err:?}"
+        );
+        assert!(received.is_empty(), "sent {received:?}");
+    }
+
+    #[tokio::test]
+    async fn plaintext_preauth_is_taken_when_allowed() {
+        let (port, server) = preauth_server().await;
+        let mut base = base_with_mode(port, "plaintext");
+        base.allow_plaintext_auth = true;
+        let imap = Imap::<()>::connect_base(&base).await.expect("preauth");
+        imap.close().await.expect("close");
+        // The LOGIN was answered without reaching the server
+        assert_eq!(server.await.expect("server"), ["A0002 LOGOUT"]);
+    }
+
+    #[tokio::test]
+    async fn async_imap_sends_login_as_one_line() {
+        use tokio::io::{AsyncBufReadExt as _, BufReader};
+
+        // PreAuthStream answers LOGIN once it has its first line
+        let (client, server) = tokio::io::duplex(1024);
+        let login = tokio::spawn(async_imap::Client::new(client).login("preauth", "preauth"));
+        let mut line = String::new();
+        BufReader::new(server)
+            .read_line(&mut line)
+            .await
+            .expect("read login");
+        login.abort();
+        assert_eq!(line, "A0001 LOGIN \"preauth\" \"preauth\"\r\n");
+    }
+
+    #[tokio::test]
+    async fn tunnel_logs_in_without_preauth() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let mut base = test_base();
+        base.server = None;
+        base.tunnel = Some(tunnel_script(&dir, "* OK ready"));
+        let imap = Imap::<()>::connect_base(&base).await.expect("login");
+        imap.close().await.expect("close");
+    }
+
+    #[tokio::test]
+    async fn connect_through_http_proxy() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let server = MockServer::start(&[], vec![]).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let proxy_port = listener.local_addr().expect("local addr").port();
+        let proxy = tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.expect("accept");
+            let mut request = vec![];
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(client.read_u8().await.expect("read request"));
+            }
+            client
+                .write_all(b"HTTP/1.1 200 OK\r\n\r\n")
+                .await
+                .expect("write reply");
+            let mut upstream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+                .await
+                .expect("connect upstream");
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            server.join().await;
+            String::from_utf8(request).expect("utf-8 request")
+        });
+
+        let mut base = base_with_mode(143, "plaintext");
+        base.server = Some("imap.example.com".to_owned());
+        base.allow_plaintext_auth = true;
+        base.proxy = Some(format!("http://127.0.0.1:{proxy_port}"));
+        let imap = Imap::<()>::connect_base(&base)
+            .await
+            .expect("connect through proxy");
+        imap.close().await.expect("close");
+
+        let request = proxy.await.expect("proxy task");
+        assert!(
+            request.starts_with("CONNECT imap.example.com:143 HTTP/1.1\r\n"),
+            "unexpected request: {request:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn login_disabled_is_reported() {
+        let server = MockServer::start(&["LOGINDISABLED"], vec![]).await;
+        let result = Imap::<()>::connect_base_on_port(&test_base(), server.port).await;
+        server.join().await;
+        let err = result.expect_err("LOGINDISABLED");
+        assert!(
+            format!("{err:?}").contains("LOGINDISABLED"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn auto_auth_falls_back_to_login() {
+        let server = MockServer::start(&[], vec![]).await;
+        let mut base = test_base();
+        base.auth = Some(AuthMethod::Auto);
+        let imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect with LOGIN");
+        imap.close().await.expect("close");
+        server.join().await;
+    }
+
+    #[tokio::test]
+    async fn authorize_as_needs_an_authzid_capable_mechanism() {
+        let server = MockServer::start(&["AUTH=XOAUTH2"], vec![]).await;
+        let mut base = test_base();
+        base.auth = Some(AuthMethod::XOAuth2);
+        base.authorize_as = Some("bob".to_owned());
+        let result = Imap::<()>::connect_base_on_port(&base, server.port).await;
+        server.join().await;
+        let err = result.expect_err("XOAUTH2 has no authzid");
+        assert!(
+            format!("{err:?}").contains("XOAUTH2 cannot act as another user"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn auto_auth_without_usable_mechanism() {
+        let server = MockServer::start(&["AUTH=GSSAPI", "LOGINDISABLED"], vec![]).await;
+        let mut base = test_base();
+        base.auth = Some(AuthMethod::Auto);
+        let result = Imap::<()>::connect_base_on_port(&base, server.port).await;
+        server.join().await;
+        let err = result.expect_err("no mechanism");
+        assert!(
+            format!("{err:?}").contains(
+                r#"No usable authentication mechanism among the server's ["AUTH=GSSAPI", "LOGINDISABLED"]"#
+            ),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out_connecting() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let port = listener.local_addr().expect("local addr").port();
+        let server = tokio::spawn(async move { listener.accept().await.expect("accept") });
+        let mut base = test_base();
+        base.connect_timeout = Some(1);
+        let err = Imap::<()>::connect_base_on_port(&base, port)
+            .await
+            .expect_err("no greeting");
+        drop(server.await);
+        assert!(
+            format!("{err:?}").contains("Timed out during connect"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out_on_command() {
+        let server = MockServer::start(&[], vec![MockExchange::silent("SELECT \"INBOX\"")]).await;
+        let mut base = test_base();
+        base.command_timeout = Some(1);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let err = imap.select("INBOX").await.expect_err("no answer");
+        drop(imap);
+        server.join().await;
+        assert!(
+            format!("{err:?}").contains("Timed out during SELECT"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out_on_uid_fetch() {
+        let server = MockServer::start(&[], vec![MockExchange::silent(
+            "UID FETCH 1:* INTERNALDATE",
+        )])
+        .await;
+        let mut base = test_base();
+        base.command_timeout = Some(1);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let err = imap
+            .uid_fetch("1:*", "INTERNALDATE")
+            .await
+            .expect_err("no answer");
+        drop(imap);
+        server.join().await;
+        assert!(
+            format!("{err:?}").contains("Timed out during UID FETCH"),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn raw_command_leaves_the_session_usable() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("NAMESPACE", vec![
+                "* NAMESPACE ((\"\" \"/\")) NIL NIL\r\n".into(),
+            ]),
+            MockExchange::no("NAMESPACE", "not today"),
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 2 EXISTS\r\n".into()]),
+        ])
+        .await;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&test_base(), server.port)
+            .await
+            .expect("connect");
+        let lines = imap.raw_command("NAMESPACE").await.expect("namespace");
+        let refused = imap.raw_command("NAMESPACE").await.expect_err("refused");
+        let selected = imap
+            .select("INBOX")
+            .await
+            .expect("select after raw commands");
+        let _ = imap.close().await;
+        server.join().await;
+        assert_eq!(lines, [r#"* NAMESPACE (("" "/")) NIL NIL"#]);
+        assert_eq!(
+            refused.to_string(),
+            "The server answered \"NO not today\" to NAMESPACE"
+        );
+        assert_eq!(selected.exists, 2);
+    }
+
+    #[tokio::test]
+    async fn raw_command_keeps_untagged_responses_read_before_it() {
+        let server = MockServer::start(&[], vec![
+            // The EXISTS comes with the tagged line, before NAMESPACE is sent
+            MockExchange {
+                tagged: "OK completed\r\n* 3 EXISTS".to_owned(),
+                ..MockExchange::ok("SELECT \"INBOX\"", vec![
+                    "* OK [UIDVALIDITY 1] UIDs valid\r\n".into(),
+                ])
+            },
+            MockExchange::ok("NAMESPACE", vec![
+                "* NAMESPACE ((\"\" \"/\")) NIL NIL\r\n".into(),
+            ]),
+        ])
+        .await;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&test_base(), server.port)
+            .await
+            .expect("connect");
+        imap.select("INBOX").await.expect("select");
+        let lines = imap.raw_command("NAMESPACE").await.expect("namespace");
+        let _ = imap.close().await;
+        server.join().await;
+        assert_eq!(lines, ["* 3 EXISTS", r#"* NAMESPACE (("" "/")) NIL NIL"#]);
+    }
+
+    #[tokio::test]
+    async fn with_reconnect_resumes_after_lost_connection() {
+        let server = MockServer::start_sessions(&[], vec![
+            vec![
+                MockExchange::ok("SELECT \"INBOX\"", vec![
+                    "* OK [UIDVALIDITY 1] UIDs valid\r\n".into(),
+                ]),
+                MockExchange::disconnect("EXAMINE \"Sent\""),
+            ],
+            vec![
+                // The previously selected mailbox is restored first
+                MockExchange::ok("SELECT \"INBOX\"", vec![
+                    "* OK [UIDVALIDITY 1] UIDs valid\r\n".into(),
+                ]),
+                MockExchange::ok("EXAMINE \"Sent\"", vec![
+                    "* 4 EXISTS\r\n".into(),
+                    "* OK [UIDVALIDITY 2] UIDs valid\r\n".into(),
+                ]),
+            ],
+        ])
+        .await;
+        let mut base = test_base();
+        base.reconnect_delay = Some(0);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        imap.select("INBOX").await.expect("select");
+        let result = imap
+            .with_reconnect(
+                async |imap| imap.examine("Sent").await,
+                || ImapError::ConnectBase,
+            )
+            .await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert_eq!(result.expect("examine after reconnect").exists, 4);
+    }
+
+    #[tokio::test]
+    async fn with_reconnect_gives_up() {
+        let server =
+            MockServer::start(&[], vec![MockExchange::disconnect("EXAMINE \"INBOX\"")]).await;
+        let mut base = test_base();
+        base.reconnect_delay = Some(0);
+        base.reconnect_attempts = Some(1);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let result = imap
+            .with_reconnect(
+                async |imap| imap.examine("INBOX").await,
+                || ImapError::ConnectBase,
+            )
+            .await;
+        server.join().await;
+        assert!(result.is_err(), "expected reconnect to fail");
+    }
+
+    #[tokio::test]
+    async fn with_reconnect_does_not_copy_twice() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID COPY 2:3 \"Archive\"", vec![]),
+            MockExchange::disconnect("UID STORE 2:3 +FLAGS (\\Deleted)"),
+        ])
+        .await;
+        let mut base = test_base();
+        base.reconnect_delay = Some(0);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let err = imap
+            .with_reconnect(
+                async |imap| {
+                    imap.select("INBOX").await?;
+                    imap.move_uids("2:3", "Archive").await
+                },
+                || ImapError::ConnectBase,
+            )
+            .await
+            .expect_err("connection lost after COPY");
+        server.join().await;
+        let err = format!("{err:?}");
+        assert!(
+            err.contains("may already be copied to \"Archive\"")
+                && !err.contains("Connection to the IMAP server was lost"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_uids_expunges_only_given_uids() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 2:3 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID EXPUNGE 2:3", vec![
+                "* 3 EXPUNGE\r\n".into(),
+                "* 2 EXPUNGE\r\n".into(),
+            ]),
+        ])
+        .await;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&test_base(), server.port)
+            .await
+            .expect("connect");
+        let result = imap.delete_uids("INBOX", "2:3").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+    }
+
+    #[tokio::test]
+    async fn delete_uids_rejected_uid_expunge_fails_without_fallback() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 2 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::no("UID EXPUNGE 2", "not supported"),
+            MockExchange::ok("UID STORE 2 -FLAGS.SILENT (\\Deleted)", vec![]),
+        ])
+        .await;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&test_base(), server.port)
+            .await
+            .expect("connect");
+        let result = imap.delete_uids("INBOX", "2").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(result.is_err(), "expected UID EXPUNGE rejection to fail");
+    }
+
+    #[tokio::test]
+    async fn delete_uids_rejected_uid_expunge_keeps_other_deleted_messages() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 2 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::no("UID EXPUNGE 2", "not supported"),
+            MockExchange::ok("UID SEARCH DELETED NOT UID 2", vec![
+                "* SEARCH 3\r\n".into(),
+            ]),
+            MockExchange::ok("UID STORE 3 -FLAGS.SILENT (\\Deleted)", vec![]),
+            MockExchange::ok("EXPUNGE", vec!["* 2 EXPUNGE\r\n".into()]),
+            MockExchange::ok("UID STORE 3 +FLAGS.SILENT (\\Deleted)", vec![]),
+        ])
+        .await;
+        let mut base = test_base();
+        base.uidplus_fallback = true;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let result = imap.delete_uids("INBOX", "2").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+    }
+
+    #[tokio::test]
+    async fn delete_uids_moves_to_special_use_trash() {
+        let server = MockServer::start(&["MOVE"], vec![
+            MockExchange::ok("LIST \"\" *", vec![
+                "* LIST (\\HasNoChildren) \"/\" \"INBOX\"\r\n".into(),
+                "* LIST (\\HasNoChildren \\Trash) \"/\" \"Deleted Items\"\r\n".into(),
+            ]),
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID MOVE 2:3 \"Deleted Items\"", vec![]),
+        ])
+        .await;
+        let mut base = test_base();
+        base.delete_mode = Some(DeleteMode::Trash);
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let result = imap.delete_uids("INBOX", "2:3").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+    }
+
+    #[tokio::test]
+    async fn delete_uids_copies_to_trash_without_move() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID COPY 2 \"Bin\"", vec![]),
+            MockExchange::ok("UID STORE 2 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID EXPUNGE 2", vec![]),
+            // Deleting from the trash itself expunges
+            MockExchange::ok("SELECT \"Bin\"", vec!["* 1 EXISTS\r\n".into()]),
+            MockExchange::ok("UID STORE 1 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID EXPUNGE 1", vec![]),
+        ])
+        .await;
+        let mut base = test_base();
+        base.delete_mode = Some(DeleteMode::Trash);
+        base.trash_mailbox = Some("Bin".to_owned());
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        let destination = imap.destination("INBOX").await;
+        let moved = imap.delete_uids("INBOX", "2").await;
+        let expunged = imap.delete_uids("Bin", "1").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert_eq!(destination.ok().flatten().as_deref(), Some("Bin"));
+        assert!(moved.is_ok(), "expected Ok, got: {moved:?}");
+        assert!(expunged.is_ok(), "expected Ok, got: {expunged:?}");
+    }
+
+    #[tokio::test]
+    async fn delete_uids_without_uidplus_needs_fallback() {
+        // Neither flagged nor copied: the mock server fails on any command
+        let server = MockServer::start_without_uidplus(&[], vec![]).await;
+        let mut base = test_base();
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect without UIDPLUS");
+        let checked = imap.check_uidplus(false).await;
+        let deleted = imap.delete_uids("INBOX", "2").await;
+        let _ = imap.close().await;
+        server.join().await;
+        let error = checked.expect_err("expected the check to fail").to_string();
+        assert!(error.contains("uidplus-fallback"), "got: {error}");
+        assert!(deleted.is_err(), "expected no STORE without the fallback");
+
+        let server = MockServer::start_without_uidplus(&["MOVE"], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec!["* 3 EXISTS\r\n".into()]),
+            MockExchange::ok("UID MOVE 2 \"Bin\"", vec![]),
+        ])
+        .await;
+        base.delete_mode = Some(DeleteMode::Trash);
+        base.trash_mailbox = Some("Bin".to_owned());
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect without UIDPLUS");
+        let checked = imap.check_uidplus(false).await;
+        let moved = imap.delete_uids("INBOX", "2").await;
+        let expunged = imap.delete_uids("Bin", "1").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(checked.is_ok(), "expected MOVE to do, got: {checked:?}");
+        assert!(moved.is_ok(), "expected Ok, got: {moved:?}");
+        let error = expunged
+            .expect_err("expected no STORE in the trash without the fallback")
+            .to_string();
+        assert!(error.contains("uidplus-fallback"), "got: {error}");
+    }
+
+    #[tokio::test]
+    async fn delete_uids_without_uidplus_keeps_other_deleted_messages() {
+        let server = MockServer::start_without_uidplus(&[], vec![
+            MockExchange::ok("SELECT \"INBOX\"", vec![
+                "* 5 EXISTS\r\n".into(),
+                "* OK [UIDVALIDITY 7] UIDs valid\r\n".into(),
+            ]),
+            MockExchange::ok("UID STORE 2:3 +FLAGS (\\Deleted)", vec![]),
+            MockExchange::ok("UID SEARCH DELETED NOT UID 2:3", vec![
+                "* SEARCH 5 1\r\n".into(),
+            ]),
+            MockExchange::ok("UID STORE 1,5 -FLAGS.SILENT (\\Deleted)", vec![]),
+            MockExchange::ok("EXPUNGE", vec![
+                "* 3 EXPUNGE\r\n".into(),
+                "* 2 EXPUNGE\r\n".into(),
+            ]),
+            MockExchange::ok("UID STORE 1,5 +FLAGS.SILENT (\\Deleted)", vec![]),
+        ])
+        .await;
+        let mut base = test_base();
+        base.uidplus_fallback = true;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&base, server.port)
+            .await
+            .expect("connect");
+        imap.check_uidplus(false).await.expect("fallback is set");
+        let result = imap.delete_uids("INBOX", "2:3").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(result.is_ok(), "expected Ok, got: {result:?}");
+    }
+
+    #[tokio::test]
+    async fn check_uidplus_accepts_move_for_moving_commands() {
+        let server = MockServer::start_without_uidplus(&["MOVE"], vec![]).await;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&test_base(), server.port)
+            .await
+            .expect("connect without UIDPLUS");
+        let moving = imap.check_uidplus(true).await;
+        let deleting = imap.check_uidplus(false).await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert!(moving.is_ok(), "expected MOVE to do, got: {moving:?}");
+        assert!(deleting.is_err(), "expected MOVE not to do for expunging");
+    }
+
+    #[tokio::test]
+    async fn select_refuses_changed_uid_validity() {
+        let server = MockServer::start(&[], vec![
+            MockExchange::ok("EXAMINE \"INBOX\"", vec![
+                "* OK [UIDVALIDITY 1] UIDs valid\r\n".into(),
+            ]),
+            MockExchange::ok("SELECT \"INBOX\"", vec![
+                "* OK [UIDVALIDITY 2] UIDs valid\r\n".into(),
+            ]),
+        ])
+        .await;
+        let mut imap: Imap<()> = Imap::connect_base_on_port(&test_base(), server.port)
+            .await
+            .expect("connect");
+        imap.examine("INBOX").await.expect("examine");
+        let result = imap.select("INBOX").await;
+        let _ = imap.close().await;
+        server.join().await;
+        assert_eq!(
+            result.map(|_| ()).map_err(|err| err.to_string()),
+            Err(
+                "The UIDVALIDITY of \"INBOX\" changed from 1 to 2, its UIDs no longer mean the same messages"
+                    .to_owned()
+            )
+        );
+    }
 
     #[cfg_attr(not(debug_assertions), ignore = "testing debug_assert!")]
     #[test]
@@ -2,8 +2,21 @@ pub mod args;
 pub mod auth;
 pub mod base_config;
 pub mod config;
+pub mod config_file;
 pub mod filter;
 pub mod filters;
 pub mod imap;
 mod mode;
+mod netrc;
+pub mod period;
+pub mod pool;
+pub mod proxy;
 pub mod render;
+pub mod search;
+pub mod timeout;
+#[cfg(feature = "__tls")]
+pub mod tls;
+pub mod transcript;
+pub mod tunnel;
+pub mod untagged;
+pub mod users;
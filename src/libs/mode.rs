@@ -1,6 +1,7 @@
-use std::str::FromStr;
+use std::{borrow::Cow, str::FromStr};
 
 use exn::bail;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, derive_more::Display)]
@@ -30,7 +31,8 @@ pub enum Mode {
         feature = "__tls",
         doc = "Automatically detect what connection mode should be used.",
         doc = "This will use TLS if the port is 993, and otherwise STARTTLS if available.",
-        doc = "It will fallback to a plaintext connection if no TLS option can be used."
+        doc = "It will fallback to a plaintext connection if no TLS option can be used",
+        doc = "and the TLS policy is opportunistic."
     )]
     #[cfg_attr(
         not(feature = "__tls"),
@@ -113,6 +115,26 @@ impl Serialize for Mode {
     }
 }
 
+impl JsonSchema for Mode {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        "Mode".into()
+    }
+
+    /// The names taken by the configuration file.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(_generator))
+    )]
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        let names = <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .filter_map(|mode| serde_json::to_value(mode).ok())
+            .collect::<Vec<_>>();
+        json_schema!({ "type": "string", "enum": names })
+    }
+}
+
 impl Default for Mode {
     #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
     fn default() -> Self {
@@ -124,13 +146,53 @@ impl Default for Mode {
     }
 }
 
+/// Whether the connection must, may, or must not be encrypted, checked
+/// against what the connection mode ends up doing.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum, schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TlsPolicy {
+    /// The connection must be encrypted, never fall back to plaintext.
+    Require,
+    /// Encrypt the connection if the server supports it.
+    Opportunistic,
+    /// Never try to encrypt the connection.
+    Never,
+}
+
+impl TlsPolicy {
+    /// The policy used when none is set: plaintext connections stay in
+    /// plaintext, and any other mode must end up encrypted.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    pub fn default_for(mode: &Mode) -> Self {
+        if !cfg!(feature = "__tls") || *mode == Mode::Plaintext {
+            Self::Never
+        } else {
+            Self::Require
+        }
+    }
+}
+
+/// What deleting a message does.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum, schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeleteMode {
+    /// Flag it `\Deleted` and expunge it for good.
+    Expunge,
+    /// Move it to the trash mailbox.
+    Trash,
+}
+
 #[cfg(test)]
 mod tests {
     #![expect(clippy::expect_used, reason = "tests")]
 
     use serde_any::{Format, from_str, to_string};
 
-    use super::Mode;
+    use super::{Mode, TlsPolicy};
 
     #[test]
     fn from_str_auto_tls() {
@@ -221,4 +283,22 @@ mod tests {
     fn default_is_auto_tls() {
         assert_eq!(Mode::default(), Mode::AutoTls);
     }
+
+    #[test]
+    fn tls_policy_default_for_plaintext() {
+        assert_eq!(TlsPolicy::default_for(&Mode::Plaintext), TlsPolicy::Never);
+    }
+
+    #[cfg(feature = "__tls")]
+    #[test]
+    fn tls_policy_default_for_auto_requires_tls() {
+        assert_eq!(TlsPolicy::default_for(&Mode::Auto), TlsPolicy::Require);
+    }
+
+    #[test]
+    fn tls_policy_deserialize() {
+        let policy: TlsPolicy =
+            from_str("\"opportunistic\"", Format::Json).expect("should deserialize");
+        assert_eq!(policy, TlsPolicy::Opportunistic);
+    }
 }
@@ -0,0 +1,208 @@
+use std::{env, fs, path::PathBuf};
+
+/// The netrc files, in the order they are searched: `$NETRC` or
+/// `~/.netrc`, then `~/.authinfo`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+pub fn files() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    [
+        env::var_os("NETRC")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| home.join(".netrc"))),
+        home.map(|home| home.join(".authinfo")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// The password of the first entry of the `files` for `machine`, `port` and
+/// `login`, see [`lookup`].
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(files)))]
+pub fn find(files: &[PathBuf], machine: &str, port: u16, login: Option<&str>) -> Option<String> {
+    files
+        .iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .find_map(|content| lookup(&content, machine, port, login))
+}
+
+/// An entry of a netrc or authinfo file.
+#[derive(Debug, Default)]
+struct Entry {
+    /// `None` for the `default` entry.
+    machine: Option<String>,
+    login: Option<String>,
+    password: Option<String>,
+    port: Option<String>,
+}
+
+impl Entry {
+    /// Whether the entry is for `machine`, `port` and `login`. An entry
+    /// without a login or a port is for any of them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    fn matches(&self, machine: &str, port: u16, login: Option<&str>) -> bool {
+        self.machine
+            .as_deref()
+            .is_none_or(|name| name.eq_ignore_ascii_case(machine))
+            && self.port.as_deref().is_none_or(|name| {
+                name == port.to_string()
+                    || (name == "imap" && port == 143)
+                    || (name == "imaps" && port == 993)
+            })
+            && self
+                .login
+                .as_deref()
+                .is_none_or(|name| login.is_none_or(|login| name == login))
+    }
+}
+
+/// The password of `login` on `machine` and `port` in `content`, a netrc or
+/// authinfo file.
+///
+/// The entries are read in order and the first matching one wins, the
+/// `default` entry only matching when no other does. Values may be quoted
+/// like in authinfo files, and `macdef` macros are skipped.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(content))
+)]
+pub fn lookup(content: &str, machine: &str, port: u16, login: Option<&str>) -> Option<String> {
+    let mut entries: Vec<Entry> = vec![];
+    let mut default = None;
+    let mut tokens = tokens(content).into_iter();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            "machine" => entries.push(Entry {
+                machine: tokens.next(),
+                ..Entry::default()
+            }),
+            "default" => default = Some(Entry::default()),
+            "login" | "password" | "port" | "account" => {
+                let value = tokens.next();
+                let entry = if default.is_some() {
+                    default.as_mut()
+                } else {
+                    entries.last_mut()
+                };
+                if let Some(entry) = entry {
+                    match token.as_str() {
+                        "login" => entry.login = value,
+                        "password" => entry.password = value,
+                        "port" => entry.port = value,
+                        _ => {},
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    entries
+        .into_iter()
+        .chain(default)
+        .find(|entry| entry.matches(machine, port, login))?
+        .password
+}
+
+/// The tokens of `content`, without the comments and the `macdef` macros.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(content))
+)]
+fn tokens(content: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut in_macro = false;
+    for line in content.lines() {
+        if in_macro {
+            // A macro ends with an empty line
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        let mut chars = line.chars().peekable();
+        while let Some(&first) = chars.peek() {
+            if first.is_whitespace() {
+                chars.next();
+            } else if first == '#' {
+                break;
+            } else {
+                let mut token = String::new();
+                if first == '"' {
+                    chars.next();
+                    while let Some(char) = chars.next() {
+                        match char {
+                            '"' => break,
+                            '\\' => token.extend(chars.next()),
+                            _ => token.push(char),
+                        }
+                    }
+                } else {
+                    while let Some(char) = chars.next_if(|char| !char.is_whitespace()) {
+                        token.push(char);
+                    }
+                }
+                if token == "macdef" {
+                    in_macro = true;
+                    break;
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETRC: &str = r#"# work
+machine imap.example.com login alice password alice-secret
+machine imap.example.com
+    login bob
+    password "bob \"quoted\" secret"
+macdef init
+machine imap.example.com login mallory password macro
+
+machine mail.example.org login carol port 993 password imaps-secret
+default password default-secret
+"#;
+
+    #[test]
+    fn lookup_by_machine_login_and_port() {
+        assert_eq!(
+            lookup(NETRC, "imap.example.com", 993, Some("alice")).as_deref(),
+            Some("alice-secret"),
+            "first entry"
+        );
+        assert_eq!(
+            lookup(NETRC, "IMAP.example.com", 143, Some("bob")).as_deref(),
+            Some("bob \"quoted\" secret"),
+            "entry over several lines, quoted"
+        );
+        assert_eq!(
+            lookup(NETRC, "imap.example.com", 143, None).as_deref(),
+            Some("alice-secret"),
+            "any login"
+        );
+        assert_eq!(
+            lookup(NETRC, "mail.example.org", 143, Some("carol")).as_deref(),
+            Some("default-secret"),
+            "wrong port, default entry"
+        );
+        assert_eq!(
+            lookup(NETRC, "mail.example.org", 993, Some("carol")).as_deref(),
+            Some("imaps-secret"),
+            "right port"
+        );
+        assert_eq!(
+            lookup(
+                "machine other login x password y",
+                "mail.example.org",
+                993,
+                None
+            ),
+            None,
+            "no entry"
+        );
+    }
+}
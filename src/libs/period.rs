@@ -0,0 +1,263 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use chrono::{DateTime, Days, Months, Utc};
+use exn::{Exn, bail};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Serialize, de::Visitor};
+
+#[derive(Debug, derive_more::Display)]
+pub enum PeriodError {
+    #[display("Invalid duration {value:?}, expected a number and a unit like \"6 months\"")]
+    Invalid { value: String },
+    #[display("Unknown duration unit {unit:?}, expected days, weeks, months or years")]
+    UnknownUnit { unit: String },
+}
+impl std::error::Error for PeriodError {}
+
+/// A number of days, weeks, months or years, like `90d`, `2w`, `6 months`
+/// or `1y`, read alone as a number of days.
+///
+/// The months and the years are counted on the calendar, so one month
+/// before the 31st of March is the last day of February.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Period {
+    amount: u32,
+    unit: Unit,
+}
+
+/// The unit of a [`Period`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Unit {
+    /// The name of `amount` units.
+    const fn name(self, amount: u32) -> &'static str {
+        match (self, amount) {
+            (Self::Day, 1) => "day",
+            (Self::Day, _) => "days",
+            (Self::Week, 1) => "week",
+            (Self::Week, _) => "weeks",
+            (Self::Month, 1) => "month",
+            (Self::Month, _) => "months",
+            (Self::Year, 1) => "year",
+            (Self::Year, _) => "years",
+        }
+    }
+}
+
+impl Period {
+    /// A period of `amount` days.
+    pub const fn days(amount: u32) -> Self {
+        Self {
+            amount,
+            unit: Unit::Day,
+        }
+    }
+
+    /// The date this period before `now`, the earliest date when it is out
+    /// of range.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+    pub fn cutoff(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self.unit {
+            Unit::Day => now.checked_sub_days(Days::new(self.amount.into())),
+            Unit::Week => now.checked_sub_days(Days::new(u64::from(self.amount) * 7)),
+            Unit::Month => now.checked_sub_months(Months::new(self.amount)),
+            Unit::Year => now.checked_sub_months(Months::new(self.amount.saturating_mul(12))),
+        }
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+}
+
+impl FromStr for Period {
+    type Err = Exn<PeriodError>;
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", ret, err(level = "debug"))
+    )]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (amount, unit) = trimmed.split_at(
+            trimmed
+                .find(|char: char| !char.is_ascii_digit())
+                .unwrap_or(trimmed.len()),
+        );
+        let Ok(amount) = amount.parse() else {
+            bail!(PeriodError::Invalid {
+                value: s.to_owned()
+            });
+        };
+        let unit = match unit.trim_start().to_ascii_lowercase().as_str() {
+            "" | "d" | "day" | "days" => Unit::Day,
+            "w" | "week" | "weeks" => Unit::Week,
+            "mo" | "month" | "months" => Unit::Month,
+            "y" | "year" | "years" => Unit::Year,
+            _ => bail!(PeriodError::UnknownUnit {
+                unit: unit.trim_start().to_owned()
+            }),
+        };
+        Ok(Self { amount, unit })
+    }
+}
+
+impl fmt::Display for Period {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.unit.name(self.amount))
+    }
+}
+
+impl<'de> Deserialize<'de> for Period {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(deserializer), ret, err(level = "debug"))
+    )]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct PeriodVisitor;
+
+        impl Visitor<'_> for PeriodVisitor {
+            type Value = Period;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a number of days, or a duration like \"6 months\"")
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Period, E>
+            where
+                E: serde::de::Error,
+            {
+                u32::try_from(v)
+                    .map(Period::days)
+                    .map_err(|_err| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Period, E>
+            where
+                E: serde::de::Error,
+            {
+                u32::try_from(v)
+                    .map(Period::days)
+                    .map_err(|_err| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Period, E>
+            where
+                E: serde::de::Error,
+            {
+                Period::from_str(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(PeriodVisitor)
+    }
+}
+
+impl Serialize for Period {
+    /// A number of days is written as such, like it was before the other
+    /// units.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, serializer), err(level = "debug"))
+    )]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self.unit {
+            Unit::Day => serializer.serialize_u32(self.amount),
+            Unit::Week | Unit::Month | Unit::Year => serializer.collect_str(self),
+        }
+    }
+}
+
+impl JsonSchema for Period {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        "Period".into()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(_generator))
+    )]
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A number of days, or a duration like `90d`, `2w`, `6 months` or `1y`.",
+            "anyOf": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "string" },
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use super::*;
+
+    #[test]
+    fn periods_are_read_and_counted_on_the_calendar() {
+        let now = DateTime::parse_from_rfc3339("2024-03-31T12:00:00Z")
+            .expect("date")
+            .to_utc();
+        for (period, display, cutoff) in [
+            ("90", "90 days", "2024-01-01"),
+            ("90d", "90 days", "2024-01-01"),
+            ("1 day", "1 day", "2024-03-30"),
+            ("2w", "2 weeks", "2024-03-17"),
+            ("1 Month", "1 month", "2024-02-29"),
+            ("6 months", "6 months", "2023-09-30"),
+            ("1y", "1 year", "2023-03-31"),
+            (" 2 years ", "2 years", "2022-03-31"),
+        ] {
+            let period = Period::from_str(period).expect("valid period");
+            assert_eq!(period.to_string(), display, "display of {period:?}");
+            assert_eq!(
+                period.cutoff(now).format("%Y-%m-%d").to_string(),
+                cutoff,
+                "cutoff of {period:?}"
+            );
+        }
+
+        for (invalid, message) in [
+            (
+                "months",
+                "Invalid duration \"months\", expected a number and a unit like \"6 months\"",
+            ),
+            (
+                "6 fortnights",
+                "Unknown duration unit \"fortnights\", expected days, weeks, months or years",
+            ),
+        ] {
+            assert_eq!(
+                Period::from_str(invalid).map_err(|err| err.to_string()),
+                Err(message.to_owned()),
+                "{invalid:?} is invalid"
+            );
+        }
+
+        let read: Vec<Period> =
+            serde_any::from_str("[30, \"6 months\"]", serde_any::Format::Json).expect("json");
+        assert_eq!(read, [
+            Period::days(30),
+            Period::from_str("6mo").expect("6mo")
+        ]);
+        assert_eq!(
+            serde_json::to_string(&read).expect("serialize"),
+            r#"[30,"6 months"]"#
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::{collections::BTreeMap, fmt::Debug};
+
+use async_imap::types::Name;
+use exn::{Result, ResultExt as _};
+use futures::future::{join_all, try_join_all};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::libs::{
+    base_config::BaseConfig,
+    config::Config,
+    imap::{Imap, ImapError, ListResult},
+};
+
+/// A set of authenticated IMAP sessions sharing one configuration, used to
+/// process mailboxes in parallel.
+#[derive(Debug)]
+pub struct ImapPool<T>
+where
+    T: Clone + Debug + Serialize,
+{
+    /// The sessions, each one is only ever used by one mailbox at a time.
+    sessions: Vec<Mutex<Imap<T>>>,
+}
+
+impl<T> ImapPool<T>
+where
+    T: Clone + Debug + Serialize + Send + Sync,
+{
+    /// Open `concurrency` sessions to the server.
+    ///
+    /// The first session is opened on its own so that the password or
+    /// `OAuth2` command runs once and its result is reused by the others.
+    /// The commands using a pool delete or move messages, so it also checks
+    /// that they can, moving them when the command `moves` them, see
+    /// [`Imap::check_uidplus`].
+    ///
+    /// # Errors
+    /// Returns an error if any of the sessions cannot be opened.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(config), err(level = "info"))
+    )]
+    pub async fn connect(config: &Config<T>, moves: bool) -> Result<Self, ImapError> {
+        let mut first = Imap::connect(config).await?;
+        first.check_uidplus(moves).await?;
+        let others =
+            try_join_all((1..config.base.concurrency.unwrap_or(1)).map(|_| Imap::connect(config)))
+                .await?;
+
+        Ok(Self {
+            sessions: std::iter::once(first)
+                .chain(others)
+                .map(Mutex::new)
+                .collect(),
+        })
+    }
+
+    /// Open `concurrency` sessions to the server described by `base`, for
+    /// the commands that only read mailboxes.
+    ///
+    /// # Errors
+    /// Returns an error if any of the sessions cannot be opened.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(base), err(level = "info"))
+    )]
+    pub async fn connect_base(base: &BaseConfig) -> Result<Self, ImapError> {
+        // The first one alone, like in `connect`
+        let first = Imap::connect_base(base).await?;
+        let others =
+            try_join_all((1..base.concurrency.unwrap_or(1)).map(|_| Imap::connect_base(base)))
+                .await?;
+
+        Ok(Self {
+            sessions: std::iter::once(first)
+                .chain(others)
+                .map(Mutex::new)
+                .collect(),
+        })
+    }
+
+    /// Test-only: a pool of the one session `imap`.
+    #[cfg(test)]
+    pub fn of(imap: Imap<T>) -> Self {
+        Self {
+            sessions: vec![Mutex::new(imap)],
+        }
+    }
+
+    /// Get the list of mailboxes given filters, using the first session.
+    ///
+    /// # Errors
+    /// Many errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn list(&self) -> Result<BTreeMap<String, ListResult<T>>, ImapError> {
+        match self.sessions.first() {
+            Some(session) => session.lock().await.list().await,
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Run `LIST` on `pattern` in `reference` with the first session, see
+    /// [`Imap::list_names`].
+    ///
+    /// # Errors
+    /// Imap errors can happen
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "info"))
+    )]
+    pub async fn list_names(
+        &self,
+        reference: Option<&str>,
+        pattern: Option<&str>,
+    ) -> Result<Vec<Name>, ImapError> {
+        match self.sessions.first() {
+            Some(session) => session.lock().await.list_names(reference, pattern).await,
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Run `op` on each mailbox, each session taking the next mailbox once
+    /// done with its own, and hand the results to `done` in mailbox order,
+    /// so the output does not depend on which session finishes first.
+    ///
+    /// # Errors
+    /// Returns the first error from `op` or `done`, in mailbox order. The
+    /// sessions finish the mailbox they are on, and take no other one.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err(level = "info"))
+    )]
+    pub async fn for_each_mailbox<R, E>(
+        &self,
+        mailboxes: BTreeMap<String, ListResult<T>>,
+        op: impl AsyncFn(&mut Imap<T>, String, ListResult<T>) -> Result<R, E> + Sync,
+        mut done: impl FnMut(R) -> Result<(), E> + Send,
+    ) -> Result<(), E>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let (queue, todo) = async_channel::unbounded();
+        for mailbox in mailboxes.into_iter().enumerate() {
+            // Cannot fail, the queue is unbounded and `todo` is alive
+            let _ = queue.try_send(mailbox);
+        }
+        queue.close();
+
+        let (sender, results) = async_channel::unbounded();
+        let sessions = join_all(self.sessions.iter().map(|session| {
+            let (op, todo, sender) = (&op, todo.clone(), sender.clone());
+            async move {
+                while let Ok((index, (mailbox, result))) = todo.recv().await {
+                    let result = op(&mut *session.lock().await, mailbox, result).await;
+                    // Stop once an error ended the run
+                    if sender.send((index, result)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }));
+        drop(sender);
+
+        let in_order = async {
+            let mut finished = BTreeMap::new();
+            let mut next = 0;
+            while let Ok((index, result)) = results.recv().await {
+                finished.insert(index, result);
+                while let Some(result) = finished.remove(&next) {
+                    next += 1;
+                    done(result?)?;
+                }
+            }
+            Ok(())
+        };
+
+        let (_, result) = futures::join!(sessions, async {
+            let result = in_order.await;
+            results.close();
+            result
+        });
+        result
+    }
+
+    /// Close all the sessions.
+    ///
+    /// # Errors
+    /// Returns an error if a LOGOUT command fails.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "debug"))
+    )]
+    pub async fn close(self) -> Result<(), ImapError> {
+        for session in self.sessions {
+            session
+                .into_inner()
+                .close()
+                .await
+                .or_raise(|| ImapError::Logout)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use super::*;
+    use crate::{
+        libs::args::Generic,
+        test_helpers::{MockExchange, MockServer},
+    };
+
+    fn examine(mailbox: &str, exists: u32) -> MockExchange {
+        MockExchange::ok(format!("EXAMINE \"{mailbox}\""), vec![
+            format!("* {exists} EXISTS\r\n"),
+            "* OK [UIDVALIDITY 1] UIDs valid\r\n".into(),
+        ])
+    }
+
+    #[tokio::test]
+    async fn for_each_mailbox_spreads_mailboxes_and_keeps_order() {
+        // A is slow, the second session takes both B and C meanwhile
+        let server = MockServer::start_sessions(&[], vec![
+            vec![
+                MockExchange::ok("LIST \"\" *", vec![
+                    "* LIST () \"/\" A\r\n".into(),
+                    "* LIST () \"/\" B\r\n".into(),
+                    "* LIST () \"/\" C\r\n".into(),
+                ]),
+                examine("A", 1),
+            ],
+            vec![examine("B", 2), examine("C", 3)],
+        ])
+        .await;
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let runs = dir.path().join("runs");
+        let mut config = Config::<()>::new(
+            &Generic {
+                server: Some("127.0.0.1".to_owned()),
+                port: Some(server.port),
+                username: Some("test".to_owned()),
+                password_command: Some(format!(
+                    "sh -c \"echo run >> '{}'; echo test\"",
+                    runs.display()
+                )),
+                mode: Some("plaintext".parse().expect("plaintext is a valid mode")),
+                concurrency: Some(2),
+                allow_plaintext_auth: true,
+                ..Default::default()
+            },
+            None,
+        )
+        .expect("config");
+        config.base.reconnect_attempts = Some(0);
+
+        let pool = ImapPool::connect(&config, false).await.expect("connect");
+        let mut seen = vec![];
+        pool.for_each_mailbox(
+            pool.list().await.expect("list"),
+            async |imap, mailbox, _result| {
+                if mailbox == "A" {
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                }
+                let mbx = imap.examine(&mailbox).await?;
+                Ok((mailbox, mbx.exists))
+            },
+            |result| {
+                seen.push(result);
+                Ok::<_, exn::Exn<ImapError>>(())
+            },
+        )
+        .await
+        .expect("for_each_mailbox");
+        pool.close().await.expect("close");
+        server.join().await;
+
+        assert_eq!(seen, [
+            ("A".to_owned(), 1),
+            ("B".to_owned(), 2),
+            ("C".to_owned(), 3)
+        ]);
+        assert_eq!(
+            std::fs::read_to_string(runs).expect("read runs"),
+            "run\n",
+            "the password command should only run once"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use exn::Result;
+
+use crate::libs::render::traits::{Renderer, RendererError, RendererUsable};
+
+/// Renderer that only keeps the rows, formatted as strings, so rows produced
+/// by concurrent sessions can be replayed into the real renderer in order.
+#[derive(Debug)]
+pub struct RowBuffer<const N: usize> {
+    rows: Vec<[String; N]>,
+}
+
+impl<const N: usize> Default for RowBuffer<N> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn default() -> Self {
+        Self { rows: vec![] }
+    }
+}
+
+impl<const N: usize> RowBuffer<N> {
+    /// Add all the buffered rows to `renderer`.
+    ///
+    /// # Errors
+    /// Forwards the errors from `renderer`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, renderer), err(level = "info"))
+    )]
+    pub fn replay(self, renderer: &mut (dyn Renderer<N> + Send)) -> Result<(), RendererError> {
+        for row in &self.rows {
+            renderer.add_row(&row.each_ref().map(|cell| cell as &dyn Display))?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> RendererUsable for RowBuffer<N> {}
+
+impl<const N: usize> Renderer<N> for RowBuffer<N> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip(_title, _format, _headers),
+            ret,
+            err(level = "info")
+        )
+    )]
+    fn new(
+        _title: &'static str,
+        _format: &'static [&'static str; N],
+        _headers: &'static [&'static str; N],
+    ) -> Result<Self, RendererError> {
+        Ok(Self::default())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, row), err(level = "info"))
+    )]
+    fn add_row(&mut self, row: &[&dyn Display; N]) -> Result<(), RendererError> {
+        self.rows.push(row.map(ToString::to_string));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+
+    use super::*;
+    use crate::libs::render::new_renderer;
+
+    #[test]
+    fn replay_keeps_rows_and_order() {
+        let mut buffer = RowBuffer::<2>::default();
+        buffer
+            .add_row(&[&"foo" as &dyn Display, &42_u32])
+            .expect("add_row");
+        buffer
+            .add_row(&[&"bar" as &dyn Display, &"baz"])
+            .expect("add_row");
+
+        let mut renderer =
+            new_renderer(None, None, "T", &["", ""], &["Name", "Value"]).expect("new renderer");
+        buffer.replay(renderer.as_mut()).expect("replay");
+        assert_snapshot!(renderer.output(), @"
+        Name,Value
+        foo,42
+        bar,baz
+        ");
+    }
+}
@@ -1,8 +1,10 @@
+mod buffer;
 mod csv;
 #[cfg(feature = "cursive")]
 mod cursive;
 mod json;
 mod print;
+mod shared;
 #[cfg(feature = "ratatui")]
 mod terminal;
 mod traits;
@@ -10,12 +12,25 @@ mod traits;
 use exn::{Exn, Result, ResultExt as _, bail};
 use serde::{Deserialize, Serialize};
 
-pub use crate::libs::render::traits::Renderer;
 use crate::libs::render::traits::RendererError;
 #[cfg(any(feature = "ratatui", feature = "cursive"))]
 use crate::libs::render::traits::RendererUsable as _;
+pub use crate::libs::render::{
+    buffer::RowBuffer,
+    shared::{Run, SharedRenderers},
+    traits::Renderer,
+};
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize, derive_more::Display, clap::ValueEnum)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Deserialize,
+    Serialize,
+    derive_more::Display,
+    clap::ValueEnum,
+    schemars::JsonSchema,
+)]
 pub enum RendererArg {
     /// CSV output
     Csv,
@@ -87,7 +102,27 @@ impl std::str::FromStr for RendererArg {
     feature = "tracing",
     tracing::instrument(level = "trace", skip(title, format, headers), err(level = "info"))
 )]
+/// The renderer chosen by `renderer`, or within a `run` of a command for
+/// several users, the renderer shared by the runs.
 pub fn new_renderer<const N: usize>(
+    run: Option<&Run>,
+    renderer: Option<RendererArg>,
+    title: &'static str,
+    format: &'static [&'static str; N],
+    headers: &'static [&'static str; N],
+) -> Result<Box<dyn Renderer<N> + Send>, RendererError> {
+    run.map_or_else(
+        || create_renderer(renderer, title, format, headers),
+        |run| run.new_renderer(renderer, title, format, headers),
+    )
+}
+
+/// The renderer chosen by `renderer`, never shared.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(title, format, headers), err(level = "info"))
+)]
+fn create_renderer<const N: usize>(
     renderer: Option<RendererArg>,
     title: &'static str,
     format: &'static [&'static str; N],
@@ -0,0 +1,199 @@
+use std::{
+    any::Any,
+    fmt::{self, Display},
+    sync::{Arc, Mutex},
+};
+
+use exn::{Result, bail};
+
+use crate::libs::render::{
+    RendererArg, create_renderer,
+    traits::{Renderer, RendererError, RendererUsable},
+};
+
+type Shared<const N: usize> = Arc<Mutex<Box<dyn Renderer<N> + Send>>>;
+
+/// Renderer shared by the runs of a command for several users, so their
+/// rows end up in a single output, each prefixed by its user.
+#[derive(Clone, Default)]
+pub struct SharedRenderers(Arc<Mutex<Store>>);
+
+/// The shared renderer of a [`SharedRenderers`], once a run created it.
+#[derive(Default)]
+struct Store {
+    /// The `Shared<N>` of the first run.
+    shared: Option<Box<dyn Any + Send>>,
+    /// The renderer chosen by the config of the first run.
+    renderer: Option<RendererArg>,
+}
+
+impl fmt::Debug for SharedRenderers {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedRenderers").finish_non_exhaustive()
+    }
+}
+
+impl SharedRenderers {
+    /// The run labelled `label`, the renderers it creates add their rows to
+    /// the shared renderer with `label` in front of the first column.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    pub fn run(&self, label: String) -> Run {
+        Run {
+            shared: self.clone(),
+            label,
+        }
+    }
+
+    /// The renderer chosen by the config of the first run that created one.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret)
+    )]
+    pub fn renderer(&self) -> Option<RendererArg> {
+        let store = self.0.lock().ok()?;
+        store.renderer
+    }
+}
+
+/// One run of a command for several users, given to the `execute` of the
+/// command so that its renderer is the shared one.
+#[derive(Clone, Debug)]
+pub struct Run {
+    shared: SharedRenderers,
+    label: String,
+}
+
+impl Run {
+    /// The shared renderer, prefixing the rows with the label of the run.
+    ///
+    /// The first renderer created in a run is kept for the next runs. A
+    /// command creating renderers with another number of columns gets its
+    /// own.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip(self, title, format, headers),
+            err(level = "info")
+        )
+    )]
+    pub(super) fn new_renderer<const N: usize>(
+        &self,
+        renderer: Option<RendererArg>,
+        title: &'static str,
+        format: &'static [&'static str; N],
+        headers: &'static [&'static str; N],
+    ) -> Result<Box<dyn Renderer<N> + Send>, RendererError> {
+        let Ok(mut store) = self.shared.0.lock() else {
+            bail!(RendererError::Poisoned);
+        };
+        let inner = if let Some(ref any) = store.shared {
+            if let Some(inner) = any.downcast_ref::<Shared<N>>() {
+                Arc::clone(inner)
+            } else {
+                return create_renderer(renderer, title, format, headers);
+            }
+        } else {
+            let inner: Shared<N> = Arc::new(Mutex::new(create_renderer(
+                renderer, title, format, headers,
+            )?));
+            store.shared = Some(Box::new(Arc::clone(&inner)));
+            store.renderer = renderer;
+            inner
+        };
+        Ok(Box::new(PrefixedRenderer {
+            label: self.label.clone(),
+            inner,
+        }))
+    }
+}
+
+/// Adds rows to a shared renderer, with a label in front of the first
+/// column.
+struct PrefixedRenderer<const N: usize> {
+    label: String,
+    inner: Shared<N>,
+}
+
+impl<const N: usize> RendererUsable for PrefixedRenderer<N> {}
+
+impl<const N: usize> Renderer<N> for PrefixedRenderer<N> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(title, format, headers), err(level = "info"))
+    )]
+    fn new(
+        title: &'static str,
+        format: &'static [&'static str; N],
+        headers: &'static [&'static str; N],
+    ) -> Result<Self, RendererError> {
+        Ok(Self {
+            label: String::new(),
+            inner: Arc::new(Mutex::new(create_renderer(None, title, format, headers)?)),
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, row), err(level = "info"))
+    )]
+    fn add_row(&mut self, row: &[&dyn Display; N]) -> Result<(), RendererError> {
+        let first = row
+            .first()
+            .map(|cell| format!("{}: {cell}", self.label))
+            .unwrap_or_default();
+        let mut row = *row;
+        if let Some(cell) = row.first_mut() {
+            *cell = &first;
+        }
+        let Ok(mut inner) = self.inner.lock() else {
+            bail!(RendererError::Poisoned);
+        };
+        inner.add_row(&row)
+    }
+
+    #[cfg(test)]
+    fn output(&mut self) -> String {
+        self.inner
+            .lock()
+            .map(|mut inner| inner.output())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+
+    use super::*;
+    use crate::libs::render::new_renderer;
+
+    #[test]
+    fn rows_are_prefixed_and_shared() {
+        let shared = SharedRenderers::default();
+        for user in ["alice", "bob"] {
+            let run = shared.run(user.to_owned());
+            let mut renderer = new_renderer(Some(&run), None, "T", &["", ""], &["Name", "Value"])
+                .expect("new renderer");
+            renderer
+                .add_row(&[&"INBOX" as &dyn Display, &1_u32])
+                .expect("add_row");
+        }
+        let output = new_renderer(Some(&shared.run(String::new())), None, "T", &["", ""], &[
+            "Name", "Value",
+        ])
+        .expect("new renderer")
+        .output();
+        assert_snapshot!(output, @"
+        Name,Value
+        alice: INBOX,1
+        bob: INBOX,1
+        ");
+    }
+}
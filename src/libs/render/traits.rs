@@ -11,6 +11,9 @@ pub enum RendererError {
         display: Box<dyn std::fmt::Debug + Send + Sync>,
     },
 
+    #[display("Renderer shared between users is poisoned")]
+    Poisoned,
+
     // CSV specific
     #[display("Writing CSV output")]
     Csv,
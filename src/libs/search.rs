@@ -0,0 +1,357 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use chrono::NaiveDate;
+use exn::{Exn, bail};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, derive_more::Display)]
+pub enum SearchError {
+    #[display("The search expression is empty")]
+    Empty,
+    #[display("Non-ASCII text is not supported in search expressions")]
+    NonAscii,
+    #[display("Unclosed quote in the search expression")]
+    UnclosedQuote,
+    #[display("Unbalanced parenthesis in the search expression")]
+    Parenthesis,
+    #[display("Unknown search key {key:?}")]
+    UnknownKey { key: String },
+    #[display("The search key {key} needs {expected}")]
+    MissingArgument { key: String, expected: &'static str },
+    #[display("Invalid argument {value:?} for the search key {key}, expected {expected}")]
+    InvalidArgument {
+        key: String,
+        expected: &'static str,
+        value: String,
+    },
+}
+impl std::error::Error for SearchError {}
+
+/// An IMAP SEARCH expression, like `UNSEEN FROM "news@example.com"`,
+/// checked when it is read so that the server does not reject it later.
+///
+/// The keys of RFC 3501 are known, with `OLDER` and `YOUNGER` of RFC 5032.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Search(String);
+
+/// A token of a search expression.
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Atom(String),
+    Quoted(String),
+}
+
+impl FromStr for Search {
+    type Err = Exn<SearchError>;
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", ret, err(level = "debug"))
+    )]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokens(s)?.into_iter().peekable();
+        if tokens.peek().is_none() {
+            bail!(SearchError::Empty);
+        }
+        while tokens.peek().is_some() {
+            key(&mut tokens)?;
+        }
+        Ok(Self(s.trim().to_owned()))
+    }
+}
+
+impl fmt::Display for Search {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Search {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(deserializer), ret, err(level = "debug"))
+    )]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Search {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, serializer), err(level = "debug"))
+    )]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl JsonSchema for Search {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+    fn schema_name() -> Cow<'static, str> {
+        "Search".into()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(_generator))
+    )]
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "An IMAP SEARCH expression, like `UNSEEN FROM \"news@example.com\"`.",
+            "type": "string",
+        })
+    }
+}
+
+/// The tokens of the search expression `s`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn tokens(s: &str) -> Result<Vec<Token>, Exn<SearchError>> {
+    if !s.is_ascii() {
+        bail!(SearchError::NonAscii);
+    }
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(first) = chars.next() {
+        match first {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => quoted.extend(chars.next()),
+                        Some(char) => quoted.push(char),
+                        None => bail!(SearchError::UnclosedQuote),
+                    }
+                }
+                tokens.push(Token::Quoted(quoted));
+            },
+            char if char.is_ascii_whitespace() => {},
+            char => {
+                let mut atom = String::from(char);
+                while let Some(char) = chars.next_if(|char| {
+                    !char.is_ascii_whitespace() && !matches!(*char, '(' | ')' | '"')
+                }) {
+                    atom.push(char);
+                }
+                tokens.push(Token::Atom(atom));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse one search key and its arguments from `tokens`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(tokens), err(level = "debug"))
+)]
+fn key<I>(tokens: &mut std::iter::Peekable<I>) -> Result<(), Exn<SearchError>>
+where
+    I: Iterator<Item = Token>,
+{
+    let name = match tokens.next() {
+        Some(Token::Open) => {
+            if tokens.peek() == Some(&Token::Close) {
+                bail!(SearchError::Empty);
+            }
+            loop {
+                match tokens.peek() {
+                    Some(&Token::Close) => {
+                        tokens.next();
+                        return Ok(());
+                    },
+                    Some(_) => key(tokens)?,
+                    None => bail!(SearchError::Parenthesis),
+                }
+            }
+        },
+        Some(Token::Close) => bail!(SearchError::Parenthesis),
+        Some(Token::Quoted(key)) => bail!(SearchError::UnknownKey { key }),
+        Some(Token::Atom(name)) => name,
+        None => bail!(SearchError::Empty),
+    };
+
+    let key = name.to_ascii_uppercase();
+    match key.as_str() {
+        "ALL" | "ANSWERED" | "DELETED" | "DRAFT" | "FLAGGED" | "NEW" | "OLD" | "RECENT"
+        | "SEEN" | "UNANSWERED" | "UNDELETED" | "UNDRAFT" | "UNFLAGGED" | "UNSEEN" => {},
+        "BCC" | "BODY" | "CC" | "FROM" | "SUBJECT" | "TEXT" | "TO" => {
+            string(tokens, &key)?;
+        },
+        "HEADER" => {
+            string(tokens, &key)?;
+            string(tokens, &key)?;
+        },
+        "KEYWORD" | "UNKEYWORD" => {
+            atom(tokens, &key, "a keyword", |keyword| {
+                !keyword.contains(['%', '*', ']', '\\', '{'])
+            })?;
+        },
+        "BEFORE" | "ON" | "SINCE" | "SENTBEFORE" | "SENTON" | "SENTSINCE" => {
+            atom(tokens, &key, "a date like 01-Jan-2024", |date| {
+                NaiveDate::parse_from_str(date, "%d-%b-%Y").is_ok()
+            })?;
+        },
+        "LARGER" | "SMALLER" | "OLDER" | "YOUNGER" => {
+            atom(tokens, &key, "a number", |number| {
+                number.parse::<u32>().is_ok()
+            })?;
+        },
+        "UID" => {
+            atom(tokens, &key, "a sequence set", is_sequence_set)?;
+        },
+        "NOT" => key_argument(tokens, &key)?,
+        "OR" => {
+            key_argument(tokens, &key)?;
+            key_argument(tokens, &key)?;
+        },
+        _ if is_sequence_set(&name) => {},
+        _ => bail!(SearchError::UnknownKey { key: name }),
+    }
+    Ok(())
+}
+
+/// Parse the search key argument of `key`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(tokens), err(level = "debug"))
+)]
+fn key_argument<I>(tokens: &mut std::iter::Peekable<I>, key: &str) -> Result<(), Exn<SearchError>>
+where
+    I: Iterator<Item = Token>,
+{
+    if matches!(tokens.peek(), None | Some(&Token::Close)) {
+        bail!(SearchError::MissingArgument {
+            key: key.to_owned(),
+            expected: "a search key",
+        });
+    }
+    self::key(tokens)
+}
+
+/// Parse the string argument of `key`, quoted or not.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(tokens), err(level = "debug"))
+)]
+fn string<I>(tokens: &mut std::iter::Peekable<I>, key: &str) -> Result<(), Exn<SearchError>>
+where
+    I: Iterator<Item = Token>,
+{
+    match tokens.next() {
+        Some(Token::Atom(_) | Token::Quoted(_)) => Ok(()),
+        _ => bail!(SearchError::MissingArgument {
+            key: key.to_owned(),
+            expected: "a string",
+        }),
+    }
+}
+
+/// Parse the atom argument of `key`, `expected` to be `valid`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(tokens, valid), err(level = "debug"))
+)]
+fn atom<I>(
+    tokens: &mut std::iter::Peekable<I>,
+    key: &str,
+    expected: &'static str,
+    valid: impl Fn(&str) -> bool,
+) -> Result<(), Exn<SearchError>>
+where
+    I: Iterator<Item = Token>,
+{
+    match tokens.next() {
+        Some(Token::Atom(value)) if valid(&value) => Ok(()),
+        Some(Token::Atom(value) | Token::Quoted(value)) => {
+            bail!(SearchError::InvalidArgument {
+                key: key.to_owned(),
+                expected,
+                value,
+            })
+        },
+        _ => bail!(SearchError::MissingArgument {
+            key: key.to_owned(),
+            expected,
+        }),
+    }
+}
+
+/// Whether `value` is a sequence set, like `1:5,7,10:*`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn is_sequence_set(value: &str) -> bool {
+    value.split(',').all(|range| {
+        let mut bounds = range.split(':');
+        bounds.clone().count() <= 2
+            && bounds.all(|bound| {
+                bound == "*"
+                    || (!bound.is_empty()
+                        && !bound.starts_with('0')
+                        && bound.bytes().all(|byte| byte.is_ascii_digit()))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_expressions() {
+        for valid in [
+            "SEEN UNFLAGGED",
+            "unseen from \"news@example.com\"",
+            "OR FLAGGED KEYWORD $Junk LARGER 100000",
+            "(HEADER List-Id \"<news.example.com>\" NOT (SEEN)) SINCE 1-Feb-2024",
+            "UID 1:5,7,10:* YOUNGER 3600",
+        ] {
+            assert!(Search::from_str(valid).is_ok(), "{valid:?} is valid");
+        }
+        for (invalid, message) in [
+            ("", "The search expression is empty"),
+            ("SEEN ()", "The search expression is empty"),
+            (
+                "FROM \"café\"",
+                "Non-ASCII text is not supported in search expressions",
+            ),
+            ("FROM \"news", "Unclosed quote in the search expression"),
+            ("(SEEN", "Unbalanced parenthesis in the search expression"),
+            ("SEEN)", "Unbalanced parenthesis in the search expression"),
+            ("UNREAD", "Unknown search key \"UNREAD\""),
+            ("OR SEEN", "The search key OR needs a search key"),
+            ("SUBJECT", "The search key SUBJECT needs a string"),
+            (
+                "BEFORE 2024-01-01",
+                "Invalid argument \"2024-01-01\" for the search key BEFORE, expected a date like \
+                 01-Jan-2024",
+            ),
+            (
+                "LARGER 10MB",
+                "Invalid argument \"10MB\" for the search key LARGER, expected a number",
+            ),
+        ] {
+            assert_eq!(
+                Search::from_str(invalid).map_err(|err| err.to_string()),
+                Err(message.to_owned()),
+                "{invalid:?} is invalid"
+            );
+        }
+    }
+}
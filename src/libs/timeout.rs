@@ -0,0 +1,224 @@
+use std::{
+    future::Future as _,
+    io,
+    pin::Pin,
+    sync::LazyLock,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep, sleep_until},
+};
+
+use crate::libs::base_config::BaseConfig;
+
+/// Default seconds to connect, set up TLS and log in.
+const DEFAULT_CONNECT_TIMEOUT: u64 = 30;
+
+/// Default seconds to wait for the server to send or accept anything while
+/// a command runs.
+const DEFAULT_COMMAND_TIMEOUT: u64 = 300;
+
+/// When the first connection was opened, `total-timeout` counts from there.
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Why the server was given up on.
+#[derive(Debug, Clone, Copy, derive_more::Display)]
+pub enum Elapsed {
+    #[display("The server did not answer within the command-timeout of {_0} seconds")]
+    Command(u64),
+    #[display("The total-timeout of {_0} seconds is over")]
+    Total(u64),
+}
+impl std::error::Error for Elapsed {}
+
+/// The timeouts of a configuration, `0` disables one.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    connect: Option<u64>,
+    command: Option<u64>,
+    total: Option<u64>,
+}
+
+impl Timeouts {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(base)))]
+    pub fn new(base: &BaseConfig) -> Self {
+        let total = base.total_timeout.filter(|&secs| secs > 0);
+        if total.is_some() {
+            LazyLock::force(&STARTED);
+        }
+        Self {
+            connect: Some(base.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT))
+                .filter(|&secs| secs > 0),
+            command: Some(base.command_timeout.unwrap_or(DEFAULT_COMMAND_TIMEOUT))
+                .filter(|&secs| secs > 0),
+            total,
+        }
+    }
+
+    /// The end of the run, if `total-timeout` is set.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn deadline(&self) -> Option<(Instant, Elapsed)> {
+        self.total
+            .map(|secs| (*STARTED + Duration::from_secs(secs), Elapsed::Total(secs)))
+    }
+
+    /// How long connecting may take, also bounded by the end of the run.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    pub fn connect(&self) -> Option<Instant> {
+        let connect = self
+            .connect
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+        match (connect, self.deadline()) {
+            (Some(connect), Some((deadline, _))) => Some(connect.min(deadline)),
+            (connect, deadline) => connect.or_else(|| deadline.map(|(deadline, _)| deadline)),
+        }
+    }
+
+    /// When waiting on the server must stop if it started now, and why.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn command(&self) -> Option<(Instant, Elapsed)> {
+        let command = self.command.map(|secs| {
+            (
+                Instant::now() + Duration::from_secs(secs),
+                Elapsed::Command(secs),
+            )
+        });
+        match (command, self.deadline()) {
+            (Some(command), Some(deadline)) => Some(
+                if deadline.0 < command.0 {
+                    deadline
+                } else {
+                    command
+                },
+            ),
+            (command, deadline) => command.or(deadline),
+        }
+    }
+}
+
+/// Stream wrapper failing with `TimedOut` when the server neither sends nor
+/// accepts anything for the command timeout, or when the run is over.
+///
+/// The clock only runs while async-imap waits on the server, so a session
+/// left alone between commands does not time out.
+#[derive(Debug)]
+pub struct TimeoutStream<S> {
+    inner: S,
+    timeouts: Timeouts,
+    timer: Option<(Pin<Box<Sleep>>, Elapsed)>,
+}
+
+impl<S> TimeoutStream<S> {
+    pub const fn new(inner: S, timeouts: Timeouts) -> Self {
+        Self {
+            inner,
+            timeouts,
+            timer: None,
+        }
+    }
+
+    /// Called when the inner stream is pending: start the clock if needed,
+    /// and fail once it ran out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, cx), err(level = "debug"))
+    )]
+    fn check(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        if self.timer.is_none() {
+            self.timer = self
+                .timeouts
+                .command()
+                .map(|(at, elapsed)| (Box::pin(sleep_until(at)), elapsed));
+        }
+        if let Some((ref mut sleep, elapsed)) = self.timer
+            && sleep.as_mut().poll(cx).is_ready()
+        {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, elapsed));
+        }
+        Ok(())
+    }
+
+    /// Turn a pending poll into an error once the clock ran out, and stop
+    /// the clock when the server made progress.
+    fn watch<R>(&mut self, cx: &mut Context<'_>, poll: Poll<io::Result<R>>) -> Poll<io::Result<R>> {
+        if poll.is_pending() {
+            if let Err(err) = self.check(cx) {
+                return Poll::Ready(Err(err));
+            }
+        } else {
+            self.timer = None;
+        }
+        poll
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TimeoutStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.watch(cx, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.watch(cx, poll)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_flush(cx);
+        self.watch(cx, poll)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_shutdown(cx);
+        self.watch(cx, poll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn read_times_out_when_the_server_is_silent() {
+        let mut base = BaseConfig::default();
+        base.command_timeout = Some(5);
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut stream = TimeoutStream::new(client, Timeouts::new(&base));
+
+        server.write_all(b"* OK").await.expect("write");
+        let mut buf = [0_u8; 4];
+        stream.read_exact(&mut buf).await.expect("data arrives");
+
+        let err = stream.read_u8().await.expect_err("silent server");
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_snapshot!(err.to_string(), @"The server did not answer within the command-timeout of 5 seconds");
+    }
+
+    #[test]
+    fn zero_disables() {
+        let mut base = BaseConfig::default();
+        base.connect_timeout = Some(0);
+        base.command_timeout = Some(0);
+        let timeouts = Timeouts::new(&base);
+        assert!(timeouts.connect().is_none(), "no connect timeout");
+        assert!(timeouts.command().is_none(), "no command timeout");
+    }
+}
@@ -0,0 +1,450 @@
+use std::{fmt::Write as _, path::PathBuf, sync::Once};
+
+use exn::{OptionExt as _, Result, ResultExt as _, bail};
+use sha2::{Digest as _, Sha256};
+#[cfg(feature = "rustls")]
+use tokio_rustls::rustls;
+
+#[derive(Debug, derive_more::Display)]
+pub enum TlsError {
+    #[display("Reading {file:?}")]
+    ReadFile { file: PathBuf },
+    #[display("No certificate found in {file:?}")]
+    NoCertificate { file: PathBuf },
+    #[display("No private key found in {file:?}")]
+    #[cfg(feature = "rustls")]
+    NoPrivateKey { file: PathBuf },
+    #[display("Invalid SHA-256 certificate pin {pin:?}, expects 64 hex digits")]
+    InvalidPin { pin: String },
+    #[display("The server did not send a certificate")]
+    NoPeerCertificate,
+    #[display(
+        "The certificate of {server} has the SHA-256 fingerprint {fingerprint}, which does not match the pinned one"
+    )]
+    PinMismatch { server: String, fingerprint: String },
+}
+impl std::error::Error for TlsError {}
+
+/// Parse a SHA-256 fingerprint, written as hex digits, optionally separated
+/// by colons like `openssl x509 -fingerprint -sha256` prints them.
+///
+/// # Errors
+/// Returns an error if this is not 32 bytes of hex.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", ret, err(level = "debug"))
+)]
+pub fn parse_pin(pin: &str) -> Result<[u8; 32], TlsError> {
+    let digits: Vec<u32> = pin
+        .chars()
+        .filter(|&c| c != ':')
+        .map(|c| c.to_digit(16))
+        .collect::<Option<_>>()
+        .ok_or_raise(|| TlsError::InvalidPin {
+            pin: pin.to_owned(),
+        })?;
+
+    let mut ret = [0_u8; 32];
+    if digits.len() != ret.len() * 2 {
+        bail!(TlsError::InvalidPin {
+            pin: pin.to_owned(),
+        });
+    }
+    for (byte, pair) in ret.iter_mut().zip(digits.chunks_exact(2)) {
+        if let [high, low] = *pair {
+            *byte = u8::try_from(high << 4 | low).or_raise(|| TlsError::InvalidPin {
+                pin: pin.to_owned(),
+            })?;
+        }
+    }
+    Ok(ret)
+}
+
+/// Check the DER encoded certificate of `server` against the pinned
+/// fingerprint.
+///
+/// # Errors
+/// Returns an error if the fingerprint does not match.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(der), err(level = "info"))
+)]
+pub fn check_pin(server: &str, der: &[u8], pin: &[u8; 32]) -> Result<(), TlsError> {
+    if Sha256::digest(der).as_slice() != pin {
+        bail!(TlsError::PinMismatch {
+            server: server.to_owned(),
+            fingerprint: fingerprint(der),
+        });
+    }
+    Ok(())
+}
+
+/// The SHA-256 fingerprint of a DER encoded certificate, as colon separated
+/// hex digits, the way `tls-pin-sha256` takes it.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(der), ret)
+)]
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .fold(String::new(), |mut output, b| {
+            if !output.is_empty() {
+                output.push(':');
+            }
+            let _ = write!(output, "{b:02X}");
+            output
+        })
+}
+
+/// A one line summary of a DER encoded certificate: its subject, issuer,
+/// expiry and fingerprint, the parts that cannot be read are left out.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(der), ret)
+)]
+pub fn describe_certificate(der: &[u8]) -> String {
+    let mut parts = vec![];
+    if let Some((subject, issuer, not_after)) = certificate_fields(der) {
+        parts.push(subject);
+        parts.push(format!("issued by {issuer}"));
+        parts.push(format!("valid until {not_after}"));
+    }
+    parts.push(format!("SHA-256 {}", fingerprint(der)));
+    parts.join(", ")
+}
+
+/// The subject, issuer and end of validity of a DER encoded certificate.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(der), ret)
+)]
+fn certificate_fields(der: &[u8]) -> Option<(String, String, String)> {
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version
+    // OPTIONAL, serialNumber, signature, issuer, validity, subject, ... } }
+    let (_, certificate, _) = der_element(der)?;
+    let (_, mut tbs, _) = der_element(certificate)?;
+    if tbs.first() == Some(&0xA0) {
+        tbs = der_element(tbs)?.2;
+    }
+    let (_, _serial, rest) = der_element(tbs)?;
+    let (_, _signature, rest) = der_element(rest)?;
+    let (_, issuer, rest) = der_element(rest)?;
+    let (_, validity, rest) = der_element(rest)?;
+    let (_, subject, _) = der_element(rest)?;
+    let (_, _not_before, validity) = der_element(validity)?;
+    let (tag, not_after, _) = der_element(validity)?;
+
+    Some((name(subject), name(issuer), time(tag, not_after)?))
+}
+
+/// The tag, content and rest of the DER element at the start of `input`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(input)))]
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        usize::from(first)
+    } else {
+        // Long form, the low bits tell how many bytes the length takes
+        let (bytes, rest) = input.split_at_checked(usize::from(first & 0x7F))?;
+        input = rest;
+        bytes.iter().try_fold(0_usize, |len, &b| {
+            len.checked_mul(256)?.checked_add(usize::from(b))
+        })?
+    };
+    let (content, rest) = input.split_at_checked(len)?;
+    Some((tag, content, rest))
+}
+
+/// An X.509 name, like `CN=imap.example.com, O=Example`, with only the
+/// common attributes.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(content), ret)
+)]
+fn name(mut content: &[u8]) -> String {
+    let mut attributes = vec![];
+    // SEQUENCE OF SET OF SEQUENCE { type OBJECT IDENTIFIER, value }
+    while let Some((_, set, rest)) = der_element(content) {
+        content = rest;
+        if let Some((_, pair, _)) = der_element(set)
+            && let Some((_, oid, pair)) = der_element(pair)
+            && let Some((_, value, _)) = der_element(pair)
+        {
+            let key = match *oid {
+                [0x55, 0x04, 0x03] => "CN",
+                [0x55, 0x04, 0x06] => "C",
+                [0x55, 0x04, 0x0A] => "O",
+                [0x55, 0x04, 0x0B] => "OU",
+                _ => continue,
+            };
+            attributes.push(format!("{key}={}", String::from_utf8_lossy(value)));
+        }
+    }
+    attributes.join(", ")
+}
+
+/// An X.509 `UTCTime` (tag 0x17) or `GeneralizedTime` as `YYYY-MM-DD`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(content), ret)
+)]
+fn time(tag: u8, content: &[u8]) -> Option<String> {
+    let digits = std::str::from_utf8(content).ok()?;
+    let (year, rest) = if tag == 0x17 {
+        let (year, rest) = digits.split_at_checked(2)?;
+        let year: u32 = year.parse().ok()?;
+        // RFC 5280: two digit years from 50 are in the 20th century
+        (if year >= 50 { 1900 } else { 2000 } + year, rest)
+    } else {
+        let (year, rest) = digits.split_at_checked(4)?;
+        (year.parse().ok()?, rest)
+    };
+    let (month, rest) = rest.split_at_checked(2)?;
+    let day = rest.get(..2)?;
+    Some(format!("{year:04}-{month}-{day}"))
+}
+
+/// Warn, once per process and even without tracing, that certificates are
+/// not checked.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip()))]
+pub fn warn_insecure() {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("insecure-skip-verify is set, TLS certificates are NOT verified");
+
+        #[expect(clippy::print_stderr, reason = "must be seen even without logging")]
+        {
+            eprintln!(
+                "WARNING: insecure-skip-verify is set, the server certificate is NOT verified and the connection can be intercepted"
+            );
+        }
+    });
+}
+
+/// Read a PEM file, for the backends that parse PEM themselves.
+///
+/// # Errors
+/// Returns an error if the file cannot be read.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", err(level = "info"))
+)]
+#[cfg(feature = "native-tls")]
+pub fn read_pem(file: &PathBuf) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(file).or_raise(|| TlsError::ReadFile { file: file.clone() })
+}
+
+/// Split a PEM bundle into its certificates.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(pem)))]
+#[cfg(feature = "native-tls")]
+pub fn pem_certificates(pem: &[u8]) -> Vec<Vec<u8>> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    String::from_utf8_lossy(pem)
+        .split_inclusive(END)
+        .filter_map(|block| {
+            block
+                .find("-----BEGIN CERTIFICATE-----")
+                .and_then(|start| block.get(start..))
+                .filter(|cert| cert.ends_with(END))
+                .map(|cert| cert.as_bytes().to_vec())
+        })
+        .collect()
+}
+
+/// Load all the certificates of a PEM file.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or has no certificate.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", err(level = "info"))
+)]
+#[cfg(feature = "rustls")]
+pub fn load_certificates(
+    file: &PathBuf,
+) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>, TlsError> {
+    use rustls::pki_types::{CertificateDer, pem::PemObject as _};
+
+    let certs = CertificateDer::pem_file_iter(file)
+        .or_raise(|| TlsError::ReadFile { file: file.clone() })?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .or_raise(|| TlsError::ReadFile { file: file.clone() })?;
+    if certs.is_empty() {
+        bail!(TlsError::NoCertificate { file: file.clone() });
+    }
+    Ok(certs)
+}
+
+/// Load the private key of a PEM file.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or has no key.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", err(level = "info"))
+)]
+#[cfg(feature = "rustls")]
+pub fn load_private_key(
+    file: &PathBuf,
+) -> Result<rustls::pki_types::PrivateKeyDer<'static>, TlsError> {
+    use rustls::pki_types::{PrivateKeyDer, pem::PemObject as _};
+
+    PrivateKeyDer::from_pem_file(file).or_raise(|| TlsError::NoPrivateKey { file: file.clone() })
+}
+
+/// Certificate verifier that accepts any certificate, used when the
+/// certificate is pinned, and checked after the handshake, or when
+/// `insecure-skip-verify` is set. Handshake signatures are still checked.
+#[cfg(feature = "rustls")]
+#[derive(Debug)]
+pub struct AcceptAnyCertificate(pub std::sync::Arc<rustls::crypto::CryptoProvider>);
+
+#[cfg(feature = "rustls")]
+impl rustls::client::danger::ServerCertVerifier for AcceptAnyCertificate {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err(level = "debug"))
+    )]
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> std::result::Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err(level = "debug"))
+    )]
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> std::result::Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err(level = "debug"))
+    )]
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> std::result::Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    const PIN: &str = "A1:B2:C3:D4:E5:F6:07:18:29:3A:4B:5C:6D:7E:8F:90:01:12:23:34:45:56:67:78:89:9A:AB:BC:CD:DE:EF:F0";
+
+    #[test]
+    fn parse_pin_with_colons_or_not() {
+        let with = parse_pin(PIN).expect("pin with colons");
+        let without = parse_pin(&PIN.replace(':', "").to_lowercase()).expect("pin without colons");
+        assert_eq!(with, without);
+        assert_eq!(with.first(), Some(&0xA1));
+        assert_eq!(with.last(), Some(&0xF0));
+    }
+
+    #[test]
+    fn parse_pin_invalid() {
+        assert!(parse_pin("A1:B2").is_err(), "too short");
+        assert!(parse_pin(&PIN.replace('A', "G")).is_err(), "not hex");
+    }
+
+    #[test]
+    fn check_pin_matches() {
+        let pin = Sha256::digest(b"certificate");
+        check_pin("imap.example.com", b"certificate", &pin.into()).expect("pin matches");
+    }
+
+    #[test]
+    fn check_pin_mismatch() {
+        let pin = parse_pin(PIN).expect("pin");
+        let err = check_pin("imap.example.com", b"certificate", &pin).expect_err("mismatch");
+        assert_snapshot!(err.to_string(), @"The certificate of imap.example.com has the SHA-256 fingerprint 03:D6:6D:D0:88:35:C1:CA:3F:12:8C:CE:AC:D1:F3:1A:C9:41:63:09:6B:20:F4:45:AE:84:28:5B:C0:83:2D:72, which does not match the pinned one");
+    }
+
+    /// A DER element with a short length.
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut der = vec![tag, u8::try_from(content.len()).expect("short element")];
+        der.extend_from_slice(content);
+        der
+    }
+
+    /// A name with a single attribute.
+    fn name_der(oid: u8, value: &str) -> Vec<u8> {
+        tlv(
+            0x30,
+            &tlv(
+                0x31,
+                &tlv(
+                    0x30,
+                    &[tlv(0x06, &[0x55, 0x04, oid]), tlv(0x0C, value.as_bytes())].concat(),
+                ),
+            ),
+        )
+    }
+
+    #[test]
+    fn describe_certificate_fields() {
+        let tbs = [
+            tlv(0xA0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            tlv(0x30, &[]),
+            name_der(0x0A, "Example CA"),
+            tlv(
+                0x30,
+                &[tlv(0x17, b"250101000000Z"), tlv(0x18, b"20260315120000Z")].concat(),
+            ),
+            name_der(0x03, "imap.example.com"),
+        ]
+        .concat();
+        let der = tlv(0x30, &tlv(0x30, &tbs));
+        assert_snapshot!(describe_certificate(&der), @"CN=imap.example.com, issued by O=Example CA, valid until 2026-03-15, SHA-256 F1:DD:19:C4:E5:AA:95:2E:FF:F5:74:08:82:1E:E3:FC:BF:44:30:EC:4B:29:65:53:22:A2:73:1E:8C:AB:F2:2D");
+        assert_snapshot!(describe_certificate(b"junk"), @"SHA-256 EF:87:5A:17:05:A5:FD:AC:20:6B:E9:96:F4:DC:1F:72:6E:A6:B6:88:61:EB:74:1C:37:DE:F7:27:7F:17:9E:37");
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn pem_certificates_splits_bundle() {
+        let pem = b"junk\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let certs = pem_certificates(pem);
+        assert_eq!(certs.len(), 2);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use exn::{Result, ResultExt as _};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::libs::base_config::BaseConfig;
+
+/// How many bytes of a literal are shown.
+const LITERAL_PREVIEW: usize = 64;
+
+/// Numbers the connections, to tell them apart when several sessions write
+/// to the same transcript.
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug, derive_more::Display)]
+pub enum TranscriptError {
+    #[display("Opening debug file {file:?}")]
+    Open { file: PathBuf },
+}
+impl std::error::Error for TranscriptError {}
+
+/// Where the transcript goes, if `debug` or `debug-file` is set.
+///
+/// # Errors
+/// Returns an error if the debug file cannot be opened.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(base), err(level = "info"))
+)]
+pub fn sink(base: &BaseConfig) -> Result<Option<Box<dyn Write + Send>>, TranscriptError> {
+    if let Some(ref file) = base.debug_file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .or_raise(|| TranscriptError::Open { file: file.clone() })?;
+        Ok(Some(Box::new(file)))
+    } else if base.debug {
+        Ok(Some(Box::new(io::stderr())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Stream wrapper writing every line the client and the server send to
+/// `sink`, with a timestamp. The LOGIN and AUTHENTICATE payloads are
+/// redacted and literals are truncated.
+pub struct TranscriptStream<S> {
+    inner: S,
+    transcript: Transcript,
+}
+
+impl<S> fmt::Debug for TranscriptStream<S> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TranscriptStream")
+            .field("id", &self.transcript.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> TranscriptStream<S> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(inner, sink))
+    )]
+    pub fn new(inner: S, sink: Box<dyn Write + Send>) -> Self {
+        Self {
+            inner,
+            transcript: Transcript {
+                id: CONNECTIONS.fetch_add(1, Ordering::Relaxed),
+                sink,
+                client: Direction::default(),
+                server: Direction::default(),
+                secret: false,
+            },
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TranscriptStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if matches!(poll, Poll::Ready(Ok(())))
+            && let Some(read) = buf.filled().get(before..)
+        {
+            self.transcript.feed(Side::Server, read);
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TranscriptStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll
+            && let Some(written) = buf.get(..written)
+        {
+            self.transcript.feed(Side::Client, written);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Client,
+    Server,
+}
+
+/// A literal being received, `{size}` bytes announced at the end of a line.
+#[derive(Debug, Default)]
+struct Literal {
+    size: usize,
+    remaining: usize,
+    preview: Vec<u8>,
+}
+
+/// What one side sent and was not written out yet.
+#[derive(Debug, Default)]
+struct Direction {
+    line: Vec<u8>,
+    literal: Option<Literal>,
+}
+
+struct Transcript {
+    id: usize,
+    sink: Box<dyn Write + Send>,
+    client: Direction,
+    server: Direction,
+    /// Whether the client is sending credentials, from LOGIN or
+    /// AUTHENTICATE until the server answers with anything but a
+    /// continuation request.
+    secret: bool,
+}
+
+impl Transcript {
+    /// Split what `side` sent into lines and literals, and write them out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, bytes))
+    )]
+    fn feed(&mut self, side: Side, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let direction = match side {
+                Side::Client => &mut self.client,
+                Side::Server => &mut self.server,
+            };
+
+            if let Some(ref mut literal) = direction.literal {
+                let (data, rest) = bytes.split_at(literal.remaining.min(bytes.len()));
+                bytes = rest;
+                literal.remaining -= data.len();
+                let room = LITERAL_PREVIEW.saturating_sub(literal.preview.len());
+                literal
+                    .preview
+                    .extend_from_slice(data.get(..room.min(data.len())).unwrap_or_default());
+                if literal.remaining == 0
+                    && let Some(literal) = direction.literal.take()
+                {
+                    let text = if side == Side::Client && self.secret {
+                        format!("<redacted literal of {} bytes>", literal.size)
+                    } else {
+                        format!(
+                            "<literal of {} bytes> {:?}{}",
+                            literal.size,
+                            String::from_utf8_lossy(&literal.preview),
+                            if literal.size > LITERAL_PREVIEW {
+                                "..."
+                            } else {
+                                ""
+                            }
+                        )
+                    };
+                    self.write(side, &text);
+                }
+                continue;
+            }
+
+            let Some(end) = bytes.iter().position(|&b| b == b'\n') else {
+                direction.line.extend_from_slice(bytes);
+                break;
+            };
+            let (line, rest) = bytes.split_at(end + 1);
+            bytes = rest;
+            direction.line.extend_from_slice(line);
+            let line = String::from_utf8_lossy(&direction.line)
+                .trim_end_matches(['\r', '\n'])
+                .to_owned();
+            direction.line.clear();
+            direction.literal = literal_size(&line)
+                .filter(|&size| size > 0)
+                .map(|size| Literal {
+                    size,
+                    remaining: size,
+                    preview: vec![],
+                });
+
+            let text = self.redact(side, &line);
+            self.write(side, &text);
+        }
+    }
+
+    /// Hide the credentials from a line, and keep track of when they are
+    /// being sent.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, line))
+    )]
+    fn redact(&mut self, side: Side, line: &str) -> String {
+        if side == Side::Server {
+            if !line.starts_with('+') {
+                self.secret = false;
+            }
+            return line.to_owned();
+        }
+
+        let mut words = line.splitn(4, ' ');
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some(tag), Some(command), _, _) if command.eq_ignore_ascii_case("LOGIN") => {
+                self.secret = true;
+                format!("{tag} {command} <redacted>")
+            },
+            (Some(tag), Some(command), mechanism, initial)
+                if command.eq_ignore_ascii_case("AUTHENTICATE") =>
+            {
+                self.secret = true;
+                let mut text = format!("{tag} {command}");
+                if let Some(mechanism) = mechanism {
+                    text.push(' ');
+                    text.push_str(mechanism);
+                }
+                if initial.is_some() {
+                    text.push_str(" <redacted>");
+                }
+                text
+            },
+            _ if self.secret => "<redacted>".to_owned(),
+            _ => line.to_owned(),
+        }
+    }
+
+    /// Write one line out, errors are ignored as the transcript is only a
+    /// debugging help.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, text))
+    )]
+    fn write(&mut self, side: Side, text: &str) {
+        let arrow = match side {
+            Side::Client => "C:",
+            Side::Server => "S:",
+        };
+        let _ = writeln!(
+            self.sink,
+            "{} [{}] {arrow} {text}",
+            chrono::Local::now().format("%H:%M:%S%.3f"),
+            self.id
+        );
+    }
+}
+
+/// The size of the literal announced at the end of `line`, as `{N}` or
+/// `{N+}`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", ret))]
+fn literal_size(line: &str) -> Option<usize> {
+    let (_, size) = line.strip_suffix('}')?.rsplit_once('{')?;
+    size.strip_suffix('+').unwrap_or(size).parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use std::sync::{Arc, Mutex};
+
+    use insta::assert_snapshot;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use super::*;
+
+    /// A sink the test can read back.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .lock()
+                .map_err(|_err| io::Error::other("poisoned"))?
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn literal_size_parsing() {
+        assert_eq!(literal_size("* 1 FETCH (BODY[] {42}"), Some(42));
+        assert_eq!(literal_size("A1 APPEND INBOX {7+}"), Some(7));
+        assert_eq!(literal_size("* OK {not a literal}"), None);
+        assert_eq!(literal_size("* OK done"), None);
+    }
+
+    #[tokio::test]
+    async fn transcript_redacts_and_truncates() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let shared = Shared::default();
+        let mut stream = TranscriptStream::new(client, Box::new(shared.clone()));
+
+        stream
+            .write_all(b"A1 LOGIN alice \"s3cret\"\r\n")
+            .await
+            .expect("write");
+        stream
+            .write_all(b"A2 AUTHENTICATE PLAIN\r\n")
+            .await
+            .expect("write");
+        server.write_all(b"+ ready\r\n").await.expect("write");
+        let mut buf = [0_u8; 9];
+        stream.read_exact(&mut buf).await.expect("read");
+        stream
+            .write_all(b"AGFsaWNlAHMzY3JldA==\r\n")
+            .await
+            .expect("write");
+        server
+            .write_all(
+                format!(
+                    "A2 OK done\r\n* 1 FETCH (BODY[] {{100}}\r\n{})\r\n",
+                    "x".repeat(100)
+                )
+                .as_bytes(),
+            )
+            .await
+            .expect("write");
+        let mut buf = vec![0_u8; 140];
+        stream.read_exact(&mut buf).await.expect("read");
+        stream.write_all(b"A3 NOOP\r\n").await.expect("write");
+
+        let output = String::from_utf8(shared.0.lock().expect("lock").clone()).expect("utf-8");
+        let output = output
+            .lines()
+            .map(|line| line.split_once(' ').map_or(line, |(_time, rest)| rest))
+            .map(|line| line.split_once(' ').map_or(line, |(_id, rest)| rest))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_snapshot!(output, @r#"
+        C: A1 LOGIN <redacted>
+        C: A2 AUTHENTICATE PLAIN
+        S: + ready
+        C: <redacted>
+        S: A2 OK done
+        S: * 1 FETCH (BODY[] {100}
+        S: <literal of 100 bytes> "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"...
+        S: )
+        C: A3 NOOP
+        "#);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    io,
+    pin::Pin,
+    process::Stdio,
+    task::{Context, Poll},
+};
+
+use exn::{OptionExt as _, Result, ResultExt as _};
+use shell_words::split;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    process::{Child, ChildStdin, ChildStdout, Command},
+};
+
+#[derive(Debug, derive_more::Display)]
+pub enum TunnelError {
+    #[display("Parsing tunnel command {command}")]
+    Parsing { command: String },
+    #[display("Tunnel command is empty")]
+    Empty,
+    #[display("Starting tunnel command {command:?}")]
+    Spawn { command: String },
+    #[display("Tunnel command {command:?} has no {stream}")]
+    NoPipe {
+        command: String,
+        stream: &'static str,
+    },
+}
+impl std::error::Error for TunnelError {}
+
+/// A process speaking IMAP on its standard input and output, like
+/// `ssh host /usr/lib/dovecot/imap`.
+///
+/// Its standard error is left alone so that `ssh` can still ask questions
+/// and report problems. The process is killed when the stream is dropped.
+#[derive(Debug)]
+pub struct TunnelStream {
+    /// Kept so the process is killed with the stream.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl TunnelStream {
+    /// Start `command`, split like a shell would, without running a shell.
+    ///
+    /// # Errors
+    /// Returns an error if the command cannot be parsed or started.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", ret, err(level = "info"))
+    )]
+    pub fn spawn(command: &str) -> Result<Self, TunnelError> {
+        let command_vec = split(command).or_raise(|| TunnelError::Parsing {
+            command: command.to_owned(),
+        })?;
+        let (exe, args) = command_vec
+            .split_first()
+            .ok_or_raise(|| TunnelError::Empty)?;
+
+        let mut child = Command::new(exe)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .or_raise(|| TunnelError::Spawn {
+                command: command.to_owned(),
+            })?;
+
+        let stdin = child.stdin.take().ok_or_raise(|| TunnelError::NoPipe {
+            command: command.to_owned(),
+            stream: "stdin",
+        })?;
+        let stdout = child.stdout.take().ok_or_raise(|| TunnelError::NoPipe {
+            command: command.to_owned(),
+            stream: "stdout",
+        })?;
+
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl AsyncRead for TunnelStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdout).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TunnelStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stdin).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdin).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdin).poll_shutdown(cx)
+    }
+}
+
+/// Stream wrapper for servers greeting with `PREAUTH`.
+///
+/// async-imap only hands out an authenticated session after a successful
+/// LOGIN or AUTHENTICATE, so the first command written, that LOGIN, is not
+/// sent to the server but answered here with a tagged OK.
+#[derive(Debug)]
+pub struct PreAuthStream<S> {
+    inner: S,
+    state: PreAuthState,
+}
+
+#[derive(Debug)]
+enum PreAuthState {
+    /// Collecting the first command, up to its CRLF.
+    Intercepting(Vec<u8>),
+    /// Handing out the fake answer.
+    Replying(Vec<u8>),
+    /// Everything goes to the server.
+    Passthrough,
+}
+
+impl<S> PreAuthStream<S> {
+    pub const fn new(inner: S) -> Self {
+        Self {
+            inner,
+            state: PreAuthState::Intercepting(Vec::new()),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PreAuthStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let PreAuthState::Replying(ref mut reply) = self.state {
+            let len = reply.len().min(buf.remaining());
+            buf.put_slice(&reply.drain(..len).collect::<Vec<_>>());
+            if reply.is_empty() {
+                self.state = PreAuthState::Passthrough;
+            }
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PreAuthStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if let PreAuthState::Intercepting(ref mut line) = self.state {
+            let collected = line.len();
+            line.extend_from_slice(buf);
+            // Only the first command is taken, what follows goes to the server
+            if let Some(end) = line.windows(2).position(|w| w == b"\r\n").map(|at| at + 2) {
+                line.truncate(end);
+                let tag = line
+                    .split(|&b| b == b' ')
+                    .next()
+                    .unwrap_or_default()
+                    .to_vec();
+                let mut reply = tag;
+                reply.extend_from_slice(b" OK Already authenticated (PREAUTH)\r\n");
+                self.state = PreAuthState::Replying(reply);
+                return Poll::Ready(Ok(end - collected));
+            }
+            return Poll::Ready(Ok(buf.len()));
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use insta::assert_snapshot;
+    use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tunnel_talks_to_the_process() {
+        let mut stream = TunnelStream::spawn("cat").expect("spawn cat");
+        stream.write_all(b"A1 NOOP\r\n").await.expect("write");
+        stream.flush().await.expect("flush");
+        let mut line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut line)
+            .await
+            .expect("read");
+        assert_eq!(line, "A1 NOOP\r\n");
+    }
+
+    #[test]
+    fn tunnel_empty_command() {
+        let err = TunnelStream::spawn("").expect_err("empty command");
+        assert_snapshot!(err.to_string(), @"Tunnel command is empty");
+    }
+
+    #[tokio::test]
+    async fn preauth_answers_the_first_command_only() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut stream = BufReader::new(PreAuthStream::new(client));
+        stream
+            .write_all(b"A0001 LOGIN x y\r\n")
+            .await
+            .expect("write login");
+        let mut line = String::new();
+        stream.read_line(&mut line).await.expect("read reply");
+        assert_eq!(line, "A0001 OK Already authenticated (PREAUTH)\r\n");
+
+        stream
+            .write_all(b"A0002 NOOP\r\n")
+            .await
+            .expect("write noop");
+        stream.flush().await.expect("flush");
+        let mut server = BufReader::new(server);
+        line.clear();
+        server.read_line(&mut line).await.expect("server read");
+        assert_eq!(line, "A0002 NOOP\r\n");
+    }
+
+    #[tokio::test]
+    async fn preauth_forwards_what_follows_the_first_command() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut stream = BufReader::new(PreAuthStream::new(client));
+        stream
+            .write_all(b"A0001 LOGIN x y\r\nA0002 NOOP\r\n")
+            .await
+            .expect("write both");
+        stream.flush().await.expect("flush");
+        let mut line = String::new();
+        stream.read_line(&mut line).await.expect("read reply");
+        assert_eq!(line, "A0001 OK Already authenticated (PREAUTH)\r\n");
+
+        let mut server = BufReader::new(server);
+        line.clear();
+        server.read_line(&mut line).await.expect("server read");
+        assert_eq!(line, "A0002 NOOP\r\n");
+    }
+}
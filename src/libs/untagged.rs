@@ -0,0 +1,222 @@
+use std::{
+    fmt, io, mem,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+};
+
+use async_imap::imap_proto::parser::parse_response;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// How many bytes are read from the server at a time.
+const READ_SIZE: usize = 8192;
+
+/// The untagged lines imap-proto has no parser for, like NAMESPACE, as the
+/// server sent them, shared between a [`SetAsideStream`] and its session.
+#[derive(Clone, Debug, Default)]
+pub struct SetAside(Arc<Mutex<Vec<String>>>);
+
+impl SetAside {
+    /// Take the lines set aside so far.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), ret)
+    )]
+    pub fn take(&self) -> Vec<String> {
+        mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn push(&self, line: String) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(line);
+    }
+}
+
+/// Stream wrapper keeping the untagged lines imap-proto cannot parse away
+/// from async-imap, which would otherwise fail and give up on the
+/// connection, and putting them in a [`SetAside`] instead.
+///
+/// Everything else, literals included, goes through unchanged, a whole line
+/// at a time.
+pub struct SetAsideStream<S> {
+    inner: S,
+    set_aside: SetAside,
+    /// The start of a line, until its end arrives.
+    line: Vec<u8>,
+    /// How many bytes of a literal are still to go through.
+    literal: usize,
+    /// What is ready for async-imap.
+    out: Vec<u8>,
+}
+
+impl<S> fmt::Debug for SetAsideStream<S> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, f))
+    )]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetAsideStream")
+            .field("set_aside", &self.set_aside)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> SetAsideStream<S> {
+    pub const fn new(inner: S, set_aside: SetAside) -> Self {
+        Self {
+            inner,
+            set_aside,
+            line: vec![],
+            literal: 0,
+            out: vec![],
+        }
+    }
+
+    /// Sort what the server sent into what goes to async-imap and what is
+    /// set aside.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.literal > 0 {
+                let len = self.literal.min(data.len());
+                let (literal, rest) = data.split_at(len);
+                self.out.extend_from_slice(literal);
+                self.literal -= len;
+                data = rest;
+                continue;
+            }
+            let Some(end) = data.iter().position(|&b| b == b'\n') else {
+                self.line.extend_from_slice(data);
+                return;
+            };
+            let (line, rest) = data.split_at(end + 1);
+            self.line.extend_from_slice(line);
+            data = rest;
+
+            let line = mem::take(&mut self.line);
+            if unparsable(&line) {
+                self.set_aside
+                    .push(String::from_utf8_lossy(&line).trim_end().to_owned());
+            } else {
+                self.literal = literal_len(&line);
+                self.out.extend_from_slice(&line);
+            }
+        }
+    }
+}
+
+/// Whether `line` is a whole untagged response imap-proto has no parser for.
+/// The first line of a response with literals is incomplete, not unparsable.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(line), ret)
+)]
+fn unparsable(line: &[u8]) -> bool {
+    line.starts_with(b"* ") && parse_response(line).is_err_and(|err| !err.is_incomplete())
+}
+
+/// The size of the literal announced at the end of `line`, like `{12}` or
+/// `{12+}`, or 0.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(line), ret)
+)]
+fn literal_len(line: &[u8]) -> usize {
+    line.trim_ascii_end()
+        .strip_suffix(b"}")
+        .and_then(|line| {
+            let start = line.iter().rposition(|&b| b == b'{')?;
+            let len = line.get(start + 1..)?;
+            std::str::from_utf8(len.strip_suffix(b"+").unwrap_or(len))
+                .ok()?
+                .parse()
+                .ok()
+        })
+        .unwrap_or(0)
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SetAsideStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.out.is_empty() {
+                let len = this.out.len().min(buf.remaining());
+                let rest = this.out.split_off(len);
+                buf.put_slice(&mem::replace(&mut this.out, rest));
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut data = [0_u8; READ_SIZE];
+            let mut read = ReadBuf::new(&mut data);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut read) {
+                Poll::Ready(Ok(())) if read.filled().is_empty() => {
+                    // The end of the stream: let async-imap see what is left.
+                    this.out.append(&mut this.line);
+                    if this.out.is_empty() {
+                        return Poll::Ready(Ok(()));
+                    }
+                },
+                Poll::Ready(Ok(())) => this.feed(read.filled()),
+                poll => return poll,
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SetAsideStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sets_aside_unparsable_lines_only() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let set_aside = SetAside::default();
+        let mut stream = SetAsideStream::new(client, set_aside.clone());
+
+        server
+            .write_all(
+                b"* 2 EXISTS\r\n* NAMESPACE ((\"\" \"/\")) NIL NIL\r\n\
+                  * 1 FETCH (BODY[] {13}\r\n* NAMESPACE\r\n)\r\nA1 OK done\r\n",
+            )
+            .await
+            .expect("write");
+        drop(server);
+        let mut read = String::new();
+        stream.read_to_string(&mut read).await.expect("read");
+
+        assert_eq!(
+            read,
+            "* 2 EXISTS\r\n* 1 FETCH (BODY[] {13}\r\n* NAMESPACE\r\n)\r\nA1 OK done\r\n"
+        );
+        assert_eq!(set_aside.take(), [r#"* NAMESPACE (("" "/")) NIL NIL"#]);
+    }
+}
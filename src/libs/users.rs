@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use exn::{Result, ResultExt as _, bail};
+use serde::Deserialize;
+
+use crate::libs::base_config::SerdeAnyWrapper;
+
+#[derive(Debug, derive_more::Display)]
+pub enum UsersError {
+    #[display("Reading users CSV file {file:?}")]
+    Csv { file: PathBuf },
+    #[display("Parsing users file {file:?}")]
+    Parse { file: PathBuf },
+    #[display("Users file {file:?} lists no user")]
+    Empty { file: PathBuf },
+}
+impl std::error::Error for UsersError {}
+
+/// The users of a TOML, YAML or JSON users file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsersFile {
+    users: Vec<String>,
+}
+
+/// Read the users to run a command for.
+///
+/// A `.csv` file has the user in its first column, with an optional `user`
+/// or `username` header. Any other file is read like the configuration file
+/// and holds a `users` list.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or parsed, or lists no user.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", ret, err(level = "info"))
+)]
+pub fn read(file: &Path) -> Result<Vec<String>, UsersError> {
+    let users = if file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+    {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(file)
+            .or_raise(|| UsersError::Csv {
+                file: file.to_owned(),
+            })?;
+        let mut users = vec![];
+        for record in reader.records() {
+            let record = record.or_raise(|| UsersError::Csv {
+                file: file.to_owned(),
+            })?;
+            if let Some(user) = record.get(0) {
+                users.push(user.to_owned());
+            }
+        }
+        if users
+            .first()
+            .is_some_and(|first| ["user", "username"].contains(&first.to_lowercase().as_str()))
+        {
+            users.remove(0);
+        }
+        users
+    } else {
+        serde_any::from_file::<UsersFile, _>(file)
+            .map_err(SerdeAnyWrapper)
+            .or_raise(|| UsersError::Parse {
+                file: file.to_owned(),
+            })?
+            .users
+    };
+
+    let users: Vec<String> = users.into_iter().filter(|user| !user.is_empty()).collect();
+    if users.is_empty() {
+        bail!(UsersError::Empty {
+            file: file.to_owned()
+        });
+    }
+    Ok(users)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::expect_used, reason = "tests")]
+
+    use std::{fs::File, io::Write as _};
+
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn csv_with_header() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let file = dir.path().join("users.csv");
+        File::create(&file)
+            .expect("create")
+            .write_all(b"User,Comment\nalice,first\n\n bob ,second\n")
+            .expect("write");
+        assert_debug_snapshot!(read(&file).expect("read"), @r#"
+        [
+            "alice",
+            "bob",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn toml_list() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let file = dir.path().join("users.toml");
+        File::create(&file)
+            .expect("create")
+            .write_all(b"users = [\"alice\", \"bob\"]\n")
+            .expect("write");
+        assert_debug_snapshot!(read(&file).expect("read"), @r#"
+        [
+            "alice",
+            "bob",
+        ]
+        "#);
+    }
+}
@@ -8,9 +8,9 @@ use std::collections::VecDeque;
 
 use regex::Regex;
 use tokio::{
-    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
     net::{TcpListener, TcpStream},
-    task::JoinHandle,
+    task::{JoinHandle, JoinSet},
 };
 
 /// An expected IMAP command, either literal or regex-matched.
@@ -74,6 +74,10 @@ pub struct MockExchange {
     /// If set, the mock server asserts the client sent this exact command
     /// (everything after the IMAP tag, trimmed).
     pub command: ExpectCommand,
+    /// Drop the connection instead of answering, to simulate a lost session.
+    pub disconnect: bool,
+    /// Never answer, to simulate a hanging server.
+    pub silent: bool,
 }
 
 impl MockExchange {
@@ -88,6 +92,8 @@ impl MockExchange {
             untagged,
             tagged: "OK completed".to_owned(),
             command: command.into(),
+            disconnect: false,
+            silent: false,
         }
     }
 
@@ -102,13 +108,48 @@ impl MockExchange {
             untagged: vec![],
             tagged: format!("NO {}", reason.into()),
             command: command.into(),
+            disconnect: false,
+            silent: false,
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(command), ret)
+    )]
+    /// Lost connection: the server closes the socket when it gets this command.
+    #[track_caller]
+    pub fn disconnect(command: impl Into<ExpectCommand>) -> Self {
+        Self {
+            untagged: vec![],
+            tagged: String::new(),
+            command: command.into(),
+            disconnect: true,
+            silent: false,
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(command), ret)
+    )]
+    /// Hanging server: this command is never answered, the connection stays
+    /// open until the client drops it.
+    #[track_caller]
+    pub fn silent(command: impl Into<ExpectCommand>) -> Self {
+        Self {
+            untagged: vec![],
+            tagged: String::new(),
+            command: command.into(),
+            disconnect: false,
+            silent: true,
         }
     }
 }
 
-/// A single-connection mock IMAP TCP server for tests.
+/// A mock IMAP TCP server for tests.
 ///
-/// Handles `CAPABILITY`, `LOGIN`, and `LOGOUT` automatically.
+/// Handles `CAPABILITY`, `LOGIN`, `NOOP`, and `LOGOUT` automatically.
 /// All other commands are answered from the provided script in order.
 #[derive(Debug)]
 pub struct MockServer {
@@ -128,13 +169,59 @@ impl MockServer {
     /// `extra_caps`: additional capabilities beyond `IMAP4rev1 UIDPLUS` (e.g. `&["MOVE"]`).
     /// `script`: one `MockExchange` per non-handshake IMAP command.
     pub async fn start(extra_caps: &'static [&'static str], script: Vec<MockExchange>) -> Self {
+        Self::start_sessions(extra_caps, vec![script]).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(extra_caps, script), ret)
+    )]
+    /// Start a server without `UIDPLUS`, like some legacy ones.
+    pub async fn start_without_uidplus(
+        extra_caps: &'static [&'static str],
+        script: Vec<MockExchange>,
+    ) -> Self {
+        Self::spawn(extra_caps, false, vec![script]).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(extra_caps, scripts), ret)
+    )]
+    /// Start the server on a random local port, accepting one connection per
+    /// script in turn and serving them concurrently, e.g. to test reconnecting
+    /// after a lost session or a pool of sessions.
+    pub async fn start_sessions(
+        extra_caps: &'static [&'static str],
+        scripts: Vec<Vec<MockExchange>>,
+    ) -> Self {
+        Self::spawn(extra_caps, true, scripts).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(extra_caps, scripts), ret)
+    )]
+    /// Start the server, advertising `UIDPLUS` or not.
+    async fn spawn(
+        extra_caps: &'static [&'static str],
+        uidplus: bool,
+        scripts: Vec<Vec<MockExchange>>,
+    ) -> Self {
         let listener = TcpListener::bind("127.0.0.1:0")
             .await
             .expect("bind to local port");
         let port = listener.local_addr().expect("get local port").port();
         let handle = tokio::spawn(async move {
-            let (stream, _) = listener.accept().await.expect("accept connection");
-            run_session(stream, extra_caps, script).await;
+            let mut sessions = JoinSet::new();
+            for script in scripts {
+                let (stream, _) = listener.accept().await.expect("accept connection");
+                sessions.spawn(run_session(stream, extra_caps, uidplus, script));
+            }
+            drop(listener);
+            while let Some(session) = sessions.join_next().await {
+                session.expect("mock session panicked");
+            }
         });
         Self { port, handle }
     }
@@ -150,7 +237,12 @@ impl MockServer {
     feature = "tracing",
     tracing::instrument(level = "trace", skip(stream, extra_caps, script))
 )]
-async fn run_session(stream: TcpStream, extra_caps: &[&str], script: Vec<MockExchange>) {
+async fn run_session(
+    stream: TcpStream,
+    extra_caps: &[&str],
+    uidplus: bool,
+    script: Vec<MockExchange>,
+) {
     let mut script: VecDeque<MockExchange> = script.into();
     let (read_half, mut writer) = stream.into_split();
     let mut reader = BufReader::new(read_half);
@@ -175,11 +267,11 @@ async fn run_session(stream: TcpStream, extra_caps: &[&str], script: Vec<MockExc
 
         match cmd.as_str() {
             "CAPABILITY" => {
-                let caps = if extra_caps.is_empty() {
-                    "IMAP4rev1 UIDPLUS".to_owned()
-                } else {
-                    format!("IMAP4rev1 UIDPLUS {}", extra_caps.join(" "))
-                };
+                let caps = std::iter::once("IMAP4rev1")
+                    .chain(uidplus.then_some("UIDPLUS"))
+                    .chain(extra_caps.iter().copied())
+                    .collect::<Vec<_>>()
+                    .join(" ");
                 writer
                     .write_all(
                         format!("* CAPABILITY {caps}\r\n{tag} OK CAPABILITY completed\r\n")
@@ -188,6 +280,12 @@ async fn run_session(stream: TcpStream, extra_caps: &[&str], script: Vec<MockExc
                     .await
                     .expect("write capability");
             },
+            "NOOP" => {
+                writer
+                    .write_all(format!("{tag} OK NOOP completed\r\n").as_bytes())
+                    .await
+                    .expect("write noop");
+            },
             "LOGIN" => {
                 writer
                     .write_all(format!("{tag} OK LOGIN completed\r\n").as_bytes())
@@ -224,6 +322,14 @@ async fn run_session(stream: TcpStream, extra_caps: &[&str], script: Vec<MockExc
                     },
                 }
                 exchange_index += 1;
+                if exchange.disconnect {
+                    break;
+                }
+                if exchange.silent {
+                    let mut rest = vec![];
+                    let _ = reader.read_to_end(&mut rest).await;
+                    break;
+                }
                 for resp in &exchange.untagged {
                     writer
                         .write_all(resp.as_bytes())